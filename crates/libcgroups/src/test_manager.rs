@@ -10,14 +10,18 @@ use crate::{
 #[derive(Debug)]
 pub struct TestManager {
     add_task_args: RefCell<Vec<Pid>>,
+    freeze_args: RefCell<Vec<FreezerState>>,
     pub apply_called: RefCell<bool>,
+    pub remove_called: RefCell<bool>,
 }
 
 impl Default for TestManager {
     fn default() -> Self {
         Self {
             add_task_args: RefCell::new(vec![]),
+            freeze_args: RefCell::new(vec![]),
             apply_called: RefCell::new(false),
+            remove_called: RefCell::new(false),
         }
     }
 }
@@ -37,11 +41,13 @@ impl CgroupManager for TestManager {
     }
 
     fn remove(&self) -> Result<(), Infallible> {
-        unimplemented!()
+        *self.remove_called.borrow_mut() = true;
+        Ok(())
     }
 
-    fn freeze(&self, _state: FreezerState) -> Result<(), Infallible> {
-        unimplemented!()
+    fn freeze(&self, state: FreezerState) -> Result<(), Infallible> {
+        self.freeze_args.borrow_mut().push(state);
+        Ok(())
     }
 
     fn stats(&self) -> Result<Stats, Infallible> {
@@ -49,7 +55,7 @@ impl CgroupManager for TestManager {
     }
 
     fn get_all_pids(&self) -> Result<Vec<Pid>, Infallible> {
-        unimplemented!()
+        Ok(self.get_add_task_args())
     }
 }
 
@@ -58,7 +64,15 @@ impl TestManager {
         self.add_task_args.borrow_mut().clone()
     }
 
+    pub fn get_freeze_args(&self) -> Vec<FreezerState> {
+        self.freeze_args.borrow_mut().clone()
+    }
+
     pub fn apply_called(&self) -> bool {
         *self.apply_called.borrow_mut()
     }
+
+    pub fn remove_called(&self) -> bool {
+        *self.remove_called.borrow_mut()
+    }
 }
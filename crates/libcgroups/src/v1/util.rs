@@ -27,6 +27,15 @@ pub fn list_subsystem_mount_points() -> Result<Vec<PathBuf>, V1MountPointError>
         .collect())
 }
 
+/// Determine the directory the cgroup v1 hierarchies are mounted under, which
+/// is the parent of the first hierarchy mounted at a path with a parent.
+pub fn cgroup_root_of(mount_points: &[PathBuf]) -> Option<PathBuf> {
+    mount_points
+        .iter()
+        .find_map(|mount_point| mount_point.parent())
+        .map(|root| root.to_path_buf())
+}
+
 /// List the mount points of all currently supported cgroup subsystems.
 pub fn list_supported_mount_points() -> Result<HashMap<ControllerType, PathBuf>, V1MountPointError>
 {
@@ -1,8 +1,13 @@
+use crate::container::ContainerEvent;
+use crate::process::scheduler::SchedulerSettings;
+use crate::rootfs::RootfsValidator;
 use crate::workload::default::DefaultExecutor;
 use crate::workload::{Executor, ExecutorManager};
 use crate::{syscall::Syscall, utils::PathBufExt};
 use anyhow::{anyhow, bail, Context, Result};
 use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
 
 use super::{init_builder::InitContainerBuilder, tenant_builder::TenantContainerBuilder};
 
@@ -16,6 +21,8 @@ pub struct ContainerBuilder<'a> {
     /// File which will be used to communicate the pid of the
     /// container process to the higher level runtime
     pub(super) pid_file: Option<PathBuf>,
+    /// Write the pid file as JSON object with the creation time
+    pub(super) pid_file_json: bool,
     /// Socket to communicate the file descriptor of the ptty
     pub(super) console_socket: Option<PathBuf>,
     /// File descriptors to be passed into the container process
@@ -23,6 +30,28 @@ pub struct ContainerBuilder<'a> {
     /// Manage the functions that actually run on the container
     /// Default executes the specified execution of a generic command
     pub(super) executor_manager: ExecutorManager,
+    /// Checks the root filesystem of an init container before the container
+    /// process pivots into it
+    pub(super) rootfs_validator: Option<Box<dyn RootfsValidator>>,
+    /// Receives the progress of the container creation
+    pub(super) event_sender: Option<Sender<ContainerEvent>>,
+    /// File the events of the creation are appended to as JSON lines
+    pub(super) event_log: Option<PathBuf>,
+    /// Unix socket the seccomp notify fd is sent to, takes precedence over
+    /// the listener path of the spec
+    pub(super) seccomp_notify_path: Option<PathBuf>,
+    /// Maximum time the container processes may take to be set up
+    pub(super) create_timeout: Option<Duration>,
+    /// Environment added to the environment of the createRuntime hooks
+    pub(super) extra_hook_env: Vec<(String, String)>,
+    /// Enter the rootfs with MS_MOVE and chroot instead of pivot_root
+    pub(super) no_pivot: bool,
+    /// File opened by the runtime and passed to the container process
+    pub(super) log_file: Option<PathBuf>,
+    /// Chown the notify socket to the host ids of the container user
+    pub(super) chown_notify_socket: bool,
+    /// Scheduling the container process is set to
+    pub(super) scheduler: Option<SchedulerSettings>,
 }
 
 /// Builder that can be used to configure the common properties of
@@ -68,11 +97,22 @@ impl<'a> ContainerBuilder<'a> {
             root_path,
             syscall,
             pid_file: None,
+            pid_file_json: false,
             console_socket: None,
             preserve_fds: 0,
             executor_manager: ExecutorManager {
                 executors: vec![Box::<DefaultExecutor>::default()],
             },
+            rootfs_validator: None,
+            event_sender: None,
+            event_log: None,
+            seccomp_notify_path: None,
+            create_timeout: None,
+            extra_hook_env: Vec::new(),
+            no_pivot: false,
+            log_file: None,
+            chown_notify_socket: false,
+            scheduler: None,
         }
     }
 
@@ -205,6 +245,25 @@ impl<'a> ContainerBuilder<'a> {
         Ok(self)
     }
 
+    /// Sets if the pid file is written as JSON object holding the pid and the
+    /// creation time in RFC 3339 format, instead of only the pid
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_pid_file_json(true);
+    /// ```
+    pub fn with_pid_file_json(mut self, json: bool) -> Self {
+        self.pid_file_json = json;
+        self
+    }
+
     /// Sets the console socket, which will be used to send the file descriptor
     /// of the pseudoterminal
     /// # Example
@@ -266,6 +325,231 @@ impl<'a> ContainerBuilder<'a> {
         self.executor_manager = ExecutorManager { executors };
         Ok(self)
     }
+
+    /// Sets a validator which checks the root filesystem of an init container
+    /// after all mounts are set up and before the container process pivots
+    /// into it. An error returned by the validator aborts the creation.
+    /// Tenant containers join the rootfs of the running container, which is
+    /// not validated again, so the validator is ignored for them.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::rootfs::RootfsValidator;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    /// # use oci_spec::runtime::Spec;
+    /// # use std::path::Path;
+    ///
+    /// struct HasShell;
+    ///
+    /// impl RootfsValidator for HasShell {
+    ///     fn validate(&self, rootfs: &Path, _spec: &Spec) -> anyhow::Result<()> {
+    ///         anyhow::ensure!(rootfs.join("bin/sh").exists(), "no shell in rootfs");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_rootfs_validator(Box::new(HasShell));
+    /// ```
+    pub fn with_rootfs_validator(mut self, validator: Box<dyn RootfsValidator>) -> Self {
+        self.rootfs_validator = Some(validator);
+        self
+    }
+
+    /// Sets a sender which receives an event at each step of the container
+    /// creation, e.g. to track its progress or measure the duration of the
+    /// phases.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    /// # use std::sync::mpsc;
+    ///
+    /// let (sender, _receiver) = mpsc::channel();
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_event_sender(sender);
+    /// ```
+    pub fn with_event_sender(mut self, sender: Sender<ContainerEvent>) -> Self {
+        self.event_sender = Some(sender);
+        self
+    }
+
+    /// Sets a file the events of the container creation and its failure are
+    /// appended to as newline-delimited JSON. Unlike the events of
+    /// `with_event_sender`, the file is kept for debugging after a crash.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_event_log(Some("/var/log/youki/events.ndjson"));
+    /// ```
+    pub fn with_event_log<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
+        self.event_log = path.map(|p| p.into());
+        self
+    }
+
+    /// Sets the Unix socket the seccomp notify fd of a container using
+    /// SCMP_ACT_NOTIFY is sent to. It takes precedence over the listener path
+    /// of the seccomp configuration in the spec.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_seccomp_notify_path("/run/seccomp-agent.sock");
+    /// ```
+    pub fn with_seccomp_notify_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.seccomp_notify_path = Some(path.into());
+        self
+    }
+
+    /// Sets the maximum time the container processes may take to be set up.
+    /// When it is exceeded, the processes are killed and the creation fails.
+    /// There is no limit by default.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    /// # use std::time::Duration;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_create_timeout(Duration::from_secs(30));
+    /// ```
+    pub fn with_create_timeout(mut self, timeout: Duration) -> Self {
+        self.create_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets environment variables added to the environment of the
+    /// createRuntime hooks, e.g. to pass a trace id. Variables set by the env
+    /// of a hook in the spec take precedence.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_extra_hook_env(vec![("TRACE_ID".to_owned(), "1234".to_owned())]);
+    /// ```
+    pub fn with_extra_hook_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.extra_hook_env = env;
+        self
+    }
+
+    /// Sets if the container process should enter the rootfs by moving it
+    /// onto / and chrooting instead of using pivot_root, for environments
+    /// where pivot_root is not available. This is less secure, as the old
+    /// root is only hidden by the chroot.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_no_pivot(true);
+    /// ```
+    pub fn with_no_pivot(mut self, no_pivot: bool) -> Self {
+        self.no_pivot = no_pivot;
+        self
+    }
+
+    /// Sets a file the runtime opens for appending and passes to the
+    /// container process, e.g. to collect its logs. The file descriptor
+    /// follows the preserved file descriptors and its number is set in the
+    /// YOUKI_LOG_FD environment variable of the container process.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_log_file(Some("/var/log/containers/74f1a4cb3801.log"));
+    /// ```
+    pub fn with_log_file<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
+        self.log_file = path.map(|p| p.into());
+        self
+    }
+
+    /// Chowns the notify socket to the host uid and gid the user of the
+    /// container process is mapped to. The socket is owned by the user
+    /// running the runtime otherwise, which an unprivileged container user
+    /// of a rootless container can't connect to.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_chown_notify_socket(true);
+    /// ```
+    pub fn with_chown_notify_socket(mut self, chown: bool) -> Self {
+        self.chown_notify_socket = chown;
+        self
+    }
+
+    /// Sets the scheduling policy, priority and nice value the container
+    /// process is set to once it is created, before it is started. Real-time
+    /// policies require CAP_SYS_NICE or a matching RLIMIT_RTPRIO.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::process::scheduler::{SchedPolicy, SchedulerSettings};
+    /// # use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .with_scheduler(Some(SchedulerSettings {
+    ///     policy: SchedPolicy::Fifo,
+    ///     priority: 50,
+    ///     nice: None,
+    /// }));
+    /// ```
+    pub fn with_scheduler(mut self, scheduler: Option<SchedulerSettings>) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -343,4 +627,30 @@ mod tests {
         assert!(result.is_ok());
         Ok(())
     }
+
+    #[test]
+    fn test_with_rootfs_validator() -> Result<()> {
+        use crate::rootfs::RootfsValidator;
+        use oci_spec::runtime::Spec;
+        use std::path::Path;
+
+        struct RejectAll;
+
+        impl RootfsValidator for RejectAll {
+            fn validate(&self, _rootfs: &Path, _spec: &Spec) -> Result<()> {
+                anyhow::bail!("rejected")
+            }
+        }
+
+        let syscall = create_syscall();
+        let builder = ContainerBuilder::new("74f1a4cb3801".to_owned(), syscall.as_ref());
+        assert!(builder.rootfs_validator.is_none());
+
+        let builder = builder.with_rootfs_validator(Box::new(RejectAll));
+        let validator = builder.rootfs_validator.context("validator is not set")?;
+        assert!(validator
+            .validate(Path::new("/"), &Spec::default())
+            .is_err());
+        Ok(())
+    }
 }
@@ -1,22 +1,46 @@
-use super::{Container, ContainerStatus};
+use super::{
+    container_restore,
+    event::{self, record_event, ContainerEvent},
+    CgroupManagerKind, Container, ContainerStatus, RestoreOptions,
+};
 use crate::{
     hooks,
-    notify_socket::NotifyListener,
+    notify_socket::{NotifyListener, NotifyListenerError},
     process::{
         self,
         args::{ContainerArgs, ContainerType},
+        container_main_process::{ContainerPids, MainProcessTimestamps},
         intel_rdt::delete_resctrl_subdirectory,
+        scheduler::{self, SchedulerSettings},
     },
-    rootless::Rootless,
+    rootfs::RootfsValidator,
+    rootless::{self, Rootless},
     syscall::Syscall,
     utils,
     workload::ExecutorManager,
 };
-use anyhow::{bail, Context, Result};
-use libcgroups::common::CgroupManager;
-use nix::unistd::Pid;
-use oci_spec::runtime::Spec;
-use std::{fs, io::Write, os::unix::prelude::RawFd, path::PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use libcgroups::common::{AnyCgroupManager, CgroupManager};
+use nix::{
+    fcntl,
+    unistd::{Gid, Pid, Uid},
+};
+use oci_spec::runtime::{LinuxIdMapping, LinuxNamespace, Spec, User};
+use std::{
+    fs, io,
+    ops::Range,
+    os::unix::{
+        fs::FileTypeExt,
+        net::UnixStream,
+        prelude::{AsRawFd, FromRawFd, RawFd},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    time::{Duration, Instant},
+};
 
 pub(super) struct ContainerBuilderImpl<'a> {
     /// Flag indicating if an init or a tenant container should be created
@@ -25,6 +49,8 @@ pub(super) struct ContainerBuilderImpl<'a> {
     pub syscall: &'a dyn Syscall,
     /// Flag indicating if systemd should be used for cgroup management
     pub use_systemd: bool,
+    /// Cgroup manager to use, Auto decides based on use_systemd and rootless
+    pub cgroup_manager_kind: CgroupManagerKind,
     /// Id of the container
     pub container_id: String,
     /// OCI complient runtime spec
@@ -34,6 +60,8 @@ pub(super) struct ContainerBuilderImpl<'a> {
     /// File which will be used to communicate the pid of the
     /// container process to the higher level runtime
     pub pid_file: Option<PathBuf>,
+    /// Write the pid file as JSON with the creation time instead of the bare pid
+    pub pid_file_json: bool,
     /// Socket to communicate the file descriptor of the ptty
     pub console_socket: Option<RawFd>,
     /// Options for rootless containers
@@ -42,23 +70,152 @@ pub(super) struct ContainerBuilderImpl<'a> {
     pub notify_path: PathBuf,
     /// Container state
     pub container: Option<Container>,
+    /// State of the running container a tenant joins
+    pub joined_container: Option<Container>,
     /// File descriptos preserved/passed to the container init process.
     pub preserve_fds: i32,
     /// If the container is to be run in detached mode
     pub detached: bool,
     /// Default executes the specified execution of a generic command
     pub executor_manager: ExecutorManager,
+    /// Checks the root filesystem of an init container before pivoting into it
+    pub rootfs_validator: Option<Box<dyn RootfsValidator>>,
+    /// Receives the progress of the container creation
+    pub event_sender: Option<Sender<ContainerEvent>>,
+    /// File the events of the creation are appended to as JSON lines
+    pub event_log: Option<PathBuf>,
+    /// Unix socket the seccomp notify fd is sent to
+    pub seccomp_notify_path: Option<PathBuf>,
+    /// Maximum time the container processes may take to be set up
+    pub create_timeout: Option<Duration>,
+    /// Environment added to the environment of the createRuntime hooks
+    pub extra_hook_env: Vec<(String, String)>,
+    /// Enter the rootfs with MS_MOVE and chroot instead of pivot_root
+    pub no_pivot: bool,
+    /// Checkpoint a restored container is created from
+    pub restore_options: Option<RestoreOptions>,
+    /// File passed to the container process after the preserved fds
+    pub log_file: Option<PathBuf>,
+    /// Chown the notify socket to the host ids of the container user
+    pub chown_notify_socket: bool,
+    /// Scheduling the container process is set to
+    pub scheduler: Option<SchedulerSettings>,
+}
+
+/// Result of the creation of a container, with the time its phases took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreateReport {
+    /// Pid of the init process of the container
+    pub pid: Pid,
+    /// Time the init process took to set up the rootfs and the mounts
+    pub mount_duration: Duration,
+    /// Time until the init process was forked into the namespaces
+    pub namespace_duration: Duration,
+    /// Time the whole creation took
+    pub total: Duration,
+}
+
+impl CreateReport {
+    /// Computes the durations between the start of the creation, the points
+    /// the main process reached and the end. A restored container doesn't go
+    /// through those phases, so they are zero for it.
+    fn new(
+        pid: Pid,
+        start: Instant,
+        timestamps: Option<MainProcessTimestamps>,
+        end: Instant,
+    ) -> Self {
+        let (namespace_duration, mount_duration) = match timestamps {
+            Some(timestamps) => (
+                timestamps
+                    .intermediate_ready
+                    .saturating_duration_since(start),
+                timestamps
+                    .init_ready
+                    .saturating_duration_since(timestamps.intermediate_ready),
+            ),
+            None => (Duration::ZERO, Duration::ZERO),
+        };
+
+        Self {
+            pid,
+            mount_duration,
+            namespace_duration,
+            total: end.saturating_duration_since(start),
+        }
+    }
 }
 
 impl<'a> ContainerBuilderImpl<'a> {
     pub(super) fn create(&mut self) -> Result<Pid> {
+        self.create_with_report().map(|report| report.pid)
+    }
+
+    /// Creates the container like [`create`](Self::create), but also reports
+    /// how long the phases of the creation took.
+    pub(super) fn create_with_report(&mut self) -> Result<CreateReport> {
+        let res = self.try_create();
+        // the checks before run_container fail the creation too
+        if let Err(err) = &res {
+            event::append_event_log(
+                self.event_log.as_deref(),
+                event::FAILED_PHASE,
+                None,
+                Some(&format!("{err:#}")),
+            );
+        }
+        res
+    }
+
+    fn try_create(&mut self) -> Result<CreateReport> {
+        let start = Instant::now();
+
+        // Catch a wrong rootfs before anything is set up for the container,
+        // instead of failing later on the first mount into it.
+        validate_rootfs(&self.rootfs)?;
+
+        // A populated cgroup belongs to another container. This has to be
+        // checked before run_container, as cleaning up after a failure would
+        // remove that cgroup.
+        // A tenant joins the cgroup of the running container instead, which
+        // must not be created anew if it is gone.
+        match self.container_type {
+            ContainerType::InitContainer | ContainerType::RestoredContainer => {
+                ensure_cgroup_unused(&self.cgroup_manager()?, &self.container_id)?
+            }
+            ContainerType::TenantContainer { .. } => {
+                ensure_cgroup_exists(&self.cgroup_manager()?, &self.container_id)?
+            }
+        }
+
         match self.run_container().context("failed to create container") {
-            Ok(pid) => Ok(pid),
+            Ok((pid, timestamps)) => {
+                let report = CreateReport::new(pid, start, timestamps, Instant::now());
+                log::debug!(
+                    "container {} created in {:?}, namespaces took {:?}, mounts took {:?}",
+                    self.container_id,
+                    report.total,
+                    report.namespace_duration,
+                    report.mount_duration
+                );
+                Ok(report)
+            }
             Err(outer) => {
-                // Only the init container should be cleaned up in the case of
-                // an error.
-                if matches!(self.container_type, ContainerType::InitContainer) {
-                    if let Err(inner) = self.cleanup_container() {
+                // Only the init and restored containers should be cleaned up
+                // in the case of an error.
+                if matches!(
+                    self.container_type,
+                    ContainerType::InitContainer | ContainerType::RestoredContainer
+                ) {
+                    // The CgroupGuard in run_container already removed the
+                    // cgroup of an init container, removing a systemd unit a
+                    // second time fails.
+                    let remove_cgroup =
+                        matches!(self.container_type, ContainerType::RestoredContainer);
+                    // the socket of a busy notify path belongs to the process
+                    // listening on it
+                    let remove_socket = outer.downcast_ref::<NotifySocketInUse>().is_none();
+                    if let Err(inner) = self.cleanup_container(remove_cgroup, remove_socket) {
                         return Err(outer.context(inner));
                     }
                 }
@@ -68,23 +225,73 @@ impl<'a> ContainerBuilderImpl<'a> {
         }
     }
 
-    fn run_container(&mut self) -> Result<Pid> {
+    /// Path of the container cgroup and whether systemd manages it, as
+    /// configured by the spec. A tenant joins the cgroup of the running
+    /// container instead, which is looked up from its state.
+    fn cgroup_config(&self) -> Result<(PathBuf, bool)> {
+        if let Some(joined) = &self.joined_container {
+            return joined
+                .cgroup_config()
+                .context("failed to look up the cgroup of the container");
+        }
+
         let linux = self.spec.linux().as_ref().context("no linux in spec")?;
         let cgroups_path = utils::get_cgroup_path(
             linux.cgroups_path(),
             &self.container_id,
             self.rootless.is_some(),
         );
-        let cmanager = libcgroups::common::create_cgroup_manager(
+        Ok((
             cgroups_path,
-            self.use_systemd || self.rootless.is_some(),
+            self.cgroup_manager_kind
+                .use_systemd(self.use_systemd, self.rootless.is_some()),
+        ))
+    }
+
+    /// Creates the manager of the container cgroup.
+    fn cgroup_manager(&self) -> Result<AnyCgroupManager> {
+        let (cgroups_path, use_systemd) = self.cgroup_config()?;
+        Ok(libcgroups::common::create_cgroup_manager(
+            cgroups_path,
+            use_systemd,
             &self.container_id,
-        )?;
+        )?)
+    }
+
+    fn run_container(&mut self) -> Result<(Pid, Option<MainProcessTimestamps>)> {
+        if matches!(self.container_type, ContainerType::RestoredContainer) {
+            return Ok((self.restore_container()?, None));
+        }
+
+        let linux = self.spec.linux().as_ref().context("no linux in spec")?;
+        let cmanager = self.cgroup_manager()?;
         let process = self.spec.process().as_ref().context("No process in spec")?;
 
+        // The cgroup of an init container is created for it and must not be
+        // left behind if anything below fails.
+        let cleanup_manager = match self.container_type {
+            ContainerType::InitContainer | ContainerType::RestoredContainer => {
+                Some(self.cgroup_manager()?)
+            }
+            ContainerType::TenantContainer { .. } => None,
+        };
+        let cgroup_guard = cleanup_manager.as_ref().map(CgroupGuard::new);
+
+        // the preserved fds follow stdio, make sure they are all open before
+        // the container process inherits them.
+        validate_preserved_fds(3..3 + self.preserve_fds)?;
+
+        // kept open until the container processes inherited it
+        let (log_file, preserve_fds) = pass_log_file(self.log_file.as_deref(), self.preserve_fds)?;
+        let log_fd = log_file.as_ref().map(|file| file.as_raw_fd());
+
         if matches!(self.container_type, ContainerType::InitContainer) {
             if let Some(hooks) = self.spec.hooks() {
-                hooks::run_hooks(hooks.create_runtime().as_ref(), self.container.as_ref())?
+                hooks::run_hooks_with_env(
+                    hooks.create_runtime().as_ref(),
+                    self.container.as_ref(),
+                    &self.extra_hook_env,
+                )?
             }
         }
 
@@ -92,7 +299,15 @@ impl<'a> ContainerBuilderImpl<'a> {
         // domain socket used here is outside of the rootfs of container. During
         // exec, need to create the socket before we enter into existing mount
         // namespace.
-        let notify_socket: NotifyListener = NotifyListener::new(&self.notify_path)?;
+        let notify_socket = create_notify_listener(&self.notify_path)?;
+        if self.chown_notify_socket {
+            chown_notify_socket(
+                self.syscall,
+                &self.notify_path,
+                process.user(),
+                self.rootless.as_ref(),
+            )?;
+        }
 
         // If Out-of-memory score adjustment is set in specification.  set the score
         // value for the current process check
@@ -104,9 +319,9 @@ impl<'a> ContainerBuilderImpl<'a> {
         // set). All children inherit their parent's oom_score_adj value on
         // fork(2) so this will always be propagated properly.
         if let Some(oom_score_adj) = process.oom_score_adj() {
-            log::debug!("Set OOM score to {}", oom_score_adj);
-            let mut f = fs::File::create("/proc/self/oom_score_adj")?;
-            f.write_all(oom_score_adj.to_string().as_bytes())?;
+            set_oom_score_adj(oom_score_adj, self.rootless.is_some(), |value| {
+                fs::write("/proc/self/oom_score_adj", value)
+            })?;
         }
 
         // Make the process non-dumpable, to avoid various race conditions that
@@ -117,9 +332,7 @@ impl<'a> ContainerBuilderImpl<'a> {
         // going to be switching to a different security context. Thus setting
         // ourselves to be non-dumpable only breaks things (like rootless
         // containers), which is the recommendation from the kernel folks.
-        if linux.namespaces().is_some() {
-            prctl::set_dumpable(false).unwrap();
-        }
+        set_non_dumpable(linux.namespaces().as_deref(), prctl::set_dumpable);
 
         // This container_args will be passed to the container processes,
         // therefore we will have to move all the variable by value. Since self
@@ -131,20 +344,46 @@ impl<'a> ContainerBuilderImpl<'a> {
             rootfs: &self.rootfs,
             console_socket: self.console_socket,
             notify_socket,
-            preserve_fds: self.preserve_fds,
+            preserve_fds,
             container: &self.container,
             rootless: &self.rootless,
             cgroup_manager: cmanager,
             detached: self.detached,
             executor_manager: &self.executor_manager,
+            rootfs_validator: self.rootfs_validator.as_deref(),
+            seccomp_notify_path: self.seccomp_notify_path.as_deref(),
+            no_pivot: self.no_pivot,
+            log_fd,
         };
 
-        let (init_pid, need_to_clean_up_intel_rdt_dir) =
-            process::container_main_process::container_main_process(&container_args)?;
+        record_event(
+            self.event_sender.as_ref(),
+            self.event_log.as_deref(),
+            ContainerEvent::SettingUpNamespaces,
+        );
+        // Only the processes forked for this container are killed on a
+        // timeout, the runtime may be embedded in a daemon with other children.
+        let pids = ContainerPids::default();
+        let (init_pid, need_to_clean_up_intel_rdt_dir, timestamps) = run_with_deadline(
+            self.create_timeout,
+            || process::container_main_process::container_main_process(&container_args, &pids),
+            || pids.kill(),
+        )?;
+        record_event(
+            self.event_sender.as_ref(),
+            self.event_log.as_deref(),
+            ContainerEvent::InitProcessCreated { pid: init_pid },
+        );
+
+        // The process only waits to be started at this point, the scheduling
+        // is inherited by the container workload it execs.
+        if let Some(settings) = &self.scheduler {
+            scheduler::apply_scheduler(self.syscall, init_pid, settings)?;
+        }
 
         // if file to write the pid to is specified, write pid of the child
         if let Some(pid_file) = &self.pid_file {
-            fs::write(pid_file, format!("{init_pid}")).context("failed to write pid file")?;
+            write_pid_file(pid_file, init_pid, self.pid_file_json)?;
         }
 
         if let Some(container) = &mut self.container {
@@ -156,28 +395,70 @@ impl<'a> ContainerBuilderImpl<'a> {
                 .set_clean_up_intel_rdt_directory(need_to_clean_up_intel_rdt_dir)
                 .save()
                 .context("Failed to save container state")?;
+            record_event(
+                self.event_sender.as_ref(),
+                self.event_log.as_deref(),
+                ContainerEvent::StateSaved { pid: init_pid },
+            );
         }
 
-        Ok(init_pid)
+        if let Some(cgroup_guard) = cgroup_guard {
+            cgroup_guard.commit();
+        }
+
+        Ok((init_pid, Some(timestamps)))
     }
 
-    fn cleanup_container(&self) -> Result<()> {
-        let linux = self.spec.linux().as_ref().context("no linux in spec")?;
-        let cgroups_path = utils::get_cgroup_path(
-            linux.cgroups_path(),
-            &self.container_id,
-            self.rootless.is_some(),
-        );
-        let cmanager = libcgroups::common::create_cgroup_manager(
-            cgroups_path,
-            self.use_systemd || self.rootless.is_some(),
-            &self.container_id,
-        )?;
+    /// Restores the processes of the container from the checkpoint in the
+    /// restore options with CRIU instead of running the init process.
+    fn restore_container(&mut self) -> Result<Pid> {
+        let opts = self
+            .restore_options
+            .as_ref()
+            .context("no checkpoint to restore the container from")?;
+        let container = self
+            .container
+            .as_mut()
+            .context("no state for the restored container")?;
+        let mounts = self.spec.mounts().clone().unwrap_or_default();
+        let args = container_restore::criu_restore_args(&self.rootfs, &mounts, opts);
+        let criu_pid_file = container_restore::restore_pid_file(opts);
+
+        let pid = container_restore::restore_container(container, || {
+            container_restore::run_criu_restore(&args, &criu_pid_file)
+        })?;
 
+        if let Some(pid_file) = &self.pid_file {
+            write_pid_file(pid_file, pid, self.pid_file_json)?;
+        }
+
+        Ok(pid)
+    }
+
+    fn cleanup_container(&self, remove_cgroup: bool, remove_socket: bool) -> Result<()> {
         let mut errors = Vec::new();
 
-        if let Err(e) = cmanager.remove().context("failed to remove cgroup") {
-            errors.push(e.to_string());
+        if remove_cgroup {
+            if let Err(e) = self
+                .cgroup_manager()
+                .and_then(|cmanager| Ok(cmanager.remove()?))
+                .context("failed to remove cgroup")
+            {
+                errors.push(e.to_string());
+            }
+        }
+
+        if remove_socket {
+            if let Err(e) = remove_notify_socket(&self.notify_path) {
+                errors.push(e.to_string());
+            }
+        }
+
+        // tooling relies on the poststop hooks like for a deleted container
+        if matches!(self.container_type, ContainerType::InitContainer) {
+            if let Err(e) = run_poststop_hooks(self.spec, self.container.as_ref()) {
+                errors.push(e.to_string());
+            }
         }
 
         if let Some(container) = &self.container {
@@ -208,3 +489,882 @@ impl<'a> ContainerBuilderImpl<'a> {
         Ok(())
     }
 }
+
+/// Runs the poststop hooks of spec for container, e.g. when its creation
+/// failed.
+fn run_poststop_hooks(spec: &Spec, container: Option<&Container>) -> Result<()> {
+    match spec
+        .hooks()
+        .as_ref()
+        .and_then(|hooks| hooks.poststop().as_ref())
+    {
+        Some(poststop) => {
+            hooks::run_hooks(Some(poststop), container).context("failed to run poststop hooks")
+        }
+        None => Ok(()),
+    }
+}
+
+/// Removes a cgroup when dropped, unless the creation of its container was
+/// committed.
+struct CgroupGuard<'a, T: CgroupManager> {
+    cmanager: &'a T,
+    committed: bool,
+}
+
+impl<'a, T: CgroupManager> CgroupGuard<'a, T> {
+    fn new(cmanager: &'a T) -> Self {
+        Self {
+            cmanager,
+            committed: false,
+        }
+    }
+
+    /// Keeps the cgroup, the container was created successfully.
+    fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl<T: CgroupManager> Drop for CgroupGuard<'_, T> {
+    fn drop(&mut self) {
+        if !self.committed {
+            // the error type of the manager isn't necessarily printable
+            if self.cmanager.remove().is_err() {
+                log::warn!("failed to remove cgroup of a partially created container");
+            }
+        }
+    }
+}
+
+/// Sets the OOM score adjustment through write. Rootless containers often run
+/// in sandboxes where /proc/self/oom_score_adj isn't writable, so a failure
+/// is only logged for them, while it is fatal for everyone else.
+fn set_oom_score_adj<F>(oom_score_adj: i32, rootless: bool, write: F) -> Result<()>
+where
+    F: FnOnce(&str) -> io::Result<()>,
+{
+    log::debug!("Set OOM score to {}", oom_score_adj);
+    match write(&oom_score_adj.to_string()) {
+        Err(err) if rootless => {
+            log::warn!("failed to set OOM score to {}: {}", oom_score_adj, err);
+            Ok(())
+        }
+        res => res.context("failed to write oom_score_adj"),
+    }
+}
+
+/// Fails if the cgroup managed by cmanager already contains processes, which
+/// means it is in use by another container, e.g. one with stale state.
+fn ensure_cgroup_unused<T: CgroupManager>(cmanager: &T, container_id: &str) -> Result<()> {
+    // a cgroup that doesn't exist yet can't be listed, which is fine
+    if let Ok(pids) = cmanager.get_all_pids() {
+        if !pids.is_empty() {
+            bail!(
+                "cgroup for container {} already contains processes {:?}, is the container id already in use?",
+                container_id,
+                pids
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Fails unless the cgroup managed by cmanager contains processes, which means
+/// it still belongs to the running container and can be joined by adding a
+/// task to it. Adding a task to a missing cgroup would create a new one.
+fn ensure_cgroup_exists<T: CgroupManager>(cmanager: &T, container_id: &str) -> Result<()> {
+    match cmanager.get_all_pids() {
+        Ok(pids) if !pids.is_empty() => Ok(()),
+        _ => bail!(
+            "cgroup for container {} does not exist or is empty, is the container still running?",
+            container_id
+        ),
+    }
+}
+
+/// Checks that every file descriptor in fds is open.
+fn validate_preserved_fds(fds: Range<RawFd>) -> Result<()> {
+    for fd in fds.clone() {
+        if let Err(errno) = fcntl::fcntl(fd, fcntl::FcntlArg::F_GETFD) {
+            bail!(
+                "preserved file descriptor {} of {:?} is not usable: {}",
+                fd,
+                fds,
+                errno
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the rootfs is a readable directory.
+fn validate_rootfs(rootfs: &Path) -> Result<()> {
+    let metadata =
+        fs::metadata(rootfs).with_context(|| format!("rootfs {rootfs:?} is not accessible"))?;
+    if !metadata.is_dir() {
+        bail!("rootfs {:?} is not a directory", rootfs);
+    }
+    fs::read_dir(rootfs).with_context(|| format!("rootfs {rootfs:?} is not readable"))?;
+
+    Ok(())
+}
+
+/// Opens the log file at path, if any, right after the preserved file
+/// descriptors and returns it with the number of file descriptors to
+/// preserve, which then includes the log file.
+fn pass_log_file(path: Option<&Path>, preserve_fds: i32) -> Result<(Option<fs::File>, i32)> {
+    match path {
+        Some(path) => Ok((
+            Some(open_log_file(path, 3 + preserve_fds)?),
+            preserve_fds + 1,
+        )),
+        None => Ok((None, preserve_fds)),
+    }
+}
+
+/// Opens the log file at path for appending as file descriptor fd.
+fn open_log_file(path: &Path, fd: RawFd) -> Result<fs::File> {
+    if fcntl::fcntl(fd, fcntl::FcntlArg::F_GETFD).is_ok() {
+        bail!("file descriptor {fd} for the log file {path:?} is already in use");
+    }
+
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open log file {path:?}"))?;
+    // dup2 clears FD_CLOEXEC, the container processes inherit the new fd
+    nix::unistd::dup2(file.as_raw_fd(), fd)
+        .with_context(|| format!("failed to move log file {path:?} to fd {fd}"))?;
+
+    // fd was just duplicated from file and isn't owned by anything else
+    Ok(unsafe { fs::File::from_raw_fd(fd) })
+}
+
+/// The notify socket at the path is in use by another process.
+#[derive(Debug, thiserror::Error)]
+#[error("notify socket {0:?} is in use, the container may already be running")]
+struct NotifySocketInUse(PathBuf);
+
+/// Creates the notify socket at path. A socket left behind by a crashed run
+/// is removed and the creation retried once, but a socket something still
+/// listens on means the container is probably running.
+fn create_notify_listener(path: &Path) -> Result<NotifyListener> {
+    match NotifyListener::new(path) {
+        Err(NotifyListenerError::Bind { source, .. })
+            if source.kind() == io::ErrorKind::AddrInUse =>
+        {
+            match UnixStream::connect(path) {
+                Ok(_) => Err(NotifySocketInUse(path.to_path_buf()).into()),
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    log::warn!("removing stale notify socket {:?}", path);
+                    remove_notify_socket(path)?;
+                    NotifyListener::new(path)
+                        .with_context(|| format!("failed to create notify socket {path:?}"))
+                }
+                Err(err) => {
+                    Err(err).with_context(|| format!("failed to check notify socket {path:?}"))
+                }
+            }
+        }
+        res => Ok(res?),
+    }
+}
+
+/// Chowns the notify socket at path to the host ids user is mapped to, the
+/// ids of user are host ids already if the container isn't rootless.
+fn chown_notify_socket(
+    syscall: &dyn Syscall,
+    path: &Path,
+    user: &User,
+    rootless: Option<&Rootless>,
+) -> Result<()> {
+    let (uid, gid) = match rootless {
+        Some(rootless) => (
+            map_to_host(user.uid(), rootless.uid_mappings, "uid")?,
+            map_to_host(user.gid(), rootless.gid_mappings, "gid")?,
+        ),
+        None => (user.uid(), user.gid()),
+    };
+
+    syscall
+        .chown(path, Some(Uid::from_raw(uid)), Some(Gid::from_raw(gid)))
+        .with_context(|| format!("failed to chown notify socket {path:?} to {uid}:{gid}"))
+}
+
+/// Maps id of the container user to the host, ids are not changed without
+/// mappings, e.g. when joining an existing user namespace.
+fn map_to_host(id: u32, mappings: Option<&Vec<LinuxIdMapping>>, kind: &str) -> Result<u32> {
+    match mappings {
+        Some(mappings) => rootless::host_id(id, mappings)
+            .with_context(|| format!("{kind} {id} of the container process is not mapped")),
+        None => Ok(id),
+    }
+}
+
+/// Removes the notify socket created by run_container. Anything at the path
+/// that isn't a socket wasn't created by us and is left alone.
+fn remove_notify_socket(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)
+            .with_context(|| format!("failed to remove notify socket {path:?}")),
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("failed to stat notify socket {path:?}")),
+    }
+}
+
+/// Runs run, calling kill from a watchdog thread if it doesn't return within
+/// timeout. kill has to make run return, e.g. by killing the processes it
+/// waits for. Without a timeout, run is just called.
+fn run_with_deadline<T, R, K>(timeout: Option<Duration>, run: R, kill: K) -> Result<T>
+where
+    R: FnOnce() -> Result<T>,
+    K: FnOnce() + Send,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return run(),
+    };
+
+    let timed_out = AtomicBool::new(false);
+    let (done_sender, done_receiver) = mpsc::channel::<()>();
+    let res = crossbeam_utils::thread::scope(|s| {
+        let timed_out = &timed_out;
+        s.spawn(move |_| {
+            // the sender is dropped once run returns
+            if let Err(RecvTimeoutError::Timeout) = done_receiver.recv_timeout(timeout) {
+                timed_out.store(true, Ordering::SeqCst);
+                kill();
+            }
+        });
+        let res = run();
+        drop(done_sender);
+        res
+    })
+    .unwrap_or_else(|err| std::panic::resume_unwind(err));
+
+    if timed_out.load(Ordering::SeqCst) {
+        let msg = format!("container processes were not set up within {timeout:?}");
+        // the error of run, e.g. how the killed process exited, is the cause
+        return Err(match res {
+            Err(inner) => inner.context(msg),
+            Ok(_) => anyhow!(msg),
+        });
+    }
+
+    res
+}
+
+/// Writes pid to the pid file, either as bare integer or as JSON object like
+/// `{"pid":1234,"created":"2023-05-04T12:00:00+00:00"}`.
+fn write_pid_file(path: &Path, pid: Pid, json: bool) -> Result<()> {
+    let contents = if json {
+        serde_json::json!({
+            "pid": pid.as_raw(),
+            "created": chrono::Utc::now().to_rfc3339(),
+        })
+        .to_string()
+    } else {
+        format!("{pid}")
+    };
+
+    utils::write_file_atomically(path, contents).context("failed to write pid file")
+}
+
+/// Makes the process non-dumpable through set_dumpable if the container joins
+/// any namespaces, an empty list of namespaces counts as none. This is only a
+/// hardening measure, so a failure is logged instead of failing the creation.
+fn set_non_dumpable<F>(namespaces: Option<&[LinuxNamespace]>, set_dumpable: F)
+where
+    F: FnOnce(bool) -> std::result::Result<(), i32>,
+{
+    if namespaces.map_or(true, |namespaces| namespaces.is_empty()) {
+        return;
+    }
+
+    if let Err(errno) = set_dumpable(false) {
+        log::warn!(
+            "failed to make the process non-dumpable: {}",
+            nix::errno::Errno::from_i32(errno)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::YoukiConfig, process::container_intermediate_process::apply_cgroups,
+        syscall::syscall::create_syscall,
+    };
+    use libcgroups::test_manager::TestManager;
+    use oci_spec::runtime::LinuxResources;
+    use serial_test::serial;
+    use std::thread;
+
+    fn failing_write(_: &str) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::PermissionDenied))
+    }
+
+    fn builder_impl<'a>(
+        syscall: &'a dyn Syscall,
+        spec: &'a Spec,
+        dir: &Path,
+        container_type: ContainerType,
+    ) -> ContainerBuilderImpl<'a> {
+        ContainerBuilderImpl {
+            container_type,
+            syscall,
+            use_systemd: false,
+            cgroup_manager_kind: CgroupManagerKind::Auto,
+            container_id: "container".to_owned(),
+            spec,
+            rootfs: dir.to_path_buf(),
+            pid_file: None,
+            pid_file_json: false,
+            console_socket: None,
+            rootless: None,
+            notify_path: dir.join("notify.sock"),
+            container: None,
+            joined_container: None,
+            preserve_fds: 0,
+            detached: true,
+            executor_manager: ExecutorManager {
+                executors: Vec::new(),
+            },
+            rootfs_validator: None,
+            event_sender: None,
+            event_log: None,
+            seccomp_notify_path: None,
+            create_timeout: None,
+            extra_hook_env: Vec::new(),
+            no_pivot: false,
+            restore_options: None,
+            log_file: None,
+            chown_notify_socket: false,
+            scheduler: None,
+        }
+    }
+
+    #[test]
+    fn test_set_oom_score_adj() -> Result<()> {
+        let mut written = String::new();
+        set_oom_score_adj(-500, false, |value| {
+            written = value.to_string();
+            Ok(())
+        })?;
+        assert_eq!(written, "-500");
+
+        assert!(set_oom_score_adj(100, true, failing_write).is_ok());
+        assert!(set_oom_score_adj(100, false, failing_write).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_run_poststop_hooks() -> Result<()> {
+        use oci_spec::runtime::{HookBuilder, HooksBuilder, SpecBuilder};
+
+        let tmp = tempfile::tempdir()?;
+        let marker = tmp.path().join("poststop");
+        let hook = |script: String| {
+            HookBuilder::default()
+                .path("bash")
+                .args(vec!["bash".to_string(), "-c".to_string(), script])
+                .build()
+        };
+        let spec = |script| -> Result<Spec> {
+            Ok(SpecBuilder::default()
+                .hooks(
+                    HooksBuilder::default()
+                        .poststop(vec![hook(script)?])
+                        .build()?,
+                )
+                .build()?)
+        };
+        let container = Container::default();
+
+        run_poststop_hooks(
+            &spec(format!("touch {}", marker.display()))?,
+            Some(&container),
+        )?;
+        assert!(marker.exists());
+
+        let err = run_poststop_hooks(&spec("exit 1".to_string())?, Some(&container)).unwrap_err();
+        assert!(err.to_string().contains("poststop"), "{err}");
+
+        // nothing to run without hooks
+        run_poststop_hooks(
+            &SpecBuilder::default()
+                .hooks(HooksBuilder::default().build()?)
+                .build()?,
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chown_notify_socket() -> Result<()> {
+        use crate::rootless::RootlessIDMapper;
+        use crate::syscall::{
+            syscall::create_syscall,
+            test::{ChownArgs, TestHelperSyscall},
+        };
+        use oci_spec::runtime::{LinuxIdMappingBuilder, UserBuilder};
+
+        let path = Path::new("/run/youki/container/notify.sock");
+        let user = UserBuilder::default().uid(1000u32).gid(100u32).build()?;
+        let mappings = vec![LinuxIdMappingBuilder::default()
+            .container_id(0u32)
+            .host_id(100000u32)
+            .size(65536u32)
+            .build()?];
+        let rootless = Rootless {
+            newuidmap: None,
+            newgidmap: None,
+            uid_mappings: Some(&mappings),
+            gid_mappings: Some(&mappings),
+            user_namespace: None,
+            privileged: false,
+            rootless_id_mapper: RootlessIDMapper::new(),
+        };
+
+        let syscall = create_syscall();
+        chown_notify_socket(syscall.as_ref(), path, &user, Some(&rootless))?;
+        chown_notify_socket(syscall.as_ref(), path, &user, None)?;
+
+        let got = syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_chown_args();
+        let chown = |uid, gid| ChownArgs {
+            path: path.to_path_buf(),
+            owner: Some(Uid::from_raw(uid)),
+            group: Some(Gid::from_raw(gid)),
+        };
+        assert_eq!(got, vec![chown(101000, 100100), chown(1000, 100)]);
+
+        // an unmapped user can't be chowned to
+        let user = UserBuilder::default().uid(70000u32).gid(100u32).build()?;
+        let err = chown_notify_socket(syscall.as_ref(), path, &user, Some(&rootless)).unwrap_err();
+        assert!(err.to_string().contains("uid 70000"), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_notify_socket() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let socket_path = tmp.path().join("notify.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket_path)?;
+
+        remove_notify_socket(&socket_path)?;
+        assert!(!socket_path.exists());
+        // removing it again is not an error
+        remove_notify_socket(&socket_path)?;
+
+        let file_path = tmp.path().join("notify.file");
+        fs::write(&file_path, "")?;
+        remove_notify_socket(&file_path)?;
+        assert!(file_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_create_notify_listener_stale_socket() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let socket_path = tmp.path().join("notify.sock");
+        // the socket file stays behind when the listener is dropped
+        drop(std::os::unix::net::UnixListener::bind(&socket_path)?);
+        assert!(socket_path.exists());
+
+        let _listener = create_notify_listener(&socket_path)?;
+        UnixStream::connect(&socket_path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_create_notify_listener_in_use() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let socket_path = tmp.path().join("notify.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket_path)?;
+
+        let err = create_notify_listener(&socket_path).unwrap_err();
+        assert!(err.to_string().contains("may already be running"), "{err}");
+        assert!(socket_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_cleanup_keeps_notify_socket_in_use() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let syscall = create_syscall();
+        let spec = Spec::default();
+        let builder = builder_impl(
+            syscall.as_ref(),
+            &spec,
+            tmp_dir.path(),
+            ContainerType::InitContainer,
+        );
+        let _listener = std::os::unix::net::UnixListener::bind(&builder.notify_path)?;
+
+        let err = create_notify_listener(&builder.notify_path)
+            .context("failed to create container")
+            .unwrap_err();
+        assert!(err.downcast_ref::<NotifySocketInUse>().is_some());
+        builder.cleanup_container(false, false)?;
+        assert!(builder.notify_path.exists());
+
+        // a socket left behind by the failed creation is removed
+        builder.cleanup_container(false, true)?;
+        assert!(!builder.notify_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_rootfs() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        validate_rootfs(tmp_dir.path())?;
+
+        let missing = tmp_dir.path().join("rootfs");
+        let err = validate_rootfs(&missing).unwrap_err();
+        assert!(err.to_string().contains("is not accessible"), "{err}");
+
+        let file = tmp_dir.path().join("config.json");
+        fs::write(&file, "{}")?;
+        let err = validate_rootfs(&file).unwrap_err();
+        assert!(err.to_string().contains("is not a directory"), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pass_log_file() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("container.log");
+
+        let (log_file, preserve_fds) = pass_log_file(None, 2)?;
+        assert!(log_file.is_none());
+        assert_eq!(preserve_fds, 2);
+
+        // far above the fds the test harness uses
+        let (log_file, preserve_fds) = pass_log_file(Some(&path), 997)?;
+        let mut log_file = log_file.unwrap();
+        assert_eq!(preserve_fds, 998);
+        assert_eq!(log_file.as_raw_fd(), 1000);
+        let flags = fcntl::fcntl(1000, fcntl::FcntlArg::F_GETFD)?;
+        assert!(!fcntl::FdFlag::from_bits_truncate(flags).contains(fcntl::FdFlag::FD_CLOEXEC));
+        std::io::Write::write_all(&mut log_file, b"log")?;
+        assert_eq!(fs::read_to_string(&path)?, "log");
+
+        // the fd after the preserved ones must be free
+        assert!(pass_log_file(Some(&path), 997).is_err());
+        drop(log_file);
+        assert!(fcntl::fcntl(1000, fcntl::FcntlArg::F_GETFD).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_preserved_fds() -> Result<()> {
+        let (read_end, write_end) = nix::unistd::pipe()?;
+        validate_preserved_fds(read_end..write_end + 1)?;
+
+        // claim one more fd than was actually opened
+        nix::unistd::close(write_end)?;
+        let err = validate_preserved_fds(read_end..write_end + 1).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("preserved file descriptor {write_end} ")),
+            "{err}"
+        );
+
+        nix::unistd::close(read_end)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_cgroup_unused() -> Result<()> {
+        let cmanager = TestManager::default();
+        ensure_cgroup_unused(&cmanager, "container")?;
+
+        cmanager.add_task(Pid::from_raw(42))?;
+        let err = ensure_cgroup_unused(&cmanager, "container").unwrap_err();
+        assert!(err.to_string().contains("already in use"), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_cgroup_exists() -> Result<()> {
+        let cmanager = TestManager::default();
+        let err = ensure_cgroup_exists(&cmanager, "container").unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+        assert!(cmanager.get_add_task_args().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tenant_joins_cgroup() -> Result<()> {
+        // cgroup of the running container, which holds its init process
+        let cmanager = TestManager::default();
+        let init_pid = Pid::from_raw(42);
+        cmanager.add_task(init_pid)?;
+
+        // what a tenant goes through, in create_with_report and the
+        // intermediate process
+        ensure_cgroup_exists(&cmanager, "container")?;
+        apply_cgroups(&cmanager, Some(&LinuxResources::default()), false)?;
+
+        let tenant_pid = Pid::from_raw(procfs::process::Process::myself()?.pid());
+        assert_eq!(cmanager.get_add_task_args(), vec![init_pid, tenant_pid]);
+        assert!(!cmanager.apply_called());
+        assert!(!cmanager.remove_called());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tenant_cgroup_config() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let syscall = create_syscall();
+        let spec = Spec::default();
+
+        let init = builder_impl(
+            syscall.as_ref(),
+            &spec,
+            tmp_dir.path(),
+            ContainerType::InitContainer,
+        );
+        assert_eq!(init.cgroup_config()?, (PathBuf::from("container"), false));
+
+        // the running container was created with another cgroup than the
+        // spec of the tenant would give
+        let mut container = Container::new(
+            "container",
+            ContainerStatus::Running,
+            Some(42),
+            tmp_dir.path(),
+            tmp_dir.path(),
+        )?;
+        container.set_systemd(true);
+        YoukiConfig {
+            hooks: None,
+            cgroup_path: PathBuf::from("/youki/container"),
+        }
+        .save(&container.root)?;
+
+        let mut tenant = builder_impl(
+            syscall.as_ref(),
+            &spec,
+            tmp_dir.path(),
+            ContainerType::TenantContainer { exec_notify_fd: -1 },
+        );
+        tenant.joined_container = Some(container);
+        assert_eq!(
+            tenant.cgroup_config()?,
+            (PathBuf::from("/youki/container"), true)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_pid_file() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let pid_file = tmp_dir.path().join("container.pid");
+
+        write_pid_file(&pid_file, Pid::from_raw(42), false)?;
+        let got: i32 = fs::read_to_string(&pid_file)?.parse()?;
+        assert_eq!(got, 42);
+
+        write_pid_file(&pid_file, Pid::from_raw(42), true)?;
+        let got: serde_json::Value = serde_json::from_str(&fs::read_to_string(&pid_file)?)?;
+        assert_eq!(got["pid"], 42);
+        let created = got["created"].as_str().context("no created in pid file")?;
+        chrono::DateTime::parse_from_rfc3339(created)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_with_deadline() -> Result<()> {
+        let got = run_with_deadline(None, || Ok(42), || unreachable!())?;
+        assert_eq!(got, 42);
+
+        // run blocks until it is killed
+        let (sender, receiver) = mpsc::channel::<()>();
+        let err = run_with_deadline(
+            Some(Duration::from_millis(100)),
+            || {
+                receiver
+                    .recv()
+                    .context("init process exited before it was set up")?;
+                Ok(())
+            },
+            move || drop(sender),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("were not set up within"), "{err}");
+
+        let got = run_with_deadline(Some(Duration::from_secs(10)), || Ok(42), || {})?;
+        assert_eq!(got, 42);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_with_deadline_kills_container_processes() -> Result<()> {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn()?;
+        let pids = ContainerPids::default();
+        pids.set_init(Pid::from_raw(child.id() as i32));
+
+        let start = Instant::now();
+        let err = run_with_deadline(
+            Some(Duration::from_millis(100)),
+            || {
+                let status = child.wait()?;
+                bail!("init process exited with {status}")
+            },
+            || pids.kill(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("were not set up within"), "{err}");
+        assert!(format!("{err:#}").contains("signal: 9"), "{err:#}");
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_event_log() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let syscall = create_syscall();
+        let spec = Spec::default();
+        let event_log = tmp_dir.path().join("events.log");
+
+        let mut builder = builder_impl(
+            syscall.as_ref(),
+            &spec,
+            &tmp_dir.path().join("missing"),
+            ContainerType::InitContainer,
+        );
+        builder.event_log = Some(event_log.clone());
+        let err = builder.create_with_report().unwrap_err();
+
+        let records = fs::read_to_string(&event_log)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<Vec<serde_json::Value>, _>>()?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["phase"], event::FAILED_PHASE);
+        assert_eq!(records[0]["pid"], serde_json::Value::Null);
+        assert_eq!(records[0]["error"], format!("{err:#}"));
+        assert!(err.to_string().contains("is not accessible"), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_report() {
+        let pid = Pid::from_raw(42);
+        let start = Instant::now();
+        thread::sleep(Duration::from_millis(1));
+        let intermediate_ready = Instant::now();
+        thread::sleep(Duration::from_millis(1));
+        let init_ready = Instant::now();
+        thread::sleep(Duration::from_millis(1));
+        let end = Instant::now();
+
+        let report = CreateReport::new(
+            pid,
+            start,
+            Some(MainProcessTimestamps {
+                intermediate_ready,
+                init_ready,
+            }),
+            end,
+        );
+        assert_eq!(report.pid, pid);
+        assert!(report.namespace_duration > Duration::ZERO);
+        assert!(report.mount_duration > Duration::ZERO);
+        assert!(report.total >= report.namespace_duration + report.mount_duration);
+
+        // a restored container only has a total
+        let report = CreateReport::new(pid, start, None, end);
+        assert_eq!(report.namespace_duration, Duration::ZERO);
+        assert_eq!(report.mount_duration, Duration::ZERO);
+        assert!(report.total > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_cgroup_manager_kind() {
+        for (use_systemd, rootless) in [(false, false), (true, false), (false, true)] {
+            assert!(CgroupManagerKind::Systemd.use_systemd(use_systemd, rootless));
+            assert!(!CgroupManagerKind::CgroupFs.use_systemd(use_systemd, rootless));
+        }
+
+        assert!(!CgroupManagerKind::Auto.use_systemd(false, false));
+        assert!(CgroupManagerKind::Auto.use_systemd(true, false));
+        // rootless containers use systemd unless told otherwise
+        assert!(CgroupManagerKind::Auto.use_systemd(false, true));
+    }
+
+    #[test]
+    fn test_cgroup_guard() -> Result<()> {
+        fn create(cmanager: &TestManager, fail: bool) -> Result<()> {
+            let guard = CgroupGuard::new(cmanager);
+            if fail {
+                bail!("failed to create container");
+            }
+            guard.commit();
+            Ok(())
+        }
+
+        let cmanager = TestManager::default();
+        assert!(create(&cmanager, true).is_err());
+        assert!(cmanager.remove_called());
+
+        let cmanager = TestManager::default();
+        create(&cmanager, false)?;
+        assert!(!cmanager.remove_called());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_non_dumpable() {
+        let namespaces = [LinuxNamespace::default()];
+        let mut requested = None;
+        set_non_dumpable(Some(&namespaces), |dumpable| {
+            requested = Some(dumpable);
+            Err(libc::EPERM)
+        });
+        assert_eq!(requested, Some(false));
+    }
+
+    #[test]
+    fn test_set_non_dumpable_without_namespaces() {
+        for namespaces in [None, Some(&[][..])] {
+            let mut called = false;
+            set_non_dumpable(namespaces, |_| {
+                called = true;
+                Ok(())
+            });
+            assert!(!called, "{namespaces:?}");
+        }
+    }
+}
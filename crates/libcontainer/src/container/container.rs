@@ -3,8 +3,9 @@ use std::ffi::OsString;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::DateTime;
+use libcgroups::common::AnyCgroupManager;
 use nix::unistd::Pid;
 
 use chrono::Utc;
@@ -206,6 +207,26 @@ impl Container {
         let spec = YoukiConfig::load(&self.root)?;
         Ok(spec)
     }
+
+    /// Path of the container cgroup and whether systemd manages it, from the
+    /// saved state.
+    pub(super) fn cgroup_config(&self) -> Result<(PathBuf, bool)> {
+        let cgroups_path = self.spec()?.cgroup_path;
+        let use_systemd = self
+            .systemd()
+            .context("container state does not contain cgroup manager")?;
+        Ok((cgroups_path, use_systemd))
+    }
+
+    /// Creates the manager of the container cgroup from the saved state.
+    pub(super) fn cgroup_manager(&self) -> Result<AnyCgroupManager> {
+        let (cgroups_path, use_systemd) = self.cgroup_config()?;
+        Ok(libcgroups::common::create_cgroup_manager(
+            cgroups_path,
+            use_systemd,
+            self.id(),
+        )?)
+    }
 }
 
 /// Checkpoint parameter structure
@@ -219,10 +240,20 @@ pub struct CheckpointOptions {
     pub work_path: Option<PathBuf>,
 }
 
+/// Restore parameter structure
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    pub ext_unix_sk: bool,
+    pub file_locks: bool,
+    pub image_path: PathBuf,
+    pub shell_job: bool,
+    pub tcp_established: bool,
+    pub work_path: Option<PathBuf>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use anyhow::Context;
     use serial_test::serial;
 
     #[test]
@@ -1,14 +1,15 @@
 use super::{Container, ContainerStatus};
 use crate::container::container::CheckpointOptions;
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
 use libcgroups::common::CgroupSetup::{Hybrid, Legacy};
 #[cfg(feature = "v1")]
 use libcgroups::common::DEFAULT_CGROUP_ROOT;
-use oci_spec::runtime::Spec;
+use oci_spec::runtime::{Mount, Spec};
 use std::fs::{self, File};
 use std::io::Write;
 use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
 
 const CRIU_CHECKPOINT_LOG_FILE: &str = "dump.log";
 const DESCRIPTORS_JSON: &str = "descriptors.json";
@@ -29,7 +30,10 @@ impl Container {
             );
         }
 
-        let mut criu = rust_criu::Criu::new().unwrap();
+        let pid: i32 = self
+            .pid()
+            .with_context(|| format!("container {} has no pid", self.id()))?
+            .into();
 
         // We need to tell CRIU that all bind mounts are external. CRIU will fail checkpointing
         // if it does not know that these bind mounts are coming from the outside of the container.
@@ -38,46 +42,13 @@ impl Container {
         // information found in 'config.json'.
         let source_spec_path = self.bundle().join("config.json");
         let spec = Spec::load(source_spec_path)?;
-        let mounts = spec.mounts().clone();
-        for m in mounts.unwrap() {
-            match m.typ().as_deref() {
-                Some("bind") => {
-                    let dest = m
-                        .destination()
-                        .clone()
-                        .into_os_string()
-                        .into_string()
-                        .expect("failed to convert mount destination");
-                    criu.set_external_mount(dest.clone(), dest);
-                }
-                Some("cgroup") => {
-                    match libcgroups::common::get_cgroup_setup()
-                        .context("failed to determine cgroup setup")?
-                    {
-                        // For v1 it is necessary to list all cgroup mounts as external mounts
-                        Legacy | Hybrid => {
-                            #[cfg(not(feature = "v1"))]
-                            panic!("libcontainer can't run in a Legacy or Hybrid cgroup setup without the v1 feature");
-                            #[cfg(feature = "v1")]
-                            for mp in libcgroups::v1::util::list_subsystem_mount_points()
-                                .context("failed to get subsystem mount points")?
-                            {
-                                let cgroup_mount = mp
-                                    .clone()
-                                    .into_os_string()
-                                    .into_string()
-                                    .expect("failed to convert mount point");
-                                if cgroup_mount.starts_with(DEFAULT_CGROUP_ROOT) {
-                                    criu.set_external_mount(cgroup_mount.clone(), cgroup_mount);
-                                }
-                            }
-                        }
-                        _ => (),
-                    }
-                }
-                _ => (),
-            }
-        }
+        let mounts = spec.mounts().clone().unwrap_or_default();
+        let cgroup_mount_points = cgroup_mount_points(&mounts)?;
+        let args = CriuDumpArgs::new(pid, self.bundle(), opts, &mounts, &cgroup_mount_points)?;
+
+        let mut criu =
+            rust_criu::Criu::new().map_err(|err| anyhow!("failed to initialize CRIU: {err}"))?;
+        args.apply(&mut criu);
 
         let directory = std::fs::File::open(&opts.image_path)
             .with_context(|| format!("failed to open {:?}", opts.image_path))?;
@@ -91,13 +62,13 @@ impl Container {
             criu.set_work_dir_fd(work_dir.as_raw_fd());
         }
 
-        let pid: i32 = self.pid().unwrap().into();
-
         // Remember original stdin, stdout, stderr for container restore.
         let mut descriptors = Vec::new();
         for n in 0..3 {
             let link_path = match fs::read_link(format!("/proc/{pid}/fd/{n}")) {
-                Ok(lp) => lp.into_os_string().into_string().unwrap(),
+                Ok(lp) => lp.into_os_string().into_string().map_err(|path| {
+                    anyhow!("descriptor {n} of the container links to non UTF-8 path {path:?}")
+                })?,
                 Err(..) => "/dev/null".to_string(),
             };
             descriptors.push(link_path);
@@ -106,23 +77,6 @@ impl Container {
         let mut descriptors_json = File::create(descriptors_json_path)?;
         write!(descriptors_json, "{}", serde_json::to_string(&descriptors)?)?;
 
-        criu.set_log_file(CRIU_CHECKPOINT_LOG_FILE.to_string());
-        criu.set_log_level(4);
-        criu.set_pid(pid);
-        criu.set_leave_running(opts.leave_running);
-        criu.set_ext_unix_sk(opts.ext_unix_sk);
-        criu.set_shell_job(opts.shell_job);
-        criu.set_tcp_established(opts.tcp_established);
-        criu.set_file_locks(opts.file_locks);
-        criu.set_orphan_pts_master(true);
-        criu.set_manage_cgroups(true);
-        criu.set_root(
-            self.bundle()
-                .clone()
-                .into_os_string()
-                .into_string()
-                .unwrap(),
-        );
         if let Err(e) = criu.dump() {
             bail!(
                 "checkpointing container {} failed with {:?}. Please check CRIU logfile {:}/{}",
@@ -144,3 +98,178 @@ impl Container {
         Ok(())
     }
 }
+
+/// Lists the host cgroup v1 hierarchies, which have to be passed to CRIU as
+/// external mounts if the container mounts the cgroup filesystem.
+fn cgroup_mount_points(mounts: &[Mount]) -> Result<Vec<PathBuf>> {
+    if !mounts.iter().any(|m| m.typ().as_deref() == Some("cgroup")) {
+        return Ok(Vec::new());
+    }
+
+    match libcgroups::common::get_cgroup_setup().context("failed to determine cgroup setup")? {
+        Legacy | Hybrid => {
+            #[cfg(not(feature = "v1"))]
+            bail!(
+                "libcontainer can't run in a Legacy or Hybrid cgroup setup without the v1 feature"
+            );
+            #[cfg(feature = "v1")]
+            Ok(libcgroups::v1::util::list_subsystem_mount_points()
+                .context("failed to get subsystem mount points")?
+                .into_iter()
+                .filter(|mp| mp.starts_with(DEFAULT_CGROUP_ROOT))
+                .collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// The options of a CRIU dump of a container, kept apart from the CRIU
+/// handle so they can be checked without running CRIU.
+#[derive(Debug, PartialEq, Eq)]
+struct CriuDumpArgs {
+    pid: i32,
+    root: String,
+    external_mounts: Vec<String>,
+    leave_running: bool,
+    ext_unix_sk: bool,
+    shell_job: bool,
+    tcp_established: bool,
+    file_locks: bool,
+}
+
+impl CriuDumpArgs {
+    fn new(
+        pid: i32,
+        bundle: &Path,
+        opts: &CheckpointOptions,
+        mounts: &[Mount],
+        cgroup_mount_points: &[PathBuf],
+    ) -> Result<Self> {
+        let bind_mounts = mounts
+            .iter()
+            .filter(|m| m.typ().as_deref() == Some("bind"))
+            .map(|m| m.destination().as_path());
+        let external_mounts = bind_mounts
+            .chain(cgroup_mount_points.iter().map(PathBuf::as_path))
+            .map(|path| {
+                path.to_str()
+                    .map(str::to_string)
+                    .with_context(|| format!("mount destination {path:?} is not UTF-8"))
+            })
+            .collect::<Result<_>>()?;
+        let root = bundle
+            .to_str()
+            .with_context(|| format!("bundle path {bundle:?} is not UTF-8"))?
+            .to_string();
+
+        Ok(Self {
+            pid,
+            root,
+            external_mounts,
+            leave_running: opts.leave_running,
+            ext_unix_sk: opts.ext_unix_sk,
+            shell_job: opts.shell_job,
+            tcp_established: opts.tcp_established,
+            file_locks: opts.file_locks,
+        })
+    }
+
+    fn apply(&self, criu: &mut rust_criu::Criu) {
+        for mount in &self.external_mounts {
+            criu.set_external_mount(mount.clone(), mount.clone());
+        }
+        criu.set_log_file(CRIU_CHECKPOINT_LOG_FILE.to_string());
+        criu.set_log_level(4);
+        criu.set_pid(self.pid);
+        criu.set_leave_running(self.leave_running);
+        criu.set_ext_unix_sk(self.ext_unix_sk);
+        criu.set_shell_job(self.shell_job);
+        criu.set_tcp_established(self.tcp_established);
+        criu.set_file_locks(self.file_locks);
+        criu.set_orphan_pts_master(true);
+        criu.set_manage_cgroups(true);
+        criu.set_root(self.root.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::MountBuilder;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn test_criu_dump_args() -> Result<()> {
+        let mounts = vec![
+            MountBuilder::default()
+                .destination("/proc")
+                .typ("proc")
+                .source("proc")
+                .build()?,
+            MountBuilder::default()
+                .destination("/data")
+                .typ("bind")
+                .source("/var/lib/data")
+                .build()?,
+            MountBuilder::default()
+                .destination("/sys/fs/cgroup")
+                .typ("cgroup")
+                .source("cgroup")
+                .build()?,
+        ];
+        let opts = CheckpointOptions {
+            ext_unix_sk: true,
+            file_locks: false,
+            image_path: PathBuf::from("/tmp/images"),
+            leave_running: true,
+            shell_job: false,
+            tcp_established: true,
+            work_path: None,
+        };
+
+        let got = CriuDumpArgs::new(
+            42,
+            Path::new("/run/bundle"),
+            &opts,
+            &mounts,
+            &[PathBuf::from("/sys/fs/cgroup/cpu,cpuacct")],
+        )?;
+
+        assert_eq!(
+            got,
+            CriuDumpArgs {
+                pid: 42,
+                root: "/run/bundle".to_string(),
+                external_mounts: vec![
+                    "/data".to_string(),
+                    "/sys/fs/cgroup/cpu,cpuacct".to_string()
+                ],
+                leave_running: true,
+                ext_unix_sk: true,
+                shell_job: false,
+                tcp_established: true,
+                file_locks: false,
+            }
+        );
+
+        // CRIU takes the paths as strings
+        let bundle = Path::new(std::ffi::OsStr::from_bytes(b"/run/bundle-\xff"));
+        let err = CriuDumpArgs::new(42, bundle, &opts, &mounts, &[]).unwrap_err();
+        assert!(err.to_string().contains("is not UTF-8"), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cgroup_mount_points_without_cgroup_mount() -> Result<()> {
+        let mounts = vec![MountBuilder::default()
+            .destination("/data")
+            .typ("bind")
+            .source("/var/lib/data")
+            .build()?];
+
+        assert!(cgroup_mount_points(&mounts)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use super::{Container, ContainerStatus};
 use anyhow::{bail, Context, Result};
 use libcgroups::common::{CgroupManager, FreezerState};
+use std::error::Error;
 
 impl Container {
     /// Suspends all processes within the container
@@ -36,13 +37,7 @@ impl Container {
             );
         }
 
-        let cgroups_path = self.spec()?.cgroup_path;
-        let use_systemd = self
-            .systemd()
-            .context("container state does not contain cgroup manager")?;
-        let cmanager =
-            libcgroups::common::create_cgroup_manager(cgroups_path, use_systemd, self.id())?;
-        cmanager.freeze(FreezerState::Frozen)?;
+        set_freezer_state(&self.cgroup_manager()?, FreezerState::Frozen)?;
 
         log::debug!("saving paused status");
         self.set_status(ContainerStatus::Paused).save()?;
@@ -51,3 +46,36 @@ impl Container {
         Ok(())
     }
 }
+
+/// Freezes or thaws all processes in the cgroup managed by cmanager. The
+/// manager uses the freezer controller on cgroup v1 and cgroup.freeze on v2.
+pub(super) fn set_freezer_state<T>(cmanager: &T, state: FreezerState) -> Result<()>
+where
+    T: CgroupManager,
+    T::Error: Error + Send + Sync + 'static,
+{
+    log::debug!("setting freezer state to {:?}", state);
+    cmanager
+        .freeze(state)
+        .with_context(|| format!("failed to set freezer state to {state:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libcgroups::test_manager::TestManager;
+
+    #[test]
+    fn test_set_freezer_state() -> Result<()> {
+        let cmanager = TestManager::default();
+
+        set_freezer_state(&cmanager, FreezerState::Frozen)?;
+        set_freezer_state(&cmanager, FreezerState::Thawed)?;
+
+        assert_eq!(
+            cmanager.get_freeze_args(),
+            vec![FreezerState::Frozen, FreezerState::Thawed]
+        );
+        Ok(())
+    }
+}
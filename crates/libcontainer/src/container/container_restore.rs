@@ -0,0 +1,212 @@
+//! Restores the processes of a container from the CRIU images written by
+//! [`Container::checkpoint`](super::Container::checkpoint).
+use super::{Container, ContainerStatus, RestoreOptions};
+use anyhow::{bail, Context, Result};
+use nix::unistd::{self, Pid};
+use oci_spec::runtime::Mount;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CRIU_RESTORE_LOG_FILE: &str = "restore.log";
+const CRIU_RESTORE_PID_FILE: &str = "restore.pid";
+
+/// File CRIU writes the pid of the restored init process to.
+pub(super) fn restore_pid_file(opts: &RestoreOptions) -> PathBuf {
+    opts.work_path
+        .as_ref()
+        .unwrap_or(&opts.image_path)
+        .join(CRIU_RESTORE_PID_FILE)
+}
+
+/// Builds the arguments of `criu restore` for a container with the given
+/// rootfs and mounts. The bind mounts were dumped as external mounts, so they
+/// are mapped to their sources in the spec again.
+pub(super) fn criu_restore_args(
+    rootfs: &Path,
+    mounts: &[Mount],
+    opts: &RestoreOptions,
+) -> Vec<OsString> {
+    let work_path = opts.work_path.as_ref().unwrap_or(&opts.image_path);
+    let mut args: Vec<OsString> = vec![
+        "restore".into(),
+        "--images-dir".into(),
+        opts.image_path.clone().into(),
+        "--work-dir".into(),
+        work_path.clone().into(),
+        "--log-file".into(),
+        CRIU_RESTORE_LOG_FILE.into(),
+        "-v4".into(),
+        "--root".into(),
+        rootfs.into(),
+        "--restore-detached".into(),
+        "--pidfile".into(),
+        restore_pid_file(opts).into(),
+        "--manage-cgroups".into(),
+    ];
+
+    for mount in mounts.iter().filter(|m| m.typ().as_deref() == Some("bind")) {
+        if let Some(source) = mount.source() {
+            let mut mapping = OsString::from(mount.destination());
+            mapping.push(":");
+            mapping.push(source);
+            args.push("--ext-mount-map".into());
+            args.push(mapping);
+        }
+    }
+
+    let flags = [
+        (opts.ext_unix_sk, "--ext-unix-sk"),
+        (opts.file_locks, "--file-locks"),
+        (opts.shell_job, "--shell-job"),
+        (opts.tcp_established, "--tcp-established"),
+    ];
+    args.extend(
+        flags
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| flag.into()),
+    );
+
+    args
+}
+
+/// Runs `criu restore` with args and returns the pid of the restored init
+/// process, read from pid_file.
+pub(super) fn run_criu_restore(args: &[OsString], pid_file: &Path) -> Result<Pid> {
+    let status = Command::new("criu")
+        .args(args)
+        .status()
+        .context("failed to run criu")?;
+    if !status.success() {
+        bail!(
+            "criu restore failed with {}, please check the CRIU log file {:?}",
+            status,
+            pid_file.with_file_name(CRIU_RESTORE_LOG_FILE)
+        );
+    }
+
+    let pid = fs::read_to_string(pid_file)
+        .with_context(|| format!("failed to read the restored pid from {pid_file:?}"))?;
+    let pid = pid
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid pid {pid:?} in {pid_file:?}"))?;
+    Ok(Pid::from_raw(pid))
+}
+
+/// Restores the processes of container through restore, which returns the
+/// pid of the restored init process, and records the container as running.
+/// The state is left as is if the restore fails.
+pub(super) fn restore_container<F>(container: &mut Container, restore: F) -> Result<Pid>
+where
+    F: FnOnce() -> Result<Pid>,
+{
+    let pid =
+        restore().with_context(|| format!("failed to restore container {}", container.id()))?;
+
+    container
+        .set_status(ContainerStatus::Running)
+        .set_creator(unistd::geteuid().as_raw())
+        .set_pid(pid.as_raw())
+        .save()
+        .context("failed to save container state")?;
+
+    log::debug!("container {} restored with pid {}", container.id(), pid);
+    Ok(pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::MountBuilder;
+
+    #[test]
+    fn test_criu_restore_args() -> Result<()> {
+        let mounts = vec![
+            MountBuilder::default()
+                .destination("/proc")
+                .typ("proc")
+                .source("proc")
+                .build()?,
+            MountBuilder::default()
+                .destination("/data")
+                .typ("bind")
+                .source("/var/lib/data")
+                .build()?,
+        ];
+        let opts = RestoreOptions {
+            image_path: PathBuf::from("/tmp/images"),
+            tcp_established: true,
+            shell_job: true,
+            ..Default::default()
+        };
+
+        let got = criu_restore_args(Path::new("/run/rootfs"), &mounts, &opts);
+
+        let want: Vec<OsString> = [
+            "restore",
+            "--images-dir",
+            "/tmp/images",
+            "--work-dir",
+            "/tmp/images",
+            "--log-file",
+            "restore.log",
+            "-v4",
+            "--root",
+            "/run/rootfs",
+            "--restore-detached",
+            "--pidfile",
+            "/tmp/images/restore.pid",
+            "--manage-cgroups",
+            "--ext-mount-map",
+            "/data:/var/lib/data",
+            "--shell-job",
+            "--tcp-established",
+        ]
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+        assert_eq!(want, got);
+
+        let opts = RestoreOptions {
+            work_path: Some(PathBuf::from("/tmp/work")),
+            ..opts
+        };
+        assert_eq!(
+            PathBuf::from("/tmp/work/restore.pid"),
+            restore_pid_file(&opts)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_container() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut container = Container::new(
+            "container",
+            ContainerStatus::Creating,
+            None,
+            tmp_dir.path(),
+            tmp_dir.path(),
+        )?;
+
+        let err = restore_container(&mut container, || bail!("criu failed")).unwrap_err();
+        assert!(err.to_string().contains("failed to restore"), "{err}");
+        assert_eq!(container.status(), ContainerStatus::Creating);
+        assert_eq!(container.pid(), None);
+
+        let pid = restore_container(&mut container, || Ok(Pid::from_raw(42)))?;
+        assert_eq!(pid, Pid::from_raw(42));
+        assert_eq!(container.status(), ContainerStatus::Running);
+        assert_eq!(container.pid(), Some(Pid::from_raw(42)));
+
+        let saved = Container::load(container.root.clone())?;
+        assert_eq!(saved.status(), ContainerStatus::Running);
+        assert_eq!(saved.pid(), Some(Pid::from_raw(42)));
+
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
-use super::{Container, ContainerStatus};
+use super::{container_pause::set_freezer_state, Container, ContainerStatus};
 
 use anyhow::{bail, Context, Result};
-use libcgroups::common::{CgroupManager, FreezerState};
+use libcgroups::common::FreezerState;
 
 impl Container {
     /// Resumes all processes within the container
@@ -38,14 +38,8 @@ impl Container {
             );
         }
 
-        let cgroups_path = self.spec()?.cgroup_path;
-        let use_systemd = self
-            .systemd()
-            .context("container state does not contain cgroup manager")?;
-        let cmanager =
-            libcgroups::common::create_cgroup_manager(cgroups_path, use_systemd, self.id())?;
         // resume the frozen container
-        cmanager.freeze(FreezerState::Thawed)?;
+        set_freezer_state(&self.cgroup_manager()?, FreezerState::Thawed)?;
 
         log::debug!("saving running status");
         self.set_status(ContainerStatus::Running).save()?;
@@ -0,0 +1,203 @@
+use nix::unistd::Pid;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+
+/// Progress of the creation of a container, reported to the sender set with
+/// `ContainerBuilder::with_event_sender`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerEvent {
+    /// The namespaces and the processes of the container are about to be set
+    /// up.
+    SettingUpNamespaces,
+    /// The init process of the container has been created.
+    InitProcessCreated { pid: Pid },
+    /// The state of the created container has been saved.
+    StateSaved { pid: Pid },
+}
+
+impl ContainerEvent {
+    /// Name of the phase of the creation the event marks.
+    pub fn phase(&self) -> &'static str {
+        match self {
+            Self::SettingUpNamespaces => "setting_up_namespaces",
+            Self::InitProcessCreated { .. } => "init_process_created",
+            Self::StateSaved { .. } => "state_saved",
+        }
+    }
+
+    /// Pid of the init process, if it exists at this point.
+    pub fn pid(&self) -> Option<Pid> {
+        match self {
+            Self::SettingUpNamespaces => None,
+            Self::InitProcessCreated { pid } | Self::StateSaved { pid } => Some(*pid),
+        }
+    }
+}
+
+/// Phase of the record of a failed creation in the event log.
+pub(super) const FAILED_PHASE: &str = "failed";
+
+/// A line of the event log set with `ContainerBuilder::with_event_log`.
+#[derive(Debug, Serialize)]
+struct EventRecord<'a> {
+    phase: &'a str,
+    timestamp: String,
+    pid: Option<i32>,
+    error: Option<&'a str>,
+}
+
+/// Sends event if there is a sender. A receiver that went away isn't an
+/// error, the creation of the container doesn't depend on it.
+pub(super) fn send_event(sender: Option<&Sender<ContainerEvent>>, event: ContainerEvent) {
+    if let Some(sender) = sender {
+        if sender.send(event).is_err() {
+            log::debug!("receiver of container events is gone, dropping {:?}", event);
+        }
+    }
+}
+
+/// Sends event like [`send_event`] and appends it to the event log if there
+/// is one.
+pub(super) fn record_event(
+    sender: Option<&Sender<ContainerEvent>>,
+    event_log: Option<&Path>,
+    event: ContainerEvent,
+) {
+    send_event(sender, event);
+    append_event_log(event_log, event.phase(), event.pid(), None);
+}
+
+/// Appends a record of phase as a line of JSON to the event log if there is
+/// one. The file is opened for every record, so the records written so far
+/// are kept even if the runtime crashes. A failure to write is logged, the
+/// creation of the container doesn't depend on the log.
+pub(super) fn append_event_log(
+    event_log: Option<&Path>,
+    phase: &str,
+    pid: Option<Pid>,
+    error: Option<&str>,
+) {
+    if let Some(path) = event_log {
+        let record = EventRecord {
+            phase,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            pid: pid.map(Pid::as_raw),
+            error,
+        };
+        if let Err(err) = write_record(path, &record) {
+            log::warn!(
+                "failed to append {:?} to event log {:?}: {}",
+                phase,
+                path,
+                err
+            );
+        }
+    }
+}
+
+fn write_record(path: &Path, record: &EventRecord) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_send_event() {
+        let (sender, receiver) = mpsc::channel();
+        let pid = Pid::from_raw(42);
+
+        send_event(Some(&sender), ContainerEvent::SettingUpNamespaces);
+        send_event(Some(&sender), ContainerEvent::InitProcessCreated { pid });
+        send_event(None, ContainerEvent::StateSaved { pid });
+        send_event(Some(&sender), ContainerEvent::StateSaved { pid });
+        drop(sender);
+
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            vec![
+                ContainerEvent::SettingUpNamespaces,
+                ContainerEvent::InitProcessCreated { pid },
+                ContainerEvent::StateSaved { pid },
+            ]
+        );
+
+        // sending without a receiver doesn't fail
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        send_event(Some(&sender), ContainerEvent::SettingUpNamespaces);
+    }
+
+    #[test]
+    fn test_event_log() -> anyhow::Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("events.ndjson");
+        let pid = Pid::from_raw(42);
+
+        // the events of a successful creation
+        record_event(None, Some(&path), ContainerEvent::SettingUpNamespaces);
+        record_event(
+            None,
+            Some(&path),
+            ContainerEvent::InitProcessCreated { pid },
+        );
+        record_event(None, Some(&path), ContainerEvent::StateSaved { pid });
+        // and the record of a failed one
+        append_event_log(Some(&path), FAILED_PHASE, None, Some("no linux in spec"));
+
+        let records = fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+        let got: Vec<_> = records
+            .iter()
+            .map(|r| (r["phase"].clone(), r["pid"].clone(), r["error"].clone()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (
+                    "setting_up_namespaces".into(),
+                    serde_json::Value::Null,
+                    serde_json::Value::Null
+                ),
+                (
+                    "init_process_created".into(),
+                    42.into(),
+                    serde_json::Value::Null
+                ),
+                ("state_saved".into(), 42.into(), serde_json::Value::Null),
+                (
+                    "failed".into(),
+                    serde_json::Value::Null,
+                    "no linux in spec".into()
+                ),
+            ]
+        );
+        for record in &records {
+            let timestamp = record["timestamp"].as_str().unwrap();
+            chrono::DateTime::parse_from_rfc3339(timestamp)?;
+        }
+
+        // an unwritable log doesn't fail
+        record_event(
+            None,
+            Some(&tmp_dir.path().join("missing/events.ndjson")),
+            ContainerEvent::SettingUpNamespaces,
+        );
+
+        Ok(())
+    }
+}
@@ -14,14 +14,47 @@ use crate::{
 
 use super::{
     builder::ContainerBuilder, builder_impl::ContainerBuilderImpl, Container, ContainerStatus,
+    CreateReport, RestoreOptions,
 };
 
+/// Selects the cgroup manager of a container
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupManagerKind {
+    /// Manage the cgroup through systemd
+    Systemd,
+    /// Manage the cgroup directly through the cgroup filesystem
+    CgroupFs,
+    /// Use systemd if requested with `with_systemd` or if the container is
+    /// rootless, the cgroup filesystem otherwise
+    Auto,
+}
+
+impl Default for CgroupManagerKind {
+    fn default() -> Self {
+        Self::Auto
+    }
+}
+
+impl CgroupManagerKind {
+    /// Whether systemd manages the cgroup, `use_systemd` and `rootless` are
+    /// only taken into account by Auto.
+    pub fn use_systemd(&self, use_systemd: bool, rootless: bool) -> bool {
+        match self {
+            CgroupManagerKind::Systemd => true,
+            CgroupManagerKind::CgroupFs => false,
+            CgroupManagerKind::Auto => use_systemd || rootless,
+        }
+    }
+}
+
 // Builder that can be used to configure the properties of a new container
 pub struct InitContainerBuilder<'a> {
     base: ContainerBuilder<'a>,
     bundle: PathBuf,
     use_systemd: bool,
+    cgroup_manager: CgroupManagerKind,
     detached: bool,
+    restore: Option<RestoreOptions>,
 }
 
 impl<'a> InitContainerBuilder<'a> {
@@ -32,7 +65,9 @@ impl<'a> InitContainerBuilder<'a> {
             base: builder,
             bundle,
             use_systemd: true,
+            cgroup_manager: CgroupManagerKind::Auto,
             detached: true,
+            restore: None,
         }
     }
 
@@ -42,13 +77,51 @@ impl<'a> InitContainerBuilder<'a> {
         self
     }
 
+    /// Sets the cgroup manager explicitly, e.g. to manage the cgroup of a
+    /// rootless container on a host without a systemd session through the
+    /// cgroup filesystem. Overrides `with_systemd` unless it is Auto.
+    pub fn with_cgroup_manager(mut self, kind: CgroupManagerKind) -> Self {
+        self.cgroup_manager = kind;
+        self
+    }
+
     pub fn with_detach(mut self, detached: bool) -> Self {
         self.detached = detached;
         self
     }
 
+    /// Restores the processes of the container from a checkpoint created by
+    /// [`Container::checkpoint`] instead of starting the process in the spec.
+    /// The restored container is running once it is built.
+    pub fn with_restore(mut self, opts: RestoreOptions) -> Self {
+        self.restore = Some(opts);
+        self
+    }
+
     /// Creates a new container
     pub fn build(self) -> Result<Container> {
+        self.build_with_report().map(|(container, _)| container)
+    }
+
+    /// Creates a new container like [`build`](Self::build), but also reports
+    /// how long the phases of the creation took.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::create_syscall;
+    ///
+    /// let (container, report) = ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     create_syscall().as_ref(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .build_with_report()
+    /// .expect("failed to create container");
+    /// println!("{} created in {:?}", container.id(), report.total);
+    /// ```
+    pub fn build_with_report(self) -> Result<(Container, CreateReport)> {
         let spec = self.load_spec().context("failed to load spec")?;
         let container_dir = self
             .create_container_dir()
@@ -57,8 +130,14 @@ impl<'a> InitContainerBuilder<'a> {
         let mut container = self
             .create_container_state(&container_dir)
             .context("failed to create container state")?;
+        let rootless = Rootless::new(&spec)?;
+        // the state records the cgroup manager later operations on the
+        // container, e.g. exec, have to use
         container
-            .set_systemd(self.use_systemd)
+            .set_systemd(
+                self.cgroup_manager
+                    .use_systemd(self.use_systemd, rootless.is_some()),
+            )
             .set_annotations(spec.annotations().clone());
 
         unistd::chdir(&container_dir)?;
@@ -78,34 +157,51 @@ impl<'a> InitContainerBuilder<'a> {
             None
         };
 
-        let rootless = Rootless::new(&spec)?;
         let config = YoukiConfig::from_spec(&spec, container.id(), rootless.is_some())?;
         config
             .save(&container_dir)
             .context("failed to save config")?;
 
         let mut builder_impl = ContainerBuilderImpl {
-            container_type: ContainerType::InitContainer,
+            container_type: if self.restore.is_some() {
+                ContainerType::RestoredContainer
+            } else {
+                ContainerType::InitContainer
+            },
             syscall: self.base.syscall,
             container_id: self.base.container_id,
             pid_file: self.base.pid_file,
+            pid_file_json: self.base.pid_file_json,
             console_socket: csocketfd,
             use_systemd: self.use_systemd,
+            cgroup_manager_kind: self.cgroup_manager,
             spec: &spec,
             rootfs,
             rootless,
             notify_path,
             container: Some(container.clone()),
+            joined_container: None,
             preserve_fds: self.base.preserve_fds,
             detached: self.detached,
             executor_manager: self.base.executor_manager,
+            event_sender: self.base.event_sender,
+            event_log: self.base.event_log,
+            seccomp_notify_path: self.base.seccomp_notify_path,
+            create_timeout: self.base.create_timeout,
+            extra_hook_env: self.base.extra_hook_env,
+            no_pivot: self.base.no_pivot,
+            log_file: self.base.log_file,
+            chown_notify_socket: self.base.chown_notify_socket,
+            scheduler: self.base.scheduler,
+            restore_options: self.restore,
+            rootfs_validator: self.base.rootfs_validator,
         };
 
-        builder_impl.create()?;
+        let report = builder_impl.create_with_report()?;
 
         container.refresh_state()?;
 
-        Ok(container)
+        Ok((container, report))
     }
 
     fn create_container_dir(&self) -> Result<PathBuf> {
@@ -13,11 +13,17 @@ mod container_delete;
 mod container_events;
 mod container_kill;
 mod container_pause;
+mod container_restore;
 mod container_resume;
 mod container_start;
+pub mod event;
 pub mod init_builder;
 pub mod state;
 pub mod tenant_builder;
+pub use builder_impl::CreateReport;
 pub use container::CheckpointOptions;
 pub use container::Container;
+pub use container::RestoreOptions;
+pub use event::ContainerEvent;
+pub use init_builder::CgroupManagerKind;
 pub use state::{ContainerProcessState, ContainerStatus, State};
@@ -24,7 +24,7 @@ use crate::process::args::ContainerType;
 use crate::{capabilities::CapabilityExt, container::builder_impl::ContainerBuilderImpl};
 use crate::{notify_socket::NotifySocket, rootless::Rootless, tty, utils};
 
-use super::{builder::ContainerBuilder, Container};
+use super::{builder::ContainerBuilder, CgroupManagerKind, Container};
 
 const NAMESPACE_TYPES: &[&str] = &["ipc", "uts", "net", "pid", "mnt", "cgroup"];
 const TENANT_NOTIFY: &str = "tenant-notify-";
@@ -128,6 +128,12 @@ impl<'a> TenantContainerBuilder<'a> {
 
         let (read_end, write_end) = pipe2(OFlag::O_CLOEXEC)?;
 
+        if self.base.rootfs_validator.is_some() {
+            log::warn!(
+                "ignoring the rootfs validator, the rootfs of a tenant container isn't set up anew"
+            );
+        }
+
         let mut builder_impl = ContainerBuilderImpl {
             container_type: ContainerType::TenantContainer {
                 exec_notify_fd: write_end,
@@ -135,16 +141,30 @@ impl<'a> TenantContainerBuilder<'a> {
             syscall: self.base.syscall,
             container_id: self.base.container_id,
             pid_file: self.base.pid_file,
+            pid_file_json: self.base.pid_file_json,
             console_socket: csocketfd,
             use_systemd,
+            cgroup_manager_kind: Self::cgroup_manager_kind(&container),
             spec: &spec,
             rootfs,
             rootless,
             notify_path: notify_path.clone(),
             container: None,
+            joined_container: Some(container.clone()),
             preserve_fds: self.base.preserve_fds,
             detached: self.detached,
             executor_manager: self.base.executor_manager,
+            event_sender: self.base.event_sender,
+            event_log: self.base.event_log,
+            seccomp_notify_path: self.base.seccomp_notify_path,
+            create_timeout: self.base.create_timeout,
+            extra_hook_env: self.base.extra_hook_env,
+            no_pivot: self.base.no_pivot,
+            log_file: self.base.log_file,
+            chown_notify_socket: self.base.chown_notify_socket,
+            scheduler: self.base.scheduler,
+            restore_options: None,
+            rootfs_validator: None,
         };
 
         let pid = builder_impl.create()?;
@@ -383,6 +403,17 @@ impl<'a> TenantContainerBuilder<'a> {
         false
     }
 
+    /// The cgroup manager the container was created with, which the state
+    /// records as whether systemd manages the cgroup.
+    fn cgroup_manager_kind(container: &Container) -> CgroupManagerKind {
+        match container.systemd() {
+            Some(true) => CgroupManagerKind::Systemd,
+            Some(false) => CgroupManagerKind::CgroupFs,
+            // state of a container created by an older version
+            None => CgroupManagerKind::Auto,
+        }
+    }
+
     fn setup_notify_listener(container_dir: &Path) -> Result<PathBuf> {
         let notify_name = Self::generate_name(container_dir, TENANT_NOTIFY);
         let socket_path = container_dir.join(notify_name);
@@ -415,3 +446,35 @@ impl<'a> TenantContainerBuilder<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::ContainerStatus;
+
+    #[test]
+    fn test_cgroup_manager_kind() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut container = Container::new(
+            "container",
+            ContainerStatus::Running,
+            None,
+            tmp_dir.path(),
+            tmp_dir.path(),
+        )?;
+
+        // a rootless container created with the cgroupfs manager must not be
+        // joined through systemd
+        container.set_systemd(false);
+        let kind = TenantContainerBuilder::cgroup_manager_kind(&container);
+        assert_eq!(kind, CgroupManagerKind::CgroupFs);
+        assert!(!kind.use_systemd(false, true));
+
+        container.set_systemd(true);
+        let kind = TenantContainerBuilder::cgroup_manager_kind(&container);
+        assert_eq!(kind, CgroupManagerKind::Systemd);
+        assert!(kind.use_systemd(false, true));
+
+        Ok(())
+    }
+}
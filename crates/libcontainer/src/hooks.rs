@@ -32,6 +32,16 @@ pub enum HookError {
 type Result<T> = std::result::Result<T, HookError>;
 
 pub fn run_hooks(hooks: Option<&Vec<Hook>>, container: Option<&Container>) -> Result<()> {
+    run_hooks_with_env(hooks, container, &[])
+}
+
+/// Runs the hooks like run_hooks, with extra_env added to the environment of
+/// every hook. The env of a hook in the spec takes precedence over extra_env.
+pub fn run_hooks_with_env(
+    hooks: Option<&Vec<Hook>>,
+    container: Option<&Container>,
+    extra_env: &[(String, String)],
+) -> Result<()> {
     let state = &(container.ok_or(HookError::MissingContainerState)?.state);
 
     if let Some(hooks) = hooks {
@@ -50,11 +60,10 @@ pub fn run_hooks(hooks: Option<&Vec<Hook>>, container: Option<&Container>) -> Re
                 hook_command.arg0(&hook.path().display().to_string())
             };
 
-            let envs: HashMap<String, String> = if let Some(env) = hook.env() {
-                utils::parse_env(env)
-            } else {
-                HashMap::new()
-            };
+            let mut envs: HashMap<String, String> = extra_env.iter().cloned().collect();
+            if let Some(env) = hook.env() {
+                envs.extend(utils::parse_env(env));
+            }
             log::debug!("run_hooks envs: {:?}", envs);
 
             let mut hook_process = hook_command
@@ -106,9 +115,11 @@ pub fn run_hooks(hooks: Option<&Vec<Hook>>, container: Option<&Container>) -> Re
                 match r.recv_timeout(time::Duration::from_secs(timeout_sec as u64)) {
                     Ok(res) => res,
                     Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
-                        // Kill the process. There is no need to further clean
-                        // up because we will be error out.
+                        // Kill the process and wait for the wait thread to
+                        // reap it, so no hook is left running once we error
+                        // out.
                         let _ = signal::kill(hook_process_pid, signal::Signal::SIGKILL);
+                        let _ = r.recv();
                         return Err(HookError::Timeout);
                     }
                     Err(_) => {
@@ -200,6 +211,39 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_run_hook_with_env() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let env_file = tmp.path().join("env");
+        let default_container: Container = Default::default();
+        let hook = HookBuilder::default()
+            .path("bash")
+            .args(vec![
+                String::from("bash"),
+                String::from("-c"),
+                format!("printenv > {}", env_file.display()),
+            ])
+            .env(vec![String::from("key=spec")])
+            .build()?;
+        let hooks = Some(vec![hook]);
+        let extra_env = vec![
+            (String::from("TRACE_ID"), String::from("abc")),
+            (String::from("key"), String::from("extra")),
+        ];
+
+        run_hooks_with_env(hooks.as_ref(), Some(&default_container), &extra_env)?;
+
+        let env = fs::read_to_string(&env_file)?;
+        let env: Vec<&str> = env.lines().collect();
+        assert!(env.contains(&"TRACE_ID=abc"), "{env:?}");
+        // the env of the spec wins
+        assert!(env.contains(&"key=spec"), "{env:?}");
+        assert!(!env.contains(&"key=extra"), "{env:?}");
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     // This will test executing hook with a timeout. Since the timeout is set in
@@ -232,4 +276,40 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    #[serial]
+    // A hook running longer than its timeout has to be killed rather than
+    // waited for until it exits by itself.
+    fn test_run_hook_timeout_kills_hook() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let pid_file = tmp_dir.path().join("hook.pid");
+        let default_container: Container = Default::default();
+        let hook = HookBuilder::default()
+            .path("/bin/sh")
+            .args(vec![
+                String::from("sh"),
+                String::from("-c"),
+                format!("echo $$ > {}; exec sleep 30", pid_file.display()),
+            ])
+            .timeout(1)
+            .build()?;
+        let hooks = Some(vec![hook]);
+
+        let start = time::Instant::now();
+        let res = run_hooks(hooks.as_ref(), Some(&default_container));
+        assert!(matches!(res, Err(HookError::Timeout)), "{res:?}");
+        assert!(start.elapsed() < time::Duration::from_secs(10));
+
+        // The hook has to be reaped already, not only signaled
+        let pid: i32 = fs::read_to_string(&pid_file)?.trim().parse()?;
+        let pid = Pid::from_raw(pid);
+        assert_eq!(
+            nix::sys::wait::waitpid(pid, Some(nix::sys::wait::WaitPidFlag::WNOHANG)),
+            Err(nix::errno::Errno::ECHILD)
+        );
+        assert!(!std::path::Path::new(&format!("/proc/{pid}")).exists());
+
+        Ok(())
+    }
 }
@@ -60,14 +60,17 @@ impl NotifyListener {
             source: e,
             path: workdir.to_owned(),
         })?;
-        let stream = UnixListener::bind(socket_name).map_err(|e| NotifyListenerError::Bind {
-            source: e,
-            name: socket_name.to_str().unwrap().to_owned(),
-        })?;
+        let stream = UnixListener::bind(socket_name);
+        // go back to the original cwd even if the bind failed, so callers
+        // can retry with the same relative paths.
         unistd::chdir(&cwd).map_err(|e| NotifyListenerError::Chdir {
             source: e,
             path: cwd,
         })?;
+        let stream = stream.map_err(|e| NotifyListenerError::Bind {
+            source: e,
+            name: socket_name.to_str().unwrap().to_owned(),
+        })?;
 
         Ok(Self { socket: stream })
     }
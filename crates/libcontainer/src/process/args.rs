@@ -1,8 +1,9 @@
 use libcgroups::common::AnyCgroupManager;
 use oci_spec::runtime::Spec;
 use std::os::unix::prelude::RawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::rootfs::RootfsValidator;
 use crate::rootless::Rootless;
 use crate::workload::ExecutorManager;
 use crate::{container::Container, notify_socket::NotifyListener, syscall::Syscall};
@@ -11,6 +12,7 @@ use crate::{container::Container, notify_socket::NotifyListener, syscall::Syscal
 pub enum ContainerType {
     InitContainer,
     TenantContainer { exec_notify_fd: RawFd },
+    RestoredContainer,
 }
 
 pub struct ContainerArgs<'a> {
@@ -38,4 +40,14 @@ pub struct ContainerArgs<'a> {
     pub detached: bool,
     /// Manage the functions that actually run on the container
     pub executor_manager: &'a ExecutorManager,
+    /// Checks the root filesystem of an init container before pivoting into it
+    pub rootfs_validator: Option<&'a dyn RootfsValidator>,
+    /// Unix socket the seccomp notify fd is sent to, overrides the listener
+    /// path of the spec
+    pub seccomp_notify_path: Option<&'a Path>,
+    /// Enter the rootfs with MS_MOVE and chroot instead of pivot_root
+    pub no_pivot: bool,
+    /// File descriptor of the log file passed to the container process, it
+    /// is the last of the preserved file descriptors
+    pub log_fd: Option<RawFd>,
 }
@@ -2,8 +2,12 @@ use super::args::{ContainerArgs, ContainerType};
 use crate::apparmor;
 use crate::syscall::{Syscall, SyscallError};
 use crate::{
-    capabilities, hooks, namespaces::Namespaces, process::channel, rootfs::RootFS,
-    rootless::Rootless, tty, utils,
+    capabilities, hooks,
+    namespaces::Namespaces,
+    process::channel,
+    rootfs::{enter_rootfs, RootFS},
+    rootless::Rootless,
+    tty, utils,
 };
 use anyhow::{bail, Context, Ok, Result};
 use nix::mount::MsFlags;
@@ -25,6 +29,10 @@ use crate::seccomp;
 #[cfg(not(feature = "libseccomp"))]
 use log::warn;
 
+/// Environment variable holding the file descriptor of the log file passed
+/// to the container process.
+pub const LOG_FD_ENV: &str = "YOUKI_LOG_FD";
+
 fn sysctl(kernel_params: &HashMap<String, String>) -> Result<()> {
     let sys = PathBuf::from("/proc/sys");
     for (kernel_param, value) in kernel_params {
@@ -120,6 +128,48 @@ fn masked_path(path: &Path, mount_label: &Option<String>, syscall: &dyn Syscall)
     Ok(())
 }
 
+// Makes the root read-only and applies the readonly and masked paths. A tenant
+// container joins the mount namespace of the init container, where all of this
+// was done already, so nothing is mounted again for it.
+fn restrict_mounts(
+    container_type: ContainerType,
+    spec: &Spec,
+    syscall: &dyn Syscall,
+) -> Result<()> {
+    if matches!(container_type, ContainerType::TenantContainer { .. }) {
+        return Ok(());
+    }
+    let linux = spec.linux().as_ref().context("no linux in spec")?;
+
+    if let Some(true) = spec.root().as_ref().map(|r| r.readonly().unwrap_or(false)) {
+        syscall.mount(
+            None,
+            Path::new("/"),
+            None,
+            MsFlags::MS_RDONLY | MsFlags::MS_REMOUNT | MsFlags::MS_BIND,
+            None,
+        )?
+    }
+
+    if let Some(paths) = linux.readonly_paths() {
+        // mount readonly path
+        for path in paths {
+            readonly_path(Path::new(path), syscall)
+                .with_context(|| format!("failed to set read only path {path:?}"))?;
+        }
+    }
+
+    if let Some(paths) = linux.masked_paths() {
+        // mount masked path
+        for path in paths {
+            masked_path(Path::new(path), linux.mount_label(), syscall)
+                .with_context(|| format!("failed to set masked path {path:?}"))?;
+        }
+    }
+
+    Ok(())
+}
+
 // Enter into rest of namespace. Note, we already entered into user and pid
 // namespace. We also have to enter into mount namespace last since
 // namespace may be bind to /proc path. The /proc path will need to be
@@ -182,6 +232,9 @@ pub fn container_init_process(
     let linux = spec.linux().as_ref().context("no linux in spec")?;
     let proc = spec.process().as_ref().context("no process in spec")?;
     let mut envs: Vec<String> = proc.env().as_ref().unwrap_or(&vec![]).clone();
+    if let Some(log_fd) = args.log_fd {
+        envs.push(format!("{LOG_FD_ENV}={log_fd}"));
+    }
     let rootfs_path = args.rootfs;
     let hooks = spec.hooks().as_ref();
     let container = args.container.as_ref();
@@ -214,18 +267,24 @@ pub fn container_init_process(
                 spec,
                 rootfs_path,
                 bind_service,
-                namespaces.get(LinuxNamespaceType::Cgroup).is_some(),
+                None,
+                container.map(|c| c.bundle().as_path()),
             )
             .with_context(|| "failed to prepare rootfs")?;
 
+        if let Some(validator) = args.rootfs_validator {
+            validator
+                .validate(rootfs_path, spec)
+                .context("failed to validate rootfs")?;
+        }
+
         // Entering into the rootfs jail. If mount namespace is specified, then
         // we use pivot_root, but if we are on the host mount namespace, we will
         // use simple chroot. Scary things will happen if you try to pivot_root
         // in the host mount namespace...
         if namespaces.get(LinuxNamespaceType::Mount).is_some() {
             // change the root of filesystem of the process to the rootfs
-            syscall
-                .pivot_rootfs(rootfs_path)
+            enter_rootfs(syscall, rootfs_path, args.no_pivot)
                 .with_context(|| format!("failed to pivot root to {rootfs_path:?}"))?;
         } else {
             syscall
@@ -250,15 +309,7 @@ pub fn container_init_process(
             .with_context(|| format!("failed to apply apparmor profile {profile}"))?;
     }
 
-    if let Some(true) = spec.root().as_ref().map(|r| r.readonly().unwrap_or(false)) {
-        syscall.mount(
-            None,
-            Path::new("/"),
-            None,
-            MsFlags::MS_RDONLY | MsFlags::MS_REMOUNT | MsFlags::MS_BIND,
-            None,
-        )?
-    }
+    restrict_mounts(args.container_type, spec, syscall)?;
 
     if let Some(umask) = proc.user().umask() {
         if let Some(mode) = Mode::from_bits(umask) {
@@ -268,22 +319,6 @@ pub fn container_init_process(
         }
     }
 
-    if let Some(paths) = linux.readonly_paths() {
-        // mount readonly path
-        for path in paths {
-            readonly_path(Path::new(path), syscall)
-                .with_context(|| format!("failed to set read only path {path:?}"))?;
-        }
-    }
-
-    if let Some(paths) = linux.masked_paths() {
-        // mount masked path
-        for path in paths {
-            masked_path(Path::new(path), linux.mount_label(), syscall)
-                .with_context(|| format!("failed to set masked path {path:?}"))?;
-        }
-    }
-
     let cwd = format!("{}", proc.cwd().display());
     let do_chdir = if cwd.is_empty() {
         false
@@ -571,6 +606,37 @@ mod tests {
     use serial_test::serial;
     use std::fs;
 
+    #[test]
+    fn test_restrict_mounts() -> Result<()> {
+        // the default spec has a read-only root, readonly and masked paths
+        let spec = Spec::default();
+
+        let syscall = create_syscall();
+        restrict_mounts(
+            ContainerType::TenantContainer { exec_notify_fd: -1 },
+            &spec,
+            syscall.as_ref(),
+        )?;
+        let got = syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert!(got.is_empty(), "{got:?}");
+
+        let syscall = create_syscall();
+        restrict_mounts(ContainerType::InitContainer, &spec, syscall.as_ref())?;
+        let got = syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got[0].target, PathBuf::from("/"));
+        assert!(got.len() > 1);
+
+        Ok(())
+    }
+
     #[test]
     fn test_readonly_path() -> Result<()> {
         let syscall = create_syscall();
@@ -159,7 +159,7 @@ pub fn container_intermediate_process(
     Ok(pid)
 }
 
-fn apply_cgroups<
+pub(crate) fn apply_cgroups<
     C: CgroupManager<Error = E> + ?Sized,
     E: std::error::Error + Send + Sync + 'static,
 >(
@@ -8,8 +8,11 @@ use crate::{
     utils,
 };
 use anyhow::{bail, Context, Result};
+use nix::sys::signal::{self, Signal};
 use nix::sys::wait::{waitpid, WaitStatus};
 use nix::unistd::Pid;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Instant;
 
 #[cfg(feature = "libseccomp")]
 use crate::seccomp;
@@ -21,9 +24,56 @@ use nix::{
 #[cfg(feature = "libseccomp")]
 use oci_spec::runtime;
 #[cfg(feature = "libseccomp")]
-use std::{io::IoSlice, path::Path};
+use std::{io::IoSlice, os::unix::io::RawFd, path::Path};
+
+/// Points in time the main process reached while creating the container,
+/// which are used to time the phases of the creation.
+#[derive(Debug, Clone, Copy)]
+pub struct MainProcessTimestamps {
+    /// The init process was forked into the namespaces of the container
+    pub intermediate_ready: Instant,
+    /// The init process set up the container, including the rootfs and mounts
+    pub init_ready: Instant,
+}
+
+/// Pids of the intermediate and init process, published as soon as the main
+/// process knows them, so another thread can kill the processes of a creation
+/// which hangs.
+#[derive(Debug, Default)]
+pub struct ContainerPids {
+    intermediate: AtomicI32,
+    init: AtomicI32,
+}
+
+impl ContainerPids {
+    pub(crate) fn set_intermediate(&self, pid: Option<Pid>) {
+        self.intermediate
+            .store(pid.map_or(0, Pid::as_raw), Ordering::SeqCst);
+    }
 
-pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bool)> {
+    pub(crate) fn set_init(&self, pid: Pid) {
+        self.init.store(pid.as_raw(), Ordering::SeqCst);
+    }
+
+    /// Kills the processes known so far with SIGKILL. Nothing else is
+    /// touched, other children of the runtime are left alone.
+    pub fn kill(&self) {
+        for pid in [&self.init, &self.intermediate] {
+            let pid = pid.load(Ordering::SeqCst);
+            if pid <= 0 {
+                continue;
+            }
+            if let Err(errno) = signal::kill(Pid::from_raw(pid), Signal::SIGKILL) {
+                log::warn!("failed to kill process {}: {}", pid, errno);
+            }
+        }
+    }
+}
+
+pub fn container_main_process(
+    container_args: &ContainerArgs,
+    pids: &ContainerPids,
+) -> Result<(Pid, bool, MainProcessTimestamps)> {
     // We use a set of channels to communicate between parent and child process.
     // Each channel is uni-directional. Because we will pass these channel to
     // cloned process, we have to be deligent about closing any unused channel.
@@ -43,6 +93,7 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
 
         Ok(0)
     })?;
+    pids.set_intermediate(Some(intermediate_pid));
 
     // Close down unused fds. The corresponding fds are duplicated to the
     // child process during fork.
@@ -71,6 +122,8 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
     // The intermediate process will send the init pid once it forks the init
     // process.  The intermediate process should exit after this point.
     let init_pid = main_receiver.wait_for_intermediate_ready()?;
+    pids.set_init(init_pid);
+    let intermediate_ready = Instant::now();
     let mut need_to_clean_up_intel_rdt_subdirectory = false;
 
     if let Some(linux) = container_args.spec.linux() {
@@ -90,8 +143,14 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
                     .clone(),
             };
             #[cfg(feature = "libseccomp")]
-            sync_seccomp(seccomp, &state, init_sender, main_receiver)
-                .context("failed to sync seccomp with init")?;
+            sync_seccomp(
+                seccomp,
+                container_args.seccomp_notify_path,
+                &state,
+                init_sender,
+                main_receiver,
+            )
+            .context("failed to sync seccomp with init")?;
         }
         if let Some(intel_rdt) = linux.intel_rdt() {
             let container_id = container_args
@@ -112,6 +171,10 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
     main_receiver
         .wait_for_init_ready()
         .context("failed to wait for init ready")?;
+    let timestamps = MainProcessTimestamps {
+        intermediate_ready,
+        init_ready: Instant::now(),
+    };
 
     log::debug!("init pid is {:?}", init_pid);
 
@@ -135,13 +198,30 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
         }
         Err(err) => bail!("failed to wait for intermediate process: {err}"),
     };
-
-    Ok((init_pid, need_to_clean_up_intel_rdt_subdirectory))
+    // the pid may be reused once the process is reaped
+    pids.set_intermediate(None);
+
+    Ok((
+        init_pid,
+        need_to_clean_up_intel_rdt_subdirectory,
+        timestamps,
+    ))
 }
 
+/// Forwards the seccomp notify fd of the init process to the seccomp agent.
+/// The handshake goes as follows:
+/// 1. The init process loads the seccomp filter and sends the notify fd to
+///    the main process.
+/// 2. The main process connects to the listener socket, which is
+///    notify_path if set and the listener path of the spec otherwise.
+/// 3. It sends the encoded container process state together with the fd as
+///    an SCM_RIGHTS message and closes the connection.
+/// 4. It tells the init process that the fd was forwarded, and the init
+///    process continues.
 #[cfg(feature = "libseccomp")]
 fn sync_seccomp(
     seccomp: &runtime::LinuxSeccomp,
+    notify_path: Option<&Path>,
     state: &ContainerProcessState,
     init_sender: &mut channel::InitSender,
     main_receiver: &mut channel::MainReceiver,
@@ -149,9 +229,8 @@ fn sync_seccomp(
     if seccomp::is_notify(seccomp) {
         log::debug!("main process waiting for sync seccomp");
         let seccomp_fd = main_receiver.wait_for_seccomp_request()?;
-        let listener_path = seccomp
-            .listener_path()
-            .as_ref()
+        let listener_path = notify_path
+            .or(seccomp.listener_path().as_deref())
             .context("notify will require seccomp listener path to be set")?;
         let encoded_state =
             serde_json::to_vec(state).context("failed to encode container process state")?;
@@ -183,6 +262,15 @@ fn sync_seccomp_send_msg(listener_path: &Path, msg: &[u8], fd: i32) -> Result<()
     socket::connect(socket, &unix_addr).with_context(|| {
         format!("failed to connect to seccomp notify listerner path: {listener_path:?}")
     })?;
+    let res = send_seccomp_fd(socket, msg, fd);
+    // The spec requires the listener socket to be closed immediately after sending.
+    let _ = unistd::close(socket);
+
+    res
+}
+
+#[cfg(feature = "libseccomp")]
+fn send_seccomp_fd(socket: RawFd, msg: &[u8], fd: RawFd) -> Result<()> {
     // We have to use sendmsg here because the spec requires us to send seccomp notify fds through
     // SCM_RIGHTS message.
     // Ref: https://man7.org/linux/man-pages/man3/sendmsg.3p.html
@@ -192,8 +280,6 @@ fn sync_seccomp_send_msg(listener_path: &Path, msg: &[u8], fd: i32) -> Result<()
     let cmsgs = socket::ControlMessage::ScmRights(&fds);
     socket::sendmsg::<UnixAddr>(socket, &iov, &[cmsgs], socket::MsgFlags::empty(), None)
         .context("failed to write container state to seccomp listener")?;
-    // The spec requires the listener socket to be closed immediately after sending.
-    let _ = unistd::close(socket);
 
     Ok(())
 }
@@ -380,6 +466,7 @@ mod tests {
                         .unwrap()])
                     .build()
                     .unwrap(),
+                None,
                 &state,
                 &mut init_sender,
                 &mut main_receiver,
@@ -401,4 +488,47 @@ mod tests {
         assert!(th.join().is_ok());
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "libseccomp")]
+    fn test_send_seccomp_fd() -> Result<()> {
+        use std::io::IoSliceMut;
+
+        let (sender, receiver) = socket::socketpair(
+            socket::AddressFamily::Unix,
+            socket::SockType::Stream,
+            None,
+            socket::SockFlag::empty(),
+        )?;
+        let (read_end, write_end) = unistd::pipe()?;
+
+        send_seccomp_fd(sender, b"state", write_end)?;
+
+        let mut buf = [0u8; 16];
+        let mut iov = [IoSliceMut::new(&mut buf)];
+        let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+        let msg = socket::recvmsg::<UnixAddr>(
+            receiver,
+            &mut iov,
+            Some(&mut cmsg_buf),
+            socket::MsgFlags::empty(),
+        )?;
+        let received_fd = match msg.cmsgs().next() {
+            Some(socket::ControlMessageOwned::ScmRights(fds)) => fds[0],
+            other => bail!("expected SCM_RIGHTS, got {:?}", other),
+        };
+        let len = msg.bytes;
+        assert_eq!(&buf[..len], b"state");
+
+        // the received fd refers to the same pipe as the sent one
+        unistd::write(received_fd, b"x")?;
+        let mut byte = [0u8; 1];
+        unistd::read(read_end, &mut byte)?;
+        assert_eq!(&byte, b"x");
+
+        for fd in [sender, receiver, read_end, write_end, received_fd] {
+            unistd::close(fd)?;
+        }
+        Ok(())
+    }
 }
@@ -11,6 +11,7 @@ pub mod container_main_process;
 pub mod fork;
 pub mod intel_rdt;
 pub mod message;
+pub mod scheduler;
 
 type Result<T> = std::result::Result<T, ProcessError>;
 
@@ -0,0 +1,123 @@
+//! Scheduling of the container process, set by the runtime once the process
+//! exists, e.g. a real-time policy for latency-sensitive workloads.
+use crate::syscall::Syscall;
+use anyhow::{Context, Result};
+use nix::unistd::Pid;
+
+/// Scheduling policy of a process, see sched(7).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// SCHED_OTHER, the default time-sharing policy
+    Other,
+    /// SCHED_FIFO, real-time first-in first-out
+    Fifo,
+    /// SCHED_RR, real-time round-robin
+    RoundRobin,
+    /// SCHED_BATCH, for CPU intensive batch processes
+    Batch,
+    /// SCHED_IDLE, for very low priority background processes
+    Idle,
+}
+
+impl SchedPolicy {
+    fn as_raw(self) -> i32 {
+        match self {
+            Self::Other => libc::SCHED_OTHER,
+            Self::Fifo => libc::SCHED_FIFO,
+            Self::RoundRobin => libc::SCHED_RR,
+            Self::Batch => libc::SCHED_BATCH,
+            Self::Idle => libc::SCHED_IDLE,
+        }
+    }
+}
+
+/// Scheduling the container process is set to before it is started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulerSettings {
+    /// Policy the process is scheduled with
+    pub policy: SchedPolicy,
+    /// Static priority of the real-time policies, 1 to 99. It has to be 0
+    /// for the other policies.
+    pub priority: i32,
+    /// Nice value of the process, -20 to 19, which only matters for the
+    /// policies which aren't real-time
+    pub nice: Option<i32>,
+}
+
+/// Sets the scheduling of the process with pid to settings.
+pub fn apply_scheduler(
+    syscall: &dyn Syscall,
+    pid: Pid,
+    settings: &SchedulerSettings,
+) -> Result<()> {
+    syscall
+        .sched_setscheduler(pid, settings.policy.as_raw(), settings.priority)
+        .with_context(|| {
+            format!(
+                "failed to set scheduling policy {:?} with priority {} of {}",
+                settings.policy, settings.priority, pid
+            )
+        })?;
+
+    if let Some(nice) = settings.nice {
+        syscall
+            .set_priority(pid, nice)
+            .with_context(|| format!("failed to set nice value {nice} of {pid}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syscall::{
+        syscall::create_syscall,
+        test::{ArgName, TestHelperSyscall},
+        SyscallError,
+    };
+
+    #[test]
+    fn test_apply_scheduler() -> Result<()> {
+        let syscall = create_syscall();
+        let mocks = syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        let pid = Pid::from_raw(42);
+
+        let fifo = SchedulerSettings {
+            policy: SchedPolicy::Fifo,
+            priority: 50,
+            nice: None,
+        };
+        apply_scheduler(syscall.as_ref(), pid, &fifo)?;
+        assert_eq!(
+            mocks.get_sched_setscheduler_args(),
+            vec![(pid, libc::SCHED_FIFO, 50)]
+        );
+        assert!(mocks.get_set_priority_args().is_empty());
+
+        let batch = SchedulerSettings {
+            policy: SchedPolicy::Batch,
+            priority: 0,
+            nice: Some(10),
+        };
+        apply_scheduler(syscall.as_ref(), pid, &batch)?;
+        assert_eq!(
+            mocks.get_sched_setscheduler_args()[1],
+            (pid, libc::SCHED_BATCH, 0)
+        );
+        assert_eq!(mocks.get_set_priority_args(), vec![(pid, 10)]);
+
+        mocks.set_ret_err(ArgName::SchedSetScheduler, || {
+            Err(SyscallError::SchedSetScheduler {
+                source: nix::errno::Errno::EPERM,
+            })
+        });
+        let err = apply_scheduler(syscall.as_ref(), pid, &fifo).unwrap_err();
+        assert!(err.to_string().contains("Fifo"), "{err}");
+
+        Ok(())
+    }
+}
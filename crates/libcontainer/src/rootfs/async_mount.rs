@@ -0,0 +1,92 @@
+//! Async wrappers around [`Mount`](super::Mount), for callers creating
+//! containers from an async runtime.
+//!
+//! Mounting is blocking, so the work runs on the blocking thread pool of
+//! tokio through `spawn_blocking` instead of stalling the reactor. A
+//! [`Mount`](super::Mount) holds a syscall implementation which isn't `Send`,
+//! so a new one is created on the blocking thread and the options are passed
+//! as owned values. The sync API is unaffected.
+use super::mount::{Mount, MountError, MountOptions};
+use oci_spec::runtime::Mount as SpecMount;
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, MountError>;
+
+/// Owned version of [`MountOptions`], which can be moved to another thread.
+#[derive(Debug, Clone, Default)]
+pub struct AsyncMountOptions {
+    pub root: PathBuf,
+    pub label: Option<String>,
+    pub cgroup_ns: bool,
+    pub cgroup_tmpfs_size: Option<String>,
+    pub bundle_dir: Option<PathBuf>,
+    pub cgroup_root: Option<PathBuf>,
+    pub skip_cgroup_mount: bool,
+    pub slow_mount_threshold: Option<Duration>,
+    pub sync_after_mounts: bool,
+    pub type_defaults: Option<HashMap<String, Vec<String>>>,
+    pub allowed_mount_types: Vec<String>,
+    pub denied_mount_types: Vec<String>,
+    pub root_fd: Option<RawFd>,
+    pub max_rbind_submounts: Option<usize>,
+}
+
+impl AsyncMountOptions {
+    fn as_mount_options(&self) -> MountOptions {
+        MountOptions {
+            root: &self.root,
+            label: self.label.as_deref(),
+            cgroup_ns: self.cgroup_ns,
+            cgroup_tmpfs_size: self.cgroup_tmpfs_size.clone(),
+            bundle_dir: self.bundle_dir.as_deref(),
+            cgroup_root: self.cgroup_root.as_deref(),
+            skip_cgroup_mount: self.skip_cgroup_mount,
+            slow_mount_threshold: self.slow_mount_threshold,
+            sync_after_mounts: self.sync_after_mounts,
+            type_defaults: self.type_defaults.as_ref(),
+            allowed_mount_types: &self.allowed_mount_types,
+            denied_mount_types: &self.denied_mount_types,
+            root_fd: self.root_fd,
+            max_rbind_submounts: self.max_rbind_submounts,
+        }
+    }
+}
+
+/// Sets up mount like [`Mount::setup_mount`] on the blocking thread pool.
+/// Has to be called from within a tokio runtime.
+pub async fn setup_mount(mount: SpecMount, options: AsyncMountOptions) -> Result<()> {
+    tokio::task::spawn_blocking(move || {
+        Mount::new().setup_mount(&mount, &options.as_mount_options())
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use oci_spec::runtime::MountBuilder as SpecMountBuilder;
+
+    #[test]
+    fn test_setup_mount() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/tmp"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .build()?;
+        let options = AsyncMountOptions {
+            root: tmp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(setup_mount(mount, options))?;
+
+        assert!(tmp_dir.path().join("tmp").is_dir());
+        Ok(())
+    }
+}
@@ -9,6 +9,16 @@ pub mod device;
 pub use device::Device;
 
 pub(super) mod mount;
+pub use mount::{
+    check_duplicate_destinations, check_readonly_order, container_mounts, enter_rootfs, Mount,
+    MountError, MountObserver, MountOptions, DEFAULT_UNLABELED_FS,
+};
 pub(super) mod symlink;
 
+#[cfg(feature = "async")]
+pub mod async_mount;
+
 pub mod utils;
+
+pub mod validator;
+pub use validator::RootfsValidator;
@@ -1,37 +1,360 @@
 #[cfg(feature = "v1")]
 use super::symlink::Symlink;
-use super::utils::{find_parent_mount, parse_mount, MountOptionConfig};
+use super::utils::{
+    find_parent_mount, parse_mount, parse_propagation, MountOptionConfig, MountPropagation,
+};
 use crate::{
     syscall::{linux, syscall::create_syscall, Syscall, SyscallError},
     utils,
-    utils::PathBufExt,
+    utils::{PathBufExt, PathBufExtError, SecureJoinError},
 };
-#[cfg(feature = "v2")]
-use anyhow::anyhow;
-use anyhow::{bail, Context, Result};
-use libcgroups::common::CgroupSetup::{Hybrid, Legacy, Unified};
+use libcgroups::common::CgroupSetup::{self, Hybrid, Legacy, Unified};
 #[cfg(feature = "v1")]
 use libcgroups::common::DEFAULT_CGROUP_ROOT;
-use nix::{dir::Dir, errno::Errno, fcntl::OFlag, mount::MsFlags, sys::stat::Mode};
-use oci_spec::runtime::{Mount as SpecMount, MountBuilder as SpecMountBuilder};
+use nix::{
+    errno::Errno,
+    fcntl::{self, OFlag},
+    mount::{MntFlags, MsFlags},
+    sys::stat::Mode,
+    unistd,
+};
+use oci_spec::runtime::{LinuxIdMapping, Mount as SpecMount, MountBuilder as SpecMountBuilder};
 use procfs::process::{MountInfo, MountOptFields, Process};
-use std::fs::{canonicalize, create_dir_all, OpenOptions};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, canonicalize, create_dir_all, DirBuilder, OpenOptions};
 use std::mem;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[cfg(feature = "v1")]
-use std::{borrow::Cow, collections::HashMap};
+/// Target of the log records about mounts, e.g. to enable their trace logs
+/// with `RUST_LOG=libcontainer::mount=trace`.
+pub const LOG_TARGET: &str = "libcontainer::mount";
+
+#[derive(Debug, thiserror::Error)]
+pub enum MountError {
+    #[error("failed to securely join {path:?} with {root:?}")]
+    SecureJoin {
+        source: SecureJoinError,
+        root: PathBuf,
+        path: PathBuf,
+    },
+    #[error("failed to join {path:?} with {root:?}")]
+    JoinSafely {
+        source: PathBufExtError,
+        root: PathBuf,
+        path: PathBuf,
+    },
+    #[error(
+        "mount destination {dest:?} resolves to {resolved:?} which is outside of rootfs {rootfs:?}"
+    )]
+    EscapesRootfs {
+        dest: PathBuf,
+        resolved: PathBuf,
+        rootfs: PathBuf,
+    },
+    #[error("syscall failed")]
+    Syscall(#[from] SyscallError),
+    #[error("unsupported cgroup setup: {0}")]
+    UnsupportedCgroup(String),
+    #[error("failed to determine cgroup setup")]
+    CgroupSetup(#[from] libcgroups::common::GetCgroupSetupError),
+    #[cfg(feature = "v1")]
+    #[error("failed to get cgroup v1 subsystem mount points")]
+    V1MountPoint(#[from] libcgroups::v1::util::V1MountPointError),
+    #[cfg(feature = "v2")]
+    #[error("failed to get cgroup v2 unified mount point")]
+    V2Util(#[from] libcgroups::v2::util::V2UtilError),
+    #[error("failed to find unified process cgroup")]
+    UnifiedCgroupNotFound,
+    #[error("failed to create comount symlinks for {subsystem}: {msg}")]
+    ComountSymlink { subsystem: String, msg: String },
+    #[error("no source in mount spec")]
+    MissingSource,
+    #[error("couldn't find parent mount of {0:?}")]
+    ParentMountNotFound(PathBuf),
+    #[error("failed to change the propagation of {mount_point:?}, the parent mount of the rootfs, to MS_PRIVATE")]
+    ParentMountPrivate {
+        source: SyscallError,
+        mount_point: PathBuf,
+    },
+    #[error("failed to build mount spec")]
+    SpecBuild(#[from] oci_spec::OciSpecError),
+    #[error("failed to get proc state")]
+    Procfs(#[from] procfs::ProcError),
+    #[error("io error on {path:?}")]
+    Io {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("failed to open {path:?}")]
+    Open { source: nix::Error, path: PathBuf },
+    #[error("invalid overlay mount options: {0}")]
+    InvalidOverlay(String),
+    #[error("idmapped mount of {0:?} requires both uid and gid mappings")]
+    IncompleteIdMappings(PathBuf),
+    #[error("idmapped mounts are not supported for {dest:?}, the kernel or filesystem lacks MOUNT_ATTR_IDMAP support")]
+    IdmapUnsupported {
+        source: syscalls::Errno,
+        dest: PathBuf,
+    },
+    #[error("failed to set up mount #{index} of {src:?} at {dest:?}")]
+    Setup {
+        index: usize,
+        src: Option<PathBuf>,
+        dest: PathBuf,
+        #[source]
+        err: Box<MountError>,
+    },
+    #[cfg(feature = "async")]
+    #[error("blocking mount task failed")]
+    Join(#[from] tokio::task::JoinError),
+    #[error("writable mount {writable:?} is hidden by the later read-only mount {readonly:?}, mount {readonly:?} before {writable:?}")]
+    ShadowedByReadonly {
+        writable: PathBuf,
+        readonly: PathBuf,
+    },
+    #[error("failed to remount {dest:?} with {flags:?}: the source mount has locked flags (e.g. it was mounted in a parent user namespace) that a remount can't change, set the flags when mounting the source instead")]
+    LockedFlags {
+        source: Errno,
+        dest: PathBuf,
+        flags: MsFlags,
+    },
+    #[error("bind mount source {0:?} does not exist, bind sources must exist on the host before the container is created")]
+    MissingBindSource(PathBuf),
+    #[error("mount destinations {0:?} are used by more than one mount")]
+    DuplicateDestinations(Vec<PathBuf>),
+    #[error("failed to read the mount table from {path:?}, /proc has to be mounted to set up the rootfs")]
+    MountInfo {
+        source: procfs::ProcError,
+        path: PathBuf,
+    },
+    #[error(
+        "mount data of {dest:?} is {len} bytes long, the kernel only accepts up to {max} bytes"
+    )]
+    DataTooLong {
+        dest: PathBuf,
+        len: usize,
+        max: usize,
+    },
+    #[error(
+        "mount destination {0:?} is a symlink, which is not followed when creating the mount point"
+    )]
+    DestinationIsSymlink(PathBuf),
+    #[error("overlay mount data of {dest:?} is {len} bytes long, the kernel only accepts up to {max} bytes, use shorter lowerdir paths (e.g. relative to the rootfs) or fewer layers")]
+    OverlayDataTooLong {
+        dest: PathBuf,
+        len: usize,
+        max: usize,
+    },
+    #[error("mount destination {0:?} resolves to the rootfs, mounting over the container root is not allowed")]
+    DestinationIsRootfs(PathBuf),
+    #[error("failed to open {dest:?} beneath the rootfs fd")]
+    OpenBeneath {
+        source: syscalls::Errno,
+        dest: PathBuf,
+    },
+    #[error("mount type {typ:?} of {dest:?} is not allowed")]
+    TypeNotAllowed { typ: String, dest: PathBuf },
+    #[error("mount option {option:?} of {dest:?} is only supported by proc mounts")]
+    ProcOnlyOption { option: String, dest: PathBuf },
+    #[error(
+        "recursive bind mount source {src:?} has {count} submounts, more than the limit of {max}"
+    )]
+    TooManySubmounts {
+        src: PathBuf,
+        count: usize,
+        max: usize,
+    },
+    #[error("mount of {0:?} requests a cgroup2 filesystem, but the host has a Legacy cgroup setup without a unified hierarchy, change the type of the mount to cgroup to mount the cgroup v1 hierarchies of the host")]
+    Cgroup2OnLegacy(PathBuf),
+    #[error("mount of {0:?} requests the cgroup v1 hierarchies, but the host has a Unified cgroup setup without them, change the type of the mount to cgroup2")]
+    CgroupOnUnified(PathBuf),
+}
+
+type Result<T> = std::result::Result<T, MountError>;
+
+/// Mount data options accepted by the cgroup2 filesystem.
+#[cfg(feature = "v2")]
+const CGROUP2_MOUNT_OPTIONS: &[&str] = &[
+    "nsdelegate",
+    "favordynmods",
+    "memory_localevents",
+    "memory_recursiveprot",
+    "memory_hugetlb_accounting",
+];
+
+/// Filters data down to the options accepted by the cgroup2 filesystem.
+/// Controllers are enabled through cgroup.subtree_control after the mount, so
+/// controller names or interface files such as io.latency and io.cost.qos in
+/// the spec options are dropped instead of failing the mount. Neither is the
+/// subtree_control of the mounted hierarchy touched here.
+#[cfg(feature = "v2")]
+fn cgroup2_mount_data(data: &str) -> String {
+    let mut options: Vec<&str> = Vec::new();
+    for option in data.split(',') {
+        if CGROUP2_MOUNT_OPTIONS.contains(&option) && !options.contains(&option) {
+            options.push(option);
+        }
+    }
+    options.join(",")
+}
 
-#[derive(Debug)]
+/// Files of a cgroup which have to be writable by a user the cgroup is
+/// delegated to, e.g. by systemd with Delegate=yes.
+#[cfg(feature = "v2")]
+const CGROUP_DELEGATION_FILES: &[&str] =
+    &["cgroup.procs", "cgroup.subtree_control", "cgroup.threads"];
+
+/// Lists the delegation files of cgroup which the permission bits don't
+/// allow uid and gid to write. Missing files are skipped.
+#[cfg(feature = "v2")]
+fn undelegated_cgroup_files(cgroup: &Path, uid: unistd::Uid, gid: unistd::Gid) -> Vec<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    CGROUP_DELEGATION_FILES
+        .iter()
+        .map(|file| cgroup.join(file))
+        .filter(|file| match fs::metadata(file) {
+            Ok(metadata) => {
+                let write_bit = if metadata.uid() == uid.as_raw() {
+                    0o200
+                } else if metadata.gid() == gid.as_raw() {
+                    0o020
+                } else {
+                    0o002
+                };
+                metadata.mode() & write_bit == 0
+            }
+            Err(_) => false,
+        })
+        .collect()
+}
+
+/// Filesystems which aren't backed by a device, a mount of them may omit the
+/// source.
+const SOURCELESS_FS: &[&str] = &["tmpfs", "proc", "sysfs", "mqueue", "devpts"];
+
+/// Time to wait before retrying a mount which failed with EBUSY.
+const MOUNT_BUSY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Mount options only the proc filesystem accepts.
+const PROC_ONLY_OPTIONS: &[&str] = &["hidepid"];
+
+/// Mount options setting the SELinux context of a mount.
+const SELINUX_CONTEXT_OPTIONS: &[&str] = &["context", "fscontext", "defcontext", "rootcontext"];
+
+#[derive(Debug, Clone)]
 pub struct MountOptions<'a> {
     pub root: &'a Path,
+    /// SELinux label of the mounts. It is passed as `context` unless it is
+    /// prefixed with another context option, e.g. `fscontext=<label>`.
     pub label: Option<&'a str>,
     pub cgroup_ns: bool,
+    /// Size limit of the tmpfs holding the cgroup v1 hierarchies, e.g. `1m`.
+    /// The tmpfs is not limited if unset.
+    pub cgroup_tmpfs_size: Option<String>,
+    /// Directory relative bind mount sources are resolved against, usually
+    /// the bundle directory. Relative sources are resolved against the
+    /// current working directory if unset.
+    pub bundle_dir: Option<&'a Path>,
+    /// Directory the cgroup v1 hierarchies are mounted under on the host. It
+    /// is detected from the mounted hierarchies if unset, falling back to
+    /// /sys/fs/cgroup.
+    pub cgroup_root: Option<&'a Path>,
+    /// Skip cgroup and cgroup2 mounts, for containers managing their own
+    /// cgroups or hosts without cgroups.
+    pub skip_cgroup_mount: bool,
+    /// Log a warning for every mount which takes longer than this to set up,
+    /// e.g. a bind mount of an unresponsive NFS share.
+    pub slow_mount_threshold: Option<Duration>,
+    /// Flush the filesystems with sync once all mounts are set up, e.g. for
+    /// crash consistency testing.
+    pub sync_after_mounts: bool,
+    /// Options added to every mount of a filesystem type, e.g. nosuid for
+    /// all tmpfs mounts. The options of the mount come after them, so they
+    /// take precedence.
+    pub type_defaults: Option<&'a HashMap<String, Vec<String>>>,
+    /// Filesystem types mounts may have, any type is allowed if empty.
+    pub allowed_mount_types: &'a [String],
+    /// Filesystem types mounts must not have, e.g. fuse. This takes
+    /// precedence over allowed_mount_types.
+    pub denied_mount_types: &'a [String],
+    /// O_PATH fd of the rootfs opened by the caller, which is used instead of
+    /// root so the rootfs can't be swapped after it was opened. Mount points
+    /// are created through /proc/self/fd/<root_fd> and opened beneath it
+    /// with openat2(RESOLVE_BENEATH) before mounting over them. Requires
+    /// Linux 5.6 or later.
+    pub root_fd: Option<RawFd>,
+    /// Maximum number of submounts the source of a recursive bind mount may
+    /// have, to reject specs binding huge mount trees which every recursive
+    /// operation, like mount_setattr, has to walk. Unlimited if unset.
+    pub max_rbind_submounts: Option<usize>,
+}
+
+impl<'a> MountOptions<'a> {
+    /// Options for mounts into root with everything else unset or disabled,
+    /// the other fields can be set with struct update syntax.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::rootfs::MountOptions;
+    /// # use std::path::Path;
+    /// let options = MountOptions {
+    ///     skip_cgroup_mount: true,
+    ///     ..MountOptions::new(Path::new("/run/rootfs"))
+    /// };
+    /// ```
+    pub fn new(root: &'a Path) -> Self {
+        Self {
+            root,
+            label: None,
+            cgroup_ns: false,
+            cgroup_tmpfs_size: None,
+            bundle_dir: None,
+            cgroup_root: None,
+            skip_cgroup_mount: false,
+            slow_mount_threshold: None,
+            sync_after_mounts: false,
+            type_defaults: None,
+            allowed_mount_types: &[],
+            denied_mount_types: &[],
+            root_fd: None,
+            max_rbind_submounts: None,
+        }
+    }
+}
+
+/// Receives notifications around every mount performed by [`Mount`], e.g. to
+/// build a timeline of mount operations when profiling container creation.
+pub trait MountObserver {
+    /// Called right before the mount is set up.
+    fn on_mount_start(&self, _mount: &SpecMount) {}
+    /// Called once the mount is set up, with the time it took and the result.
+    fn on_mount_end(&self, _mount: &SpecMount, _elapsed: Duration, _result: &Result<()>) {}
+    /// Called with the names of the cgroup v1 subsystems mounted for a cgroup
+    /// mount. Controllers without a hierarchy on the host are missing.
+    fn on_cgroup_v1_mounted(&self, _mount: &SpecMount, _subsystems: &[String]) {}
 }
 
+struct NoopMountObserver;
+
+impl MountObserver for NoopMountObserver {}
+
+/// Filesystems which don't accept a per-mount SELinux context, so the mount
+/// label isn't applied to them by default.
+pub const DEFAULT_UNLABELED_FS: &[&str] = &["proc", "sysfs", "mqueue", "cgroup", "cgroup2"];
+
 pub struct Mount {
     syscall: Box<dyn Syscall>,
+    observer: Box<dyn MountObserver>,
+    dry_run: bool,
+    unlabeled_fs: Vec<String>,
 }
 
 impl Default for Mount {
@@ -44,114 +367,363 @@ impl Mount {
     pub fn new() -> Mount {
         Mount {
             syscall: create_syscall(),
+            observer: Box::new(NoopMountObserver),
+            dry_run: false,
+            unlabeled_fs: default_unlabeled_fs(),
+        }
+    }
+
+    /// Creates a Mount which reports every mount it performs to the observer.
+    pub fn with_observer(observer: Box<dyn MountObserver>) -> Mount {
+        Mount {
+            syscall: create_syscall(),
+            observer,
+            dry_run: false,
+            unlabeled_fs: default_unlabeled_fs(),
+        }
+    }
+
+    /// Creates a Mount which performs its system calls through syscall, e.g. to
+    /// drive setup_mount against a recording implementation.
+    pub fn with_syscall(syscall: Box<dyn Syscall>) -> Mount {
+        Mount {
+            syscall,
+            observer: Box::new(NoopMountObserver),
+            dry_run: false,
+            unlabeled_fs: default_unlabeled_fs(),
+        }
+    }
+
+    /// Creates a Mount which only logs the mounts it would perform. Neither
+    /// mount syscalls are issued nor directories created, so the translation
+    /// of a spec into mounts can be checked without privileges.
+    pub fn new_dry_run() -> Mount {
+        Mount {
+            syscall: create_syscall(),
+            observer: Box::new(NoopMountObserver),
+            dry_run: true,
+            unlabeled_fs: default_unlabeled_fs(),
+        }
+    }
+
+    /// Sets the filesystem types the mount label is not applied to, replacing
+    /// DEFAULT_UNLABELED_FS.
+    pub fn with_unlabeled_fs(mut self, fs_types: &[&str]) -> Mount {
+        self.unlabeled_fs = fs_types.iter().map(|fs| fs.to_string()).collect();
+        self
+    }
+
+    /// Set up the given mounts in order and return their destinations on the
+    /// host side, i.e. resolved inside the rootfs.
+    pub fn setup_all_mounts(
+        &self,
+        mounts: &[SpecMount],
+        options: &MountOptions,
+    ) -> Result<Vec<PathBuf>> {
+        check_duplicate_destinations(mounts)?;
+        check_readonly_order(mounts)?;
+
+        let mut destinations = Vec::with_capacity(mounts.len());
+        for (index, mount) in mounts.iter().enumerate() {
+            self.setup_indexed_mount(index, mount, options)?;
+            destinations.push(join_rootfs(options.root, mount.destination())?);
+        }
+
+        if options.sync_after_mounts {
+            self.syscall.sync()?;
+        }
+
+        Ok(destinations)
+    }
+
+    /// Like [`Mount::setup_all_mounts`], but sets up independent mounts
+    /// concurrently on up to threads threads, e.g. to not wait for a slow NFS
+    /// bind mount before the next one. Mounts whose destinations nest into
+    /// each other depend on each other and are set up in spec order on the
+    /// same thread.
+    ///
+    /// The threads share the mount namespace of the caller, so this has to be
+    /// called after the process entered the mount namespace of the container.
+    /// Destinations are compared lexically: a mount must not depend on
+    /// another one through a symlink in the rootfs or its mount options, e.g.
+    /// an overlay with a lowerdir on an earlier mount. Every thread performs
+    /// its system calls through a new syscall implementation and the
+    /// observer of this Mount isn't notified.
+    pub fn setup_all_mounts_parallel(
+        &self,
+        mounts: &[SpecMount],
+        options: &MountOptions,
+        threads: usize,
+    ) -> Result<Vec<PathBuf>> {
+        check_duplicate_destinations(mounts)?;
+        check_readonly_order(mounts)?;
+
+        let groups = &mount_groups(mounts);
+        let next_group = &AtomicUsize::new(0);
+        let failed = &AtomicBool::new(false);
+        let dry_run = self.dry_run;
+        let unlabeled_fs = &self.unlabeled_fs;
+
+        // std::thread::scope needs a newer Rust than the crate supports
+        let results: Vec<Result<()>> = crossbeam_utils::thread::scope(|s| {
+            let workers: Vec<_> = (0..threads.clamp(1, groups.len().max(1)))
+                .map(|_| {
+                    s.spawn(move |_| {
+                        let worker = Mount {
+                            syscall: create_syscall(),
+                            observer: Box::new(NoopMountObserver),
+                            dry_run,
+                            unlabeled_fs: unlabeled_fs.clone(),
+                        };
+                        // stop taking new groups once a mount failed
+                        while !failed.load(Ordering::Relaxed) {
+                            let group = match groups.get(next_group.fetch_add(1, Ordering::Relaxed))
+                            {
+                                Some(group) => group,
+                                None => break,
+                            };
+                            for &index in group {
+                                if let Err(err) =
+                                    worker.setup_indexed_mount(index, &mounts[index], options)
+                                {
+                                    failed.store(true, Ordering::Relaxed);
+                                    return Err(err);
+                                }
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|err| std::panic::resume_unwind(err))
+                })
+                .collect()
+        })
+        .unwrap_or_else(|err| std::panic::resume_unwind(err));
+        results.into_iter().collect::<Result<()>>()?;
+
+        if options.sync_after_mounts {
+            self.syscall.sync()?;
         }
+
+        mounts
+            .iter()
+            .map(|mount| join_rootfs(options.root, mount.destination()))
+            .collect()
+    }
+
+    /// Set up mount, the index-th mount of the spec, as part of all mounts.
+    fn setup_indexed_mount(
+        &self,
+        index: usize,
+        mount: &SpecMount,
+        options: &MountOptions,
+    ) -> Result<()> {
+        let start = Instant::now();
+        self.setup_mount(mount, options)
+            .map_err(|err| MountError::Setup {
+                index,
+                src: mount.source().clone(),
+                dest: mount.destination().clone(),
+                err: Box::new(err),
+            })?;
+        warn_if_slow(
+            index,
+            mount.destination(),
+            start.elapsed(),
+            options.slow_mount_threshold,
+        );
+
+        Ok(())
     }
 
     pub fn setup_mount(&self, mount: &SpecMount, options: &MountOptions) -> Result<()> {
-        log::debug!("mounting {:?}", mount);
+        // bind mounts make up most of a spec, only log them when tracing
+        if mount.typ().as_deref() == Some("bind") {
+            log::trace!(target: LOG_TARGET, "mounting {:?}", mount);
+        } else {
+            log::debug!(target: LOG_TARGET, "mounting {:?}", mount);
+        }
+        if options.skip_cgroup_mount && matches!(mount.typ().as_deref(), Some("cgroup" | "cgroup2"))
+        {
+            log::debug!(
+                target: LOG_TARGET,
+                "skipping cgroup mount at {:?}",
+                mount.destination()
+            );
+            return Ok(());
+        }
+
+        check_mount_type(mount, options)?;
+        check_cgroup_mount_type(mount, libcgroups::common::get_cgroup_setup)?;
+
+        let root_fd_path = options.root_fd.map(proc_fd_path);
+        let with_root_fd;
+        let options = match &root_fd_path {
+            Some(root) => {
+                with_root_fd = MountOptions {
+                    root,
+                    ..options.clone()
+                };
+                &with_root_fd
+            }
+            None => options,
+        };
+
+        let mount = &resolve_bind_source(mount, options.bundle_dir);
+        let mount = &apply_type_defaults(mount, options.type_defaults);
+        check_proc_options(mount)?;
         let mut mount_option_config = parse_mount(mount);
+        mount_option_config.root_fd = options.root_fd;
 
         match mount.typ().as_deref() {
-            Some("cgroup") => {
-                match libcgroups::common::get_cgroup_setup()
-                    .context("failed to determine cgroup setup")?
-                {
-                    Legacy | Hybrid => {
-                        #[cfg(not(feature = "v1"))]
-                        panic!("libcontainer can't run in a Legacy or Hybrid cgroup setup without the v1 feature");
-                        #[cfg(feature = "v1")]
-                        self.mount_cgroup_v1(mount, options)
-                            .context("failed to mount cgroup v1")?
-                    }
-                    Unified => {
-                        #[cfg(not(feature = "v2"))]
-                        panic!("libcontainer can't run in a Unified cgroup setup without the v2 feature");
-                        #[cfg(feature = "v2")]
-                        self.mount_cgroup_v2(mount, options, &mount_option_config)
-                            .context("failed to mount cgroup v2")?
+            Some("cgroup") => match libcgroups::common::get_cgroup_setup()? {
+                Legacy | Hybrid => {
+                    #[cfg(not(feature = "v1"))]
+                    return Err(MountError::UnsupportedCgroup(
+                        "libcontainer can't run in a Legacy or Hybrid cgroup setup without the v1 feature"
+                            .to_string(),
+                    ));
+                    #[cfg(feature = "v1")]
+                    {
+                        let subsystems = self.mount_cgroup_v1(mount, options)?;
+                        log::debug!(
+                            target: LOG_TARGET,
+                            "mounted cgroup v1 subsystems {:?}",
+                            subsystems
+                        );
+                        self.observer.on_cgroup_v1_mounted(mount, &subsystems);
                     }
                 }
-            }
+                Unified => {
+                    #[cfg(not(feature = "v2"))]
+                    return Err(MountError::UnsupportedCgroup(
+                        "libcontainer can't run in a Unified cgroup setup without the v2 feature"
+                            .to_string(),
+                    ));
+                    #[cfg(feature = "v2")]
+                    self.mount_cgroup_v2(mount, options, &mount_option_config)?
+                }
+            },
             _ => {
                 if *mount.destination() == PathBuf::from("/dev") {
                     mount_option_config.flags &= !MsFlags::MS_RDONLY;
-                    self.mount_into_container(
-                        mount,
-                        options.root,
-                        &mount_option_config,
-                        options.label,
-                    )
-                    .with_context(|| format!("failed to mount /dev: {mount:?}"))?;
-                } else {
-                    self.mount_into_container(
-                        mount,
-                        options.root,
-                        &mount_option_config,
-                        options.label,
-                    )
-                    .with_context(|| format!("failed to mount: {mount:?}"))?;
                 }
+                if mount.destination().starts_with("/dev") {
+                    // directories under /dev must not be group or world writable
+                    mount_option_config.dir_mode = Some(0o755);
+                }
+                if let Some(max) = options.max_rbind_submounts {
+                    check_rbind_submounts(mount, &mount_option_config, max, || {
+                        read_mount_infos(Path::new("/proc/self"))
+                    })?;
+                }
+                self.mount_into_container(
+                    mount,
+                    options.root,
+                    &mount_option_config,
+                    options.label,
+                )?;
             }
         }
 
         Ok(())
     }
 
+    /// Mount the cgroup v1 subsystems of the host and return the names of the
+    /// mounted ones.
     #[cfg(feature = "v1")]
-    fn mount_cgroup_v1(&self, cgroup_mount: &SpecMount, options: &MountOptions) -> Result<()> {
-        log::debug!("Mounting cgroup v1 filesystem");
+    fn mount_cgroup_v1(
+        &self,
+        cgroup_mount: &SpecMount,
+        options: &MountOptions,
+    ) -> Result<Vec<String>> {
+        log::debug!(target: LOG_TARGET, "Mounting cgroup v1 filesystem");
         // create tmpfs into which the cgroup subsystems will be mounted
-        let tmpfs = SpecMountBuilder::default()
-            .source("tmpfs")
-            .typ("tmpfs")
-            .destination(cgroup_mount.destination())
-            .options(
-                ["noexec", "nosuid", "nodev", "mode=755"]
-                    .iter()
-                    .map(|o| o.to_string())
-                    .collect::<Vec<String>>(),
-            )
-            .build()
-            .context("failed to build tmpfs for cgroup")?;
+        let mut tmpfs_options = vec!["mode=755".to_string()];
+        if let Some(size) = &options.cgroup_tmpfs_size {
+            tmpfs_options.push(format!("size={size}"));
+        }
+        let tmpfs = tmpfs_mount(cgroup_mount.destination(), tmpfs_options)?;
+
+        self.setup_mount(&tmpfs, options)?;
+
+        // Don't leave the cgroup tree half set up if mounting any subsystem
+        // fails, the tmpfs is mounted at the cgroup root.
+        let mut mounted = Vec::new();
+        let result = self.mount_cgroup_v1_subsystems(cgroup_mount, options, &mut mounted);
+        if result.is_err() {
+            if let Ok(cgroup_root) = options.root.join_safely(cgroup_mount.destination()) {
+                mounted.insert(0, cgroup_root);
+            }
+            self.unmount_all(&mounted);
+        }
 
-        self.setup_mount(&tmpfs, options)
-            .context("failed to mount tmpfs for cgroup")?;
+        result
+    }
 
+    /// Mount the cgroup v1 subsystems into the cgroup tmpfs at the
+    /// destination of cgroup_mount, recording the mount points in mounted.
+    #[cfg(feature = "v1")]
+    fn mount_cgroup_v1_subsystems(
+        &self,
+        cgroup_mount: &SpecMount,
+        options: &MountOptions,
+        mounted: &mut Vec<PathBuf>,
+    ) -> Result<Vec<String>> {
         // get all cgroup mounts on the host system
-        let host_mounts: Vec<PathBuf> = libcgroups::v1::util::list_subsystem_mount_points()
-            .context("failed to get subsystem mount points")?
-            .into_iter()
-            .filter(|p| p.as_path().starts_with(DEFAULT_CGROUP_ROOT))
-            .collect();
-        log::debug!("cgroup mounts: {:?}", host_mounts);
+        let host_mounts = libcgroups::v1::util::list_subsystem_mount_points()?;
+        let host_cgroup_root = match options.cgroup_root {
+            Some(root) => root.to_path_buf(),
+            None => libcgroups::v1::util::cgroup_root_of(&host_mounts)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_CGROUP_ROOT)),
+        };
+        let host_mounts = filter_subsystem_mounts(host_mounts, &host_cgroup_root);
+        log::debug!(
+            target: LOG_TARGET,
+            "cgroup mounts under {:?}: {:?}",
+            host_cgroup_root,
+            host_mounts
+        );
 
         // get process cgroups
         let process_cgroups: HashMap<String, String> = Process::myself()?
-            .cgroups()
-            .context("failed to get process cgroups")?
+            .cgroups()?
             .into_iter()
             .map(|c| (c.controllers.join(","), c.pathname))
             .collect();
-        log::debug!("Process cgroups: {:?}", process_cgroups);
+        log::debug!(target: LOG_TARGET, "Process cgroups: {:?}", process_cgroups);
 
         let cgroup_root = options
             .root
             .join_safely(cgroup_mount.destination())
-            .context("could not join rootfs path with cgroup mount destination")?;
-        log::debug!("cgroup root: {:?}", cgroup_root);
+            .map_err(|err| MountError::JoinSafely {
+                source: err,
+                root: options.root.to_path_buf(),
+                path: cgroup_mount.destination().clone(),
+            })?;
+        log::debug!(target: LOG_TARGET, "cgroup root: {:?}", cgroup_root);
 
         let symlink = Symlink::new();
+        let mut subsystems = Vec::new();
 
         // setup cgroup mounts for container
         for host_mount in &host_mounts {
             if let Some(subsystem_name) = host_mount.file_name().and_then(|n| n.to_str()) {
-                if options.cgroup_ns {
+                let is_mounted = if options.cgroup_ns {
                     self.setup_namespaced_subsystem(
                         cgroup_mount,
                         options,
                         subsystem_name,
                         subsystem_name == "systemd",
                     )?;
+                    true
                 } else {
                     self.setup_emulated_subsystem(
                         cgroup_mount,
@@ -160,16 +732,29 @@ impl Mount {
                         subsystem_name == "systemd",
                         host_mount,
                         &process_cgroups,
-                    )?;
+                    )?
+                };
+                if is_mounted {
+                    mounted.push(cgroup_root.join(subsystem_name));
+                    subsystems.push(subsystem_name.to_string());
                 }
 
-                symlink.setup_comount_symlinks(&cgroup_root, subsystem_name)?;
+                symlink
+                    .setup_comount_symlinks(&cgroup_root, subsystem_name)
+                    .map_err(|err| MountError::ComountSymlink {
+                        subsystem: subsystem_name.to_string(),
+                        msg: err.to_string(),
+                    })?;
             } else {
-                log::warn!("could not get subsystem name from {:?}", host_mount);
+                log::warn!(
+                    target: LOG_TARGET,
+                    "could not get subsystem name from {:?}",
+                    host_mount
+                );
             }
         }
 
-        Ok(())
+        Ok(subsystems)
     }
 
     // On some distros cgroup subsystems are comounted e.g. cpu,cpuacct or net_cls,net_prio. These systems
@@ -183,21 +768,12 @@ impl Mount {
         named: bool,
     ) -> Result<()> {
         log::debug!(
+            target: LOG_TARGET,
             "Mounting (namespaced) {:?} cgroup subsystem",
             subsystem_name
         );
-        let subsystem_mount = SpecMountBuilder::default()
-            .source("cgroup")
-            .typ("cgroup")
-            .destination(cgroup_mount.destination().join(subsystem_name))
-            .options(
-                ["noexec", "nosuid", "nodev"]
-                    .iter()
-                    .map(|o| o.to_string())
-                    .collect::<Vec<String>>(),
-            )
-            .build()
-            .with_context(|| format!("failed to build {subsystem_name}"))?;
+        let subsystem_mount =
+            cgroup_subsystem_mount(&cgroup_mount.destination().join(subsystem_name))?;
 
         let data: Cow<str> = if named {
             format!("name={subsystem_name}").into()
@@ -205,10 +781,21 @@ impl Mount {
             subsystem_name.into()
         };
 
+        let mut flags = MsFlags::MS_NOEXEC | MsFlags::MS_NOSUID | MsFlags::MS_NODEV;
+        if is_readonly(cgroup_mount) {
+            flags |= MsFlags::MS_RDONLY;
+        }
         let mount_options_config = MountOptionConfig {
-            flags: MsFlags::MS_NOEXEC | MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+            flags,
             data: data.to_string(),
             rec_attr: None,
+            attr: None,
+            uid_mappings: None,
+            gid_mappings: None,
+            dir_mode: None,
+            copy_up: false,
+            idmap_recursive: false,
+            root_fd: None,
         };
 
         self.mount_into_container(
@@ -217,7 +804,6 @@ impl Mount {
             &mount_options_config,
             options.label,
         )
-        .with_context(|| format!("failed to mount {subsystem_mount:?}"))
     }
 
     #[cfg(feature = "v1")]
@@ -229,8 +815,12 @@ impl Mount {
         named: bool,
         host_mount: &Path,
         process_cgroups: &HashMap<String, String>,
-    ) -> Result<()> {
-        log::debug!("Mounting (emulated) {:?} cgroup subsystem", subsystem_name);
+    ) -> Result<bool> {
+        log::debug!(
+            target: LOG_TARGET,
+            "Mounting (emulated) {:?} cgroup subsystem",
+            subsystem_name
+        );
         let named_hierarchy: Cow<str> = if named {
             format!("name={subsystem_name}").into()
         } else {
@@ -238,41 +828,70 @@ impl Mount {
         };
 
         if let Some(proc_path) = process_cgroups.get(named_hierarchy.as_ref()) {
-            let emulated = SpecMountBuilder::default()
-                .source(
-                    host_mount
-                        .join_safely(proc_path.as_str())
-                        .with_context(|| {
-                            format!("failed to join mount source for {subsystem_name} subsystem")
-                        })?,
-                )
-                .destination(
-                    cgroup_mount
-                        .destination()
-                        .join_safely(subsystem_name)
-                        .with_context(|| {
-                            format!(
-                                "failed to join mount destination for {subsystem_name} subsystem"
-                            )
-                        })?,
-                )
-                .typ("bind")
-                .options(
-                    ["rw", "rbind"]
-                        .iter()
-                        .map(|o| o.to_string())
-                        .collect::<Vec<String>>(),
-                )
-                .build()?;
-            log::debug!("Mounting emulated cgroup subsystem: {:?}", emulated);
+            let source = host_mount.join_safely(proc_path.as_str()).map_err(|err| {
+                MountError::JoinSafely {
+                    source: err,
+                    root: host_mount.to_path_buf(),
+                    path: PathBuf::from(proc_path),
+                }
+            })?;
+            if !source.exists() {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "Could not mount {:?} cgroup subsystem, source {:?} does not exist",
+                    subsystem_name,
+                    source
+                );
+                return Ok(false);
+            }
+
+            let destination = cgroup_mount
+                .destination()
+                .join_safely(subsystem_name)
+                .map_err(|err| MountError::JoinSafely {
+                    source: err,
+                    root: cgroup_mount.destination().clone(),
+                    path: PathBuf::from(subsystem_name),
+                })?;
+            let access = if is_readonly(cgroup_mount) {
+                "ro"
+            } else {
+                "rw"
+            };
+            let emulated = bind_mount(&source, &destination, &[access, "rbind"])?;
+            log::debug!(
+                target: LOG_TARGET,
+                "Mounting emulated cgroup subsystem: {:?}",
+                emulated
+            );
 
-            self.setup_mount(&emulated, options)
-                .with_context(|| format!("failed to mount {subsystem_name} cgroup hierarchy"))?;
+            self.setup_mount(&emulated, options)?;
+            Ok(true)
         } else {
-            log::warn!("Could not mount {:?} cgroup subsystem", subsystem_name);
+            log::warn!(
+                target: LOG_TARGET,
+                "Could not mount {:?} cgroup subsystem",
+                subsystem_name
+            );
+            Ok(false)
         }
+    }
 
-        Ok(())
+    /// Unmount the given mount points in reverse order, after setting them up
+    /// failed part way. Errors are only logged, as the original failure is
+    /// what gets reported.
+    #[cfg(feature = "v1")]
+    fn unmount_all(&self, mount_points: &[PathBuf]) {
+        for mount_point in mount_points.iter().rev() {
+            if let Err(err) = self.syscall.umount2(mount_point, MntFlags::MNT_DETACH) {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "failed to unmount {:?}: {}",
+                    mount_point,
+                    err
+                );
+            }
+        }
     }
 
     #[cfg(feature = "v2")]
@@ -282,55 +901,70 @@ impl Mount {
         options: &MountOptions,
         mount_option_config: &MountOptionConfig,
     ) -> Result<()> {
-        log::debug!("Mounting cgroup v2 filesystem");
+        log::debug!(target: LOG_TARGET, "Mounting cgroup v2 filesystem");
 
         let cgroup_mount = SpecMountBuilder::default()
             .typ("cgroup2")
             .source("cgroup")
             .destination(cgroup_mount.destination())
-            .options(Vec::new())
+            .options(cgroup_mount.options().clone().unwrap_or_default())
             .build()?;
-        log::debug!("{:?}", cgroup_mount);
+        log::debug!(target: LOG_TARGET, "{:?}", cgroup_mount);
+
+        // the options of the spec mount are meant for a cgroup mount in
+        // general, only keep the data understood by cgroup2.
+        let mut mount_option_config = mount_option_config.clone();
+        mount_option_config.data = cgroup2_mount_data(&mount_option_config.data);
 
         if self
             .mount_into_container(
                 &cgroup_mount,
                 options.root,
-                mount_option_config,
+                &mount_option_config,
                 options.label,
             )
-            .context("failed to mount into container")
             .is_err()
         {
-            let host_mount = libcgroups::v2::util::get_unified_mount_point()
-                .context("failed to get unified mount point")?;
+            let host_mount = libcgroups::v2::util::get_unified_mount_point()?;
 
             let process_cgroup = Process::myself()?
-                .cgroups()
-                .context("failed to get process cgroups")?
+                .cgroups()?
                 .into_iter()
                 .find(|c| c.hierarchy == 0)
                 .map(|c| PathBuf::from(c.pathname))
-                .ok_or_else(|| anyhow!("failed to find unified process cgroup"))?;
+                .ok_or(MountError::UnifiedCgroupNotFound)?;
 
-            let bind_mount = SpecMountBuilder::default()
-                .typ("bind")
-                .source(host_mount.join_safely(process_cgroup)?)
-                .destination(cgroup_mount.destination())
-                .options(Vec::new())
-                .build()
-                .context("failed to build cgroup bind mount")?;
-            log::debug!("{:?}", bind_mount);
+            let source =
+                host_mount
+                    .join_safely(&process_cgroup)
+                    .map_err(|err| MountError::JoinSafely {
+                        source: err,
+                        root: host_mount.clone(),
+                        path: process_cgroup.clone(),
+                    })?;
+            // the container runs in the cgroup of the process, which it can
+            // only manage if the cgroup is delegated to the user.
+            let undelegated =
+                undelegated_cgroup_files(&source, unistd::geteuid(), unistd::getegid());
+            if !undelegated.is_empty() {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "cgroup {:?} is not delegated to the current user, {:?} are not writable and resource limits of the container will fail, see https://rootlesscontaine.rs/getting-started/common/cgroup2/ for enabling cgroup delegation",
+                    source,
+                    undelegated
+                );
+            }
+
+            let bind_mount = bind_mount(&source, cgroup_mount.destination(), &[])?;
+            log::debug!(target: LOG_TARGET, "{:?}", bind_mount);
 
-            let mut mount_option_config = (*mount_option_config).clone();
             mount_option_config.flags |= MsFlags::MS_BIND;
             self.mount_into_container(
                 &bind_mount,
                 options.root,
                 &mount_option_config,
                 options.label,
-            )
-            .context("failed to bind mount cgroup hierarchy")?;
+            )?;
         }
 
         Ok(())
@@ -339,26 +973,111 @@ impl Mount {
     /// Make parent mount of rootfs private if it was shared, which is required by pivot_root.
     /// It also makes sure following bind mount does not propagate in other namespaces.
     pub fn make_parent_mount_private(&self, rootfs: &Path) -> Result<Option<MountInfo>> {
-        let mount_infos = Process::myself()?.mountinfo()?;
-        let parent_mount = find_parent_mount(rootfs, mount_infos)?;
+        let mount_infos = read_mount_infos(Path::new("/proc/self"))?;
+        let parent_mount = find_parent_mount(rootfs, mount_infos)
+            .map_err(|_| MountError::ParentMountNotFound(rootfs.to_path_buf()))?;
+
+        self.make_mount_private(parent_mount)
+    }
 
-        // check parent mount has 'shared' propagation type
-        if parent_mount
+    /// Make mount private if it is shared, returning it if it was changed.
+    fn make_mount_private(&self, mount: MountInfo) -> Result<Option<MountInfo>> {
+        // check the mount has 'shared' propagation type
+        if mount
             .opt_fields
             .iter()
             .any(|field| matches!(field, MountOptFields::Shared(_)))
         {
+            self.syscall
+                .mount(None, &mount.mount_point, None, MsFlags::MS_PRIVATE, None)
+                .map_err(|source| MountError::ParentMountPrivate {
+                    source,
+                    mount_point: mount.mount_point.clone(),
+                })?;
+            Ok(Some(mount))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Change the root of the process to the rootfs, through pivot_root or,
+    /// with no_pivot, by moving the rootfs onto / and chrooting into it. The
+    /// latter is for environments where pivot_root isn't available (e.g. a
+    /// ramfs root) and is weaker: the old root stays reachable below the new
+    /// one, so a process able to escape the chroot (e.g. with CAP_SYS_CHROOT)
+    /// gets access to the host filesystem.
+    pub fn enter_rootfs(&self, rootfs: &Path, no_pivot: bool) -> Result<()> {
+        enter_rootfs(self.syscall.as_ref(), rootfs, no_pivot)
+    }
+
+    /// Mask a path inside the rootfs the way runc does: files are hidden by
+    /// bind mounting /dev/null over them, directories by mounting a read-only
+    /// tmpfs over them. Nothing is done if the path doesn't exist.
+    pub fn mask_path(&self, rootfs: &Path, path: &Path) -> Result<()> {
+        let dest = join_rootfs(rootfs, path)?;
+        if !dest.exists() {
+            log::debug!(target: LOG_TARGET, "masked path {:?} does not exist", dest);
+            return Ok(());
+        }
+
+        if dest.is_dir() {
             self.syscall.mount(
+                Some(Path::new("tmpfs")),
+                &dest,
+                Some("tmpfs"),
+                MsFlags::MS_RDONLY,
                 None,
-                &parent_mount.mount_point,
+            )?;
+        } else {
+            self.syscall.mount(
+                Some(Path::new("/dev/null")),
+                &dest,
                 None,
-                MsFlags::MS_PRIVATE,
+                MsFlags::MS_BIND,
                 None,
             )?;
-            Ok(Some(parent_mount))
-        } else {
-            Ok(None)
         }
+
+        Ok(())
+    }
+
+    /// Make a path inside the rootfs read-only by bind mounting it onto itself
+    /// and remounting the bind mount read-only. Nothing is done if the path
+    /// doesn't exist.
+    pub fn make_readonly(&self, rootfs: &Path, path: &Path) -> Result<()> {
+        let dest = join_rootfs(rootfs, path)?;
+        if !dest.exists() {
+            log::debug!(
+                target: LOG_TARGET,
+                "readonly path {:?} does not exist",
+                dest
+            );
+            return Ok(());
+        }
+
+        self.syscall.mount(
+            Some(&dest),
+            &dest,
+            None,
+            MsFlags::MS_BIND | MsFlags::MS_REC,
+            None,
+        )?;
+        self.syscall.mount(
+            Some(&dest),
+            &dest,
+            None,
+            MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY,
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    /// Compute where the destination of m lands on the host for the given
+    /// rootfs, resolving symlinks inside the rootfs like mounting does. Nothing
+    /// is created or mounted, so specs can be checked without privileges.
+    pub fn resolve_destination(&self, rootfs: &Path, m: &SpecMount) -> Result<PathBuf> {
+        join_rootfs(rootfs, m.destination())
     }
 
     fn mount_into_container(
@@ -367,73 +1086,248 @@ impl Mount {
         rootfs: &Path,
         mount_option_config: &MountOptionConfig,
         label: Option<&str>,
+    ) -> Result<()> {
+        self.observer.on_mount_start(m);
+        let start = Instant::now();
+        let result = self.do_mount_into_container(m, rootfs, mount_option_config, label);
+        self.observer.on_mount_end(m, start.elapsed(), &result);
+
+        result
+    }
+
+    /// Mount src at target with data, retrying with fallback_data, i.e.
+    /// without the label, if the filesystem rejects it with EINVAL.
+    #[allow(clippy::too_many_arguments)]
+    fn mount_with_fallback(
+        &self,
+        m: &SpecMount,
+        src: &Path,
+        target: &Path,
+        typ: Option<&str>,
+        flags: MsFlags,
+        data: &str,
+        fallback_data: &str,
+    ) -> Result<()> {
+        if let Err(err) = self.mount_retry_busy(src, target, typ, flags, data) {
+            if let SyscallError::Mount { source: errno, .. } = err {
+                if !matches!(errno, Errno::EINVAL) {
+                    log::error!(
+                        target: LOG_TARGET,
+                        "mount of {:?} failed. {}",
+                        m.destination(),
+                        errno
+                    );
+                    return Err(err.into());
+                }
+            }
+
+            self.mount_retry_busy(src, target, typ, flags, fallback_data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Mount src at target with data, retrying once after a short backoff if
+    /// the mount fails with EBUSY, which can be transient, e.g. while the
+    /// target is being unmounted elsewhere.
+    fn mount_retry_busy(
+        &self,
+        src: &Path,
+        target: &Path,
+        typ: Option<&str>,
+        flags: MsFlags,
+        data: &str,
+    ) -> std::result::Result<(), SyscallError> {
+        match self
+            .syscall
+            .mount(Some(src), target, typ, flags, Some(data))
+        {
+            Err(SyscallError::Mount {
+                source: Errno::EBUSY,
+            }) => {
+                log::debug!(
+                    target: LOG_TARGET,
+                    "mount at {:?} is busy, retrying in {:?}",
+                    target,
+                    MOUNT_BUSY_BACKOFF
+                );
+                thread::sleep(MOUNT_BUSY_BACKOFF);
+                self.syscall
+                    .mount(Some(src), target, typ, flags, Some(data))
+            }
+            result => result,
+        }
+    }
+
+    fn do_mount_into_container(
+        &self,
+        m: &SpecMount,
+        rootfs: &Path,
+        mount_option_config: &MountOptionConfig,
+        label: Option<&str>,
     ) -> Result<()> {
         let typ = m.typ().as_deref();
-        let mut d = mount_option_config.data.to_string();
+        let data = if typ == Some("overlay") {
+            resolve_overlay_data(rootfs, &mount_option_config.data, !self.dry_run)?
+        } else {
+            mount_option_config.data.to_string()
+        };
+        let mut d = data.clone();
 
         if let Some(l) = label {
-            if typ != Some("proc") && typ != Some("sysfs") {
-                match mount_option_config.data.is_empty() {
-                    true => d = format!("context=\"{l}\""),
-                    false => d = format!("{},context=\"{}\"", mount_option_config.data, l),
+            let unlabeled = typ.map_or(false, |typ| self.unlabeled_fs.iter().any(|fs| fs == typ));
+            if !unlabeled {
+                let (option, context) = selinux_context_option(l);
+                match data.is_empty() {
+                    true => d = format!("{option}=\"{context}\""),
+                    false => d = format!("{},{}=\"{}\"", data, option, context),
                 }
             }
         }
 
-        let dest_for_host = utils::secure_join(rootfs, m.destination())
-            .with_context(|| format!("failed to join {:?} with {:?}", rootfs, m.destination()))?;
+        check_data_len(m, &d, mount_data_limit())?;
 
+        let dest_for_host = self.resolve_destination(rootfs, m)?;
         let dest = Path::new(&dest_for_host);
-        let source = m
-            .source()
-            .as_ref()
-            .with_context(|| "no source in mount spec".to_string())?;
-        let src = if typ == Some("bind") {
-            let src = canonicalize(source)
-                .with_context(|| format!("failed to canonicalize: {source:?}"))?;
-            let dir = if src.is_file() {
-                Path::new(&dest).parent().unwrap()
-            } else {
+
+        // A mount entry without source and type only changes the propagation
+        // of the mount already at the destination.
+        if m.source().is_none() && typ.is_none() {
+            let propagation = parse_propagation(m);
+            if !propagation.is_empty() {
+                if self.dry_run {
+                    log::info!(
+                        target: LOG_TARGET,
+                        "dry run: set propagation {:?} of {:?}",
+                        propagation,
+                        dest
+                    );
+                    return Ok(());
+                }
+                ensure_dest_in_rootfs(rootfs, dest)?;
+                self.syscall.mount(None, dest, None, propagation, None)?;
+                return Ok(());
+            }
+        }
+
+        // Only the propagation of the rootfs may be changed above, a mount on
+        // top of it would hide the container root.
+        if is_rootfs(rootfs, dest) {
+            return Err(MountError::DestinationIsRootfs(m.destination().clone()));
+        }
+
+        let src = match (m.source(), typ) {
+            (Some(source), Some("bind")) => {
+                resolve_bind_mount_source(source, |source| canonicalize(source))?
+            }
+            (Some(source), _) => source.clone(),
+            // the source of a virtual filesystem is only informational, the
+            // type is what is conventionally used for it.
+            (None, Some(typ)) if SOURCELESS_FS.contains(&typ) => PathBuf::from(typ),
+            (None, _) => return Err(MountError::MissingSource),
+        };
+
+        if self.dry_run {
+            log::info!(
+                target: LOG_TARGET,
+                "dry run: mount {:?} to {:?} (type: {:?}, flags: {:?}, data: {:?})",
+                src,
+                dest,
+                typ,
+                mount_option_config.flags,
+                d
+            );
+            return Ok(());
+        }
+
+        if typ == Some("bind") {
+            // Anything but a directory (regular files, sockets, fifos and
+            // device nodes) can be bind mounted over a regular file, so an
+            // empty file is created as the mount point for those.
+            let src_is_dir = src.is_dir();
+            let dir = if src_is_dir {
                 Path::new(&dest)
+            } else {
+                Path::new(&dest).parent().unwrap()
             };
 
-            create_dir_all(dir)
-                .with_context(|| format!("failed to create dir for bind mount: {dir:?}"))?;
+            create_mount_dir(dir, mount_option_config.dir_mode)?;
 
-            if src.is_file() {
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .open(dest)
-                    .with_context(|| format!("failed to create file for bind mount: {src:?}"))?;
+            if !src_is_dir {
+                create_mount_file(dest)?;
             }
-
-            src
         } else {
-            create_dir_all(dest).with_context(|| format!("Failed to create device: {dest:?}"))?;
+            create_mount_dir(dest, mount_option_config.dir_mode)?;
+        }
+
+        // secure_join resolved the destination inside the rootfs, but a symlink
+        // could have been swapped in before the directories above were created.
+        // Check again on the final path right before mounting over it.
+        ensure_dest_in_rootfs(rootfs, dest)?;
 
-            PathBuf::from(source)
+        // tmpcopyup: save the current contents of the destination, they are
+        // copied into the tmpfs once it is mounted. The tmpfs is mounted
+        // writable for that and remounted read-only afterwards if requested.
+        let copy_up = typ == Some("tmpfs") && mount_option_config.copy_up;
+        let stash = if copy_up {
+            Some(StashedDir::new(dest)?)
+        } else {
+            None
+        };
+        let flags = if copy_up {
+            mount_option_config.flags & !MsFlags::MS_RDONLY
+        } else {
+            mount_option_config.flags
         };
 
-        if let Err(err) =
-            self.syscall
-                .mount(Some(&*src), dest, typ, mount_option_config.flags, Some(&*d))
-        {
-            if let SyscallError::Mount { source: errno, .. } = err {
-                if !matches!(errno, Errno::EINVAL) {
-                    bail!("mount of {:?} failed. {}", m.destination(), errno);
+        // With a rootfs fd the mount goes onto the destination opened beneath
+        // it, so nothing can be swapped in anymore. Everything after this
+        // happens on the new mount at dest.
+        let dest_fd = match mount_option_config.root_fd {
+            Some(root_fd) => Some(open_beneath(root_fd, rootfs, dest)?),
+            None => None,
+        };
+        let target = dest_fd.map(proc_fd_path);
+        let result = self.mount_with_fallback(
+            m,
+            &src,
+            target.as_deref().unwrap_or(dest),
+            typ,
+            flags,
+            &d,
+            &data,
+        );
+        if let Some(dest_fd) = dest_fd {
+            let _ = unistd::close(dest_fd);
+        }
+        result?;
+
+        if let Some(stash) = stash {
+            let result = stash.restore(dest).and_then(|_| {
+                if mount_option_config.flags.contains(MsFlags::MS_RDONLY) {
+                    self.syscall.mount(
+                        Some(&*src),
+                        dest,
+                        typ,
+                        mount_option_config.flags | MsFlags::MS_REMOUNT,
+                        Some(&*d),
+                    )?;
+                }
+                Ok(())
+            });
+            // don't leave a partially populated or writable tmpfs over the
+            // original contents of the destination.
+            if let Err(err) = result {
+                if let Err(umount_err) = self.syscall.umount2(dest, MntFlags::MNT_DETACH) {
+                    log::warn!(
+                        target: LOG_TARGET,
+                        "failed to unmount {:?}: {}",
+                        dest,
+                        umount_err
+                    );
                 }
+                return Err(err);
             }
-
-            self.syscall
-                .mount(
-                    Some(&*src),
-                    dest,
-                    typ,
-                    mount_option_config.flags,
-                    Some(&mount_option_config.data),
-                )
-                .with_context(|| format!("failed to mount {src:?} to {dest:?}"))?;
         }
 
         if typ == Some("bind")
@@ -441,36 +1335,967 @@ impl Mount {
                 !(MsFlags::MS_REC
                     | MsFlags::MS_REMOUNT
                     | MsFlags::MS_BIND
-                    | MsFlags::MS_PRIVATE
-                    | MsFlags::MS_SHARED
-                    | MsFlags::MS_SLAVE),
+                    | MountPropagation::mask()),
             )
         {
-            self.syscall
-                .mount(
-                    Some(dest),
-                    dest,
-                    None,
-                    mount_option_config.flags | MsFlags::MS_REMOUNT,
-                    None,
-                )
-                .with_context(|| format!("Failed to remount: {dest:?}"))?;
+            let flags = mount_option_config.flags | MsFlags::MS_REMOUNT;
+            match self.syscall.mount(Some(dest), dest, None, flags, None) {
+                Ok(()) => {}
+                // Flags like nosuid, nodev or ro of a mount created in a more
+                // privileged user namespace are locked, clearing them fails with
+                // EPERM.
+                Err(SyscallError::Mount {
+                    source: Errno::EPERM,
+                }) => {
+                    return Err(MountError::LockedFlags {
+                        source: Errno::EPERM,
+                        dest: dest.to_path_buf(),
+                        flags,
+                    })
+                }
+                Err(err) => return Err(err.into()),
+            }
+
+            // The remount above only applies to the top mount, submounts of a
+            // recursive bind mount stay writable unless made read-only too.
+            if mount_option_config
+                .flags
+                .contains(MsFlags::MS_REC | MsFlags::MS_RDONLY)
+                && src.is_dir()
+            {
+                self.make_submounts_readonly(dest, mount_option_config.flags)?;
+            }
         }
 
         if let Some(mount_attr) = &mount_option_config.rec_attr {
-            let open_dir = Dir::open(dest, OFlag::O_DIRECTORY, Mode::empty())?;
-            let dir_fd_pathbuf = PathBuf::from(format!("/proc/self/fd/{}", open_dir.as_raw_fd()));
-            self.syscall.mount_setattr(
-                -1,
-                &dir_fd_pathbuf,
-                linux::AT_RECURSIVE,
-                mount_attr,
-                mem::size_of::<linux::MountAttr>(),
+            self.mount_setattr_recursive(dest, mount_attr)?;
+        }
+        if let Some(mount_attr) = &mount_option_config.attr {
+            self.mount_setattr_at(dest, 0, mount_attr)?;
+        }
+
+        match (
+            &mount_option_config.uid_mappings,
+            &mount_option_config.gid_mappings,
+        ) {
+            (Some(uid_mappings), Some(gid_mappings)) => {
+                self.setup_idmapped_mount(
+                    dest,
+                    uid_mappings,
+                    gid_mappings,
+                    mount_option_config.idmap_recursive,
+                )?;
+            }
+            (None, None) => {}
+            _ => return Err(MountError::IncompleteIdMappings(dest.to_path_buf())),
+        }
+
+        Ok(())
+    }
+
+    fn mount_setattr_recursive(&self, dest: &Path, mount_attr: &linux::MountAttr) -> Result<()> {
+        self.mount_setattr_at(dest, linux::AT_RECURSIVE, mount_attr)
+    }
+
+    /// Apply mount_attr to the mount at dest with mount_setattr(2), flags
+    /// decide whether the submounts get it too.
+    fn mount_setattr_at(
+        &self,
+        dest: &Path,
+        flags: u32,
+        mount_attr: &linux::MountAttr,
+    ) -> Result<()> {
+        // bind mounts may target a file, so O_PATH is used instead of opening
+        // the destination as a directory.
+        let dest_fd = fcntl::open(dest, OFlag::O_PATH | OFlag::O_CLOEXEC, Mode::empty()).map_err(
+            |errno| MountError::Open {
+                source: errno,
+                path: dest.to_path_buf(),
+            },
+        )?;
+        let dest_fd_path = PathBuf::from(format!("/proc/self/fd/{dest_fd}"));
+        let result = self.syscall.mount_setattr(
+            -1,
+            &dest_fd_path,
+            flags,
+            mount_attr,
+            mem::size_of::<linux::MountAttr>(),
+        );
+        let _ = unistd::close(dest_fd);
+
+        Ok(result?)
+    }
+
+    /// Make the whole mount tree at dest read-only. mount_setattr(2) does this
+    /// in one go, on kernels older than 5.12 every submount listed in
+    /// mountinfo is remounted read-only instead.
+    fn make_submounts_readonly(&self, dest: &Path, flags: MsFlags) -> Result<()> {
+        let mount_attr = linux::MountAttr {
+            attr_set: linux::MOUNT_ATTR_RDONLY,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: 0,
+        };
+        match self.mount_setattr_recursive(dest, &mount_attr) {
+            Err(MountError::Syscall(SyscallError::MountSetattr { source }))
+                if source == syscalls::Errno::ENOSYS =>
+            {
+                log::debug!(
+                    target: LOG_TARGET,
+                    "mount_setattr is not supported, remounting submounts of {:?} one by one",
+                    dest
+                );
+            }
+            result => return result,
+        }
+
+        let submounts = Process::myself()?
+            .mountinfo()?
+            .into_iter()
+            .map(|mi| mi.mount_point)
+            .filter(|mount_point| mount_point != dest && mount_point.starts_with(dest));
+        for submount in submounts {
+            self.syscall.mount(
+                Some(&submount),
+                &submount,
+                None,
+                flags | MsFlags::MS_BIND | MsFlags::MS_REMOUNT,
+                None,
             )?;
         }
 
         Ok(())
     }
+
+    /// Apply the given id mappings to the mount at dest through mount_setattr(2)
+    /// with MOUNT_ATTR_IDMAP, to its submounts as well if recursive is set.
+    /// Requires Linux 5.12 or later.
+    fn setup_idmapped_mount(
+        &self,
+        dest: &Path,
+        uid_mappings: &[LinuxIdMapping],
+        gid_mappings: &[LinuxIdMapping],
+        recursive: bool,
+    ) -> Result<()> {
+        let userns_fd = self.syscall.open_userns_fd(uid_mappings, gid_mappings)?;
+
+        // bind mounts may target a file, so O_PATH is used instead of opening
+        // the destination as a directory.
+        let dest_fd = match fcntl::open(dest, OFlag::O_PATH | OFlag::O_CLOEXEC, Mode::empty()) {
+            Ok(fd) => fd,
+            Err(errno) => {
+                let _ = unistd::close(userns_fd);
+                return Err(MountError::Open {
+                    source: errno,
+                    path: dest.to_path_buf(),
+                });
+            }
+        };
+        let dest_fd_path = PathBuf::from(format!("/proc/self/fd/{dest_fd}"));
+        let mount_attr = linux::MountAttr {
+            attr_set: linux::MOUNT_ATTR_IDMAP,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: userns_fd as u64,
+        };
+
+        let flags = if recursive { linux::AT_RECURSIVE } else { 0 };
+
+        let result = self.syscall.mount_setattr(
+            -1,
+            &dest_fd_path,
+            flags,
+            &mount_attr,
+            mem::size_of::<linux::MountAttr>(),
+        );
+        let _ = unistd::close(dest_fd);
+        let _ = unistd::close(userns_fd);
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(SyscallError::MountSetattr { source })
+                if source == syscalls::Errno::ENOSYS || source == syscalls::Errno::EINVAL =>
+            {
+                Err(MountError::IdmapUnsupported {
+                    source,
+                    dest: dest.to_path_buf(),
+                })
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Copy of the contents of a mount destination in a temporary directory, used
+/// to implement tmpcopyup. The copy is removed when dropped.
+struct StashedDir(PathBuf);
+
+impl StashedDir {
+    fn new(dir: &Path) -> Result<Self> {
+        let template = std::env::temp_dir().join("youki-tmpcopyup.XXXXXX");
+        let tmp = unistd::mkdtemp(&template).map_err(|errno| MountError::Io {
+            source: errno.into(),
+            path: template,
+        })?;
+        let stash = StashedDir(tmp);
+        copy_dir_contents(dir, &stash.0).map_err(|err| MountError::Io {
+            source: err,
+            path: dir.to_path_buf(),
+        })?;
+
+        Ok(stash)
+    }
+
+    fn restore(&self, dir: &Path) -> Result<()> {
+        copy_dir_contents(&self.0, dir).map_err(|err| MountError::Io {
+            source: err,
+            path: dir.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for StashedDir {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.0) {
+            log::warn!(
+                target: LOG_TARGET,
+                "failed to remove tmpcopyup dir {:?}: {}",
+                self.0,
+                err
+            );
+        }
+    }
+}
+
+/// Recursively copy the directories, regular files and symlinks in src to
+/// dst, keeping their permissions.
+fn copy_dir_contents(src: &Path, dst: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = dst.join(entry.file_name());
+        if file_type.is_dir() {
+            create_dir_all(&target)?;
+            fs::set_permissions(&target, entry.metadata()?.permissions())?;
+            copy_dir_contents(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target)?;
+        } else {
+            log::warn!(
+                target: LOG_TARGET,
+                "tmpcopyup: skipping special file {:?}",
+                entry.path()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the source of a recursive bind mount has at most max submounts,
+/// the mount table is only read through mount_infos for those.
+fn check_rbind_submounts<F>(
+    mount: &SpecMount,
+    mount_option_config: &MountOptionConfig,
+    max: usize,
+    mount_infos: F,
+) -> Result<()>
+where
+    F: FnOnce() -> Result<Vec<MountInfo>>,
+{
+    let src = match mount.source() {
+        Some(src)
+            if mount_option_config
+                .flags
+                .contains(MsFlags::MS_BIND | MsFlags::MS_REC) =>
+        {
+            src
+        }
+        _ => return Ok(()),
+    };
+
+    let count = mount_infos()?
+        .iter()
+        .filter(|mi| mi.mount_point != *src && mi.mount_point.starts_with(src))
+        .count();
+    if count > max {
+        return Err(MountError::TooManySubmounts {
+            src: src.clone(),
+            count,
+            max,
+        });
+    }
+
+    Ok(())
+}
+
+/// Read the mount table of the process with the given /proc/<pid> directory.
+/// Fails with an error naming the mountinfo file if /proc is not available,
+/// as in some minimal init environments.
+fn read_mount_infos(proc_dir: &Path) -> Result<Vec<MountInfo>> {
+    Process::new_with_root(proc_dir.to_path_buf())
+        .and_then(|process| process.mountinfo())
+        .map_err(|source| MountError::MountInfo {
+            source,
+            path: proc_dir.join("mountinfo"),
+        })
+}
+
+/// Keep the cgroup v1 hierarchies mounted under the cgroup root.
+#[cfg(feature = "v1")]
+fn filter_subsystem_mounts(host_mounts: Vec<PathBuf>, cgroup_root: &Path) -> Vec<PathBuf> {
+    host_mounts
+        .into_iter()
+        .filter(|p| p.starts_with(cgroup_root))
+        .collect()
+}
+
+/// Log a warning if setting up the mount at dest took longer than the
+/// threshold. Returns whether it did.
+fn warn_if_slow(index: usize, dest: &Path, elapsed: Duration, threshold: Option<Duration>) -> bool {
+    match threshold {
+        Some(threshold) if elapsed > threshold => {
+            log::warn!(
+                target: LOG_TARGET,
+                "mount #{} at {:?} took {:?}, longer than {:?}",
+                index,
+                dest,
+                elapsed,
+                threshold
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Group the indexes of mounts whose destinations nest into each other, e.g.
+/// /dev and /dev/pts, as they have to be set up in order. The indexes of a
+/// group are in spec order and the groups are ordered by their first mount.
+fn mount_groups(mounts: &[SpecMount]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (index, mount) in mounts.iter().enumerate() {
+        let dest = mount.destination();
+        let nests = |other: &usize| {
+            let other = mounts[*other].destination();
+            dest.starts_with(other) || other.starts_with(dest)
+        };
+
+        // the mount can join groups which were independent so far, e.g. /a
+        // after /a/b and /a/c
+        let mut group = vec![index];
+        let mut position = None;
+        let mut i = 0;
+        while i < groups.len() {
+            if groups[i].iter().any(nests) {
+                position.get_or_insert(i);
+                group.extend(groups.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        group.sort_unstable();
+        groups.insert(position.unwrap_or(groups.len()), group);
+    }
+
+    groups
+}
+
+/// Check that no two mounts have the same destination, as the later mount
+/// would silently hide the earlier one.
+pub fn check_duplicate_destinations(mounts: &[SpecMount]) -> Result<()> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for mount in mounts {
+        let dest = mount.destination();
+        if !seen.insert(dest) && !duplicates.contains(dest) {
+            duplicates.push(dest.clone());
+        }
+    }
+
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(MountError::DuplicateDestinations(duplicates))
+    }
+}
+
+/// Change the root of the process to the rootfs through syscall, like
+/// [`Mount::enter_rootfs`], for callers which have a syscall at hand.
+pub fn enter_rootfs(syscall: &dyn Syscall, rootfs: &Path, no_pivot: bool) -> Result<()> {
+    if no_pivot {
+        move_root(syscall, rootfs)
+    } else {
+        pivot_rootfs(syscall, rootfs)
+    }
+}
+
+/// Make rootfs the root of the process through pivot_root.
+fn pivot_rootfs(syscall: &dyn Syscall, rootfs: &Path) -> Result<()> {
+    // open the rootfs to change into it once it is the root
+    let newroot = fcntl::open(rootfs, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty())
+        .map_err(|errno| MountError::Io {
+            source: errno.into(),
+            path: rootfs.to_path_buf(),
+        })?;
+
+    // pivot_root usually changes the root to new_root and mounts the
+    // original root at put_old. Passing the rootfs for both stacks the
+    // original root on top of the new one at the same path, so it can be
+    // unmounted from there without a temporary directory for it, see the
+    // notes of pivot_root(2).
+    let result = syscall.pivot_root(rootfs, rootfs).and_then(|_| {
+        // Make the original root rslave to avoid propagating the unmount
+        // to the host mount namespace. MS_SLAVE and not MS_PRIVATE, see
+        // https://github.com/opencontainers/runc/pull/1500.
+        syscall.mount(
+            None,
+            Path::new("/"),
+            None,
+            MsFlags::MS_SLAVE | MsFlags::MS_REC,
+            None,
+        )?;
+        // Unmount the original root stacked on top of the new one.
+        // MNT_DETACH makes it unavailable to new accesses right away.
+        syscall.umount2(Path::new("/"), MntFlags::MNT_DETACH)
+    });
+    let result = result.map_err(MountError::from).and_then(|_| {
+        unistd::fchdir(newroot).map_err(|errno| MountError::Io {
+            source: errno.into(),
+            path: rootfs.to_path_buf(),
+        })
+    });
+    let _ = unistd::close(newroot);
+
+    result
+}
+
+/// Move the rootfs mount onto / and chroot into it, see enter_rootfs.
+fn move_root(syscall: &dyn Syscall, rootfs: &Path) -> Result<()> {
+    let chdir = |path: &Path| {
+        unistd::chdir(path).map_err(|errno| MountError::Io {
+            source: errno.into(),
+            path: path.to_path_buf(),
+        })
+    };
+
+    // after the move, the rootfs is only reachable through the cwd
+    chdir(rootfs)?;
+    syscall.mount(Some(rootfs), Path::new("/"), None, MsFlags::MS_MOVE, None)?;
+    syscall.chroot(Path::new("."))?;
+    chdir(Path::new("/"))
+}
+
+/// Snapshot of the mounts at or below rootfs in the mount namespace of the
+/// calling thread, e.g. to check the mounts of a container once they are set
+/// up. rootfs has to be the path the mounts were made at, it isn't resolved.
+pub fn container_mounts(rootfs: &Path) -> Result<Vec<MountInfo>> {
+    // procfs takes the last component of the path as pid, which the
+    // /proc/thread-self link doesn't provide
+    let proc_dir = PathBuf::from(format!(
+        "/proc/{}/task/{}",
+        unistd::getpid(),
+        unistd::gettid()
+    ));
+    Ok(read_mount_infos(&proc_dir)?
+        .into_iter()
+        .filter(|mi| mi.mount_point.starts_with(rootfs))
+        .collect())
+}
+
+/// Check that no writable mount is nested under the destination of a later
+/// read-only mount. Mounts are set up in order, so the read-only mount would
+/// hide the writable one, which is unlikely to be intended.
+pub fn check_readonly_order(mounts: &[SpecMount]) -> Result<()> {
+    for (i, readonly) in mounts.iter().enumerate() {
+        if !parse_mount(readonly).flags.contains(MsFlags::MS_RDONLY) {
+            continue;
+        }
+
+        let dest = readonly.destination();
+        let shadowed = mounts[..i].iter().find(|m| {
+            m.destination() != dest
+                && m.destination().starts_with(dest)
+                && !parse_mount(m).flags.contains(MsFlags::MS_RDONLY)
+        });
+        if let Some(writable) = shadowed {
+            return Err(MountError::ShadowedByReadonly {
+                writable: writable.destination().clone(),
+                readonly: dest.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits label into the SELinux context option it is passed with and the
+/// context itself. A label without a context option is passed as `context`.
+fn selinux_context_option(label: &str) -> (&str, &str) {
+    label
+        .split_once('=')
+        .filter(|(option, _)| SELINUX_CONTEXT_OPTIONS.contains(option))
+        .unwrap_or(("context", label))
+}
+
+fn default_unlabeled_fs() -> Vec<String> {
+    DEFAULT_UNLABELED_FS
+        .iter()
+        .map(|fs| fs.to_string())
+        .collect()
+}
+
+/// Securely join a path of the container with the rootfs.
+fn join_rootfs(rootfs: &Path, path: &Path) -> Result<PathBuf> {
+    utils::secure_join(rootfs, path).map_err(|err| MountError::SecureJoin {
+        source: err,
+        root: rootfs.to_path_buf(),
+        path: path.to_path_buf(),
+    })
+}
+
+/// Check the type of mount against the allowed and denied mount types.
+/// Mounts without a type, like propagation changes, are not checked.
+fn check_mount_type(mount: &SpecMount, options: &MountOptions) -> Result<()> {
+    let typ = match mount.typ() {
+        Some(typ) => typ,
+        None => return Ok(()),
+    };
+
+    let allowed =
+        options.allowed_mount_types.is_empty() || options.allowed_mount_types.contains(typ);
+    if !allowed || options.denied_mount_types.contains(typ) {
+        return Err(MountError::TypeNotAllowed {
+            typ: typ.clone(),
+            dest: mount.destination().clone(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Check that options only proc accepts, like hidepid, are set on proc mounts
+/// only. Options like gid are accepted by other filesystems, e.g. devpts, as
+/// well, so they aren't checked.
+fn check_proc_options(mount: &SpecMount) -> Result<()> {
+    if mount.typ().as_deref() == Some("proc") {
+        return Ok(());
+    }
+
+    let proc_only = mount.options().iter().flatten().find(|option| {
+        let name = option
+            .split_once('=')
+            .map_or(option.as_str(), |(name, _)| name);
+        PROC_ONLY_OPTIONS.contains(&name)
+    });
+    match proc_only {
+        Some(option) => Err(MountError::ProcOnlyOption {
+            option: option.clone(),
+            dest: mount.destination().clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Check that the host has the hierarchies a cgroup mount asks for. A Legacy
+/// host lacks the unified hierarchy of a cgroup2 mount and a Unified host the
+/// v1 hierarchies of a cgroup mount. The cgroup setup of the host is only
+/// determined through get_setup for cgroup mounts.
+fn check_cgroup_mount_type<F>(mount: &SpecMount, get_setup: F) -> Result<()>
+where
+    F: FnOnce() -> std::result::Result<CgroupSetup, libcgroups::common::GetCgroupSetupError>,
+{
+    let typ = match mount.typ().as_deref() {
+        Some(typ @ ("cgroup" | "cgroup2")) => typ,
+        _ => return Ok(()),
+    };
+
+    match (typ, get_setup()?) {
+        ("cgroup2", Legacy) => Err(MountError::Cgroup2OnLegacy(mount.destination().clone())),
+        ("cgroup", Unified) => Err(MountError::CgroupOnUnified(mount.destination().clone())),
+        _ => Ok(()),
+    }
+}
+
+/// Resolve the source of a bind mount through canonicalize. In nested
+/// rootless containers a directory on the way to the source may not be
+/// searchable, so the source is only normalized lexically if canonicalize
+/// fails with EACCES or EPERM.
+fn resolve_bind_mount_source<F>(source: &Path, canonicalize: F) -> Result<PathBuf>
+where
+    F: FnOnce(&Path) -> std::io::Result<PathBuf>,
+{
+    match canonicalize(source) {
+        Ok(path) => Ok(path),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            Err(MountError::MissingBindSource(source.to_path_buf()))
+        }
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EACCES | libc::EPERM)) => {
+            let normalized = source.normalize();
+            log::warn!(
+                target: LOG_TARGET,
+                "skipped canonicalizing bind source {:?}, using {:?}: {}",
+                source,
+                normalized,
+                err
+            );
+            Ok(normalized)
+        }
+        Err(err) => Err(MountError::Io {
+            source: err,
+            path: source.to_path_buf(),
+        }),
+    }
+}
+
+/// Resolve a relative bind mount source against the bundle directory.
+/// Absolute sources and other mount types are returned unchanged.
+fn resolve_bind_source<'a>(mount: &'a SpecMount, bundle_dir: Option<&Path>) -> Cow<'a, SpecMount> {
+    match (mount.typ().as_deref(), mount.source(), bundle_dir) {
+        (Some("bind"), Some(source), Some(bundle_dir)) if source.is_relative() => {
+            let mut resolved = mount.clone();
+            resolved.set_source(Some(bundle_dir.join(source)));
+            Cow::Owned(resolved)
+        }
+        _ => Cow::Borrowed(mount),
+    }
+}
+
+/// Prepend the default options of the filesystem type of mount to its own
+/// options. parse_mount applies the options in order, so the options of the
+/// mount override the defaults.
+fn apply_type_defaults<'a>(
+    mount: &'a SpecMount,
+    type_defaults: Option<&HashMap<String, Vec<String>>>,
+) -> Cow<'a, SpecMount> {
+    let defaults = match (mount.typ(), type_defaults) {
+        (Some(typ), Some(type_defaults)) => type_defaults.get(typ),
+        _ => None,
+    };
+    match defaults {
+        Some(defaults) if !defaults.is_empty() => {
+            let mut options = defaults.clone();
+            options.extend(mount.options().iter().flatten().cloned());
+            let mut merged = mount.clone();
+            merged.set_options(Some(options));
+            Cow::Owned(merged)
+        }
+        _ => Cow::Borrowed(mount),
+    }
+}
+
+/// Mode of the directories created for mount destinations without a mode.
+const DEFAULT_MOUNT_DIR_MODE: u32 = 0o755;
+
+/// Path of fd in procfs, which refers to the file fd was opened for.
+fn proc_fd_path(fd: RawFd) -> PathBuf {
+    PathBuf::from(format!("/proc/self/fd/{fd}"))
+}
+
+/// Open dest, a path below rootfs, beneath root_fd, the fd rootfs refers to.
+/// Symlinks and .. escaping the rootfs fail the resolution.
+fn open_beneath(root_fd: RawFd, rootfs: &Path, dest: &Path) -> Result<RawFd> {
+    let relative = dest.strip_prefix(rootfs).unwrap_or(dest);
+    let how = linux::OpenHow {
+        flags: (libc::O_PATH | libc::O_CLOEXEC) as u64,
+        mode: 0,
+        resolve: linux::RESOLVE_BENEATH | linux::RESOLVE_NO_MAGICLINKS,
+    };
+
+    // an empty path is the rootfs itself
+    let path = if relative.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        relative
+    };
+    linux::openat2(root_fd, path, &how).map_err(|source| MountError::OpenBeneath {
+        source,
+        dest: dest.to_path_buf(),
+    })
+}
+
+/// Create a mount destination directory and its missing parents with mode,
+/// DEFAULT_MOUNT_DIR_MODE if unset. The mode is set explicitly after creating
+/// each directory, so it doesn't depend on the umask of the process.
+fn create_mount_dir(dir: &Path, mode: Option<u32>) -> Result<()> {
+    let mode = mode.unwrap_or(DEFAULT_MOUNT_DIR_MODE);
+    let missing: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|dir| fs::symlink_metadata(dir).is_err())
+        .collect();
+
+    for dir in missing.into_iter().rev() {
+        let res = match DirBuilder::new().mode(mode).create(dir) {
+            // a symlink swapped in for the new directory is not followed
+            Ok(()) => OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NOFOLLOW | libc::O_DIRECTORY)
+                .open(dir)
+                .and_then(|dir| dir.set_permissions(fs::Permissions::from_mode(mode))),
+            // created in the meantime, e.g. by a concurrent mount
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && dir.is_dir() => Ok(()),
+            Err(err) => Err(err),
+        };
+        res.map_err(|err| MountError::Io {
+            source: err,
+            path: dir.to_path_buf(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Create an empty file as the mount point of a bind mount of a file. A
+/// symlink at dest is not followed, it could point outside of the rootfs.
+fn create_mount_file(dest: &Path) -> Result<()> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(dest)
+        .map_err(|err| match err.raw_os_error() {
+            Some(libc::ELOOP) => MountError::DestinationIsSymlink(dest.to_path_buf()),
+            _ => MountError::Io {
+                source: err,
+                path: dest.to_path_buf(),
+            },
+        })?;
+
+    Ok(())
+}
+
+/// Verify that the canonical path of a mount destination still lives inside
+/// the rootfs, so a mount can't be redirected to the host through a symlink.
+fn ensure_dest_in_rootfs(rootfs: &Path, dest: &Path) -> Result<()> {
+    let rootfs = canonicalize(rootfs).map_err(|err| MountError::Io {
+        source: err,
+        path: rootfs.to_path_buf(),
+    })?;
+    let resolved = canonicalize(dest).map_err(|err| MountError::Io {
+        source: err,
+        path: dest.to_path_buf(),
+    })?;
+
+    if !resolved.starts_with(&rootfs) {
+        return Err(MountError::EscapesRootfs {
+            dest: dest.to_path_buf(),
+            resolved,
+            rootfs,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks if dest is the rootfs itself, directly or once symlinks are resolved.
+fn is_rootfs(rootfs: &Path, dest: &Path) -> bool {
+    if dest == rootfs {
+        return true;
+    }
+
+    match (canonicalize(rootfs), canonicalize(dest)) {
+        (Ok(rootfs), Ok(dest)) => rootfs == dest,
+        _ => false,
+    }
+}
+
+/// The kernel copies at most a page of mount data, including the terminating
+/// nul byte, and silently drops the rest.
+fn mount_data_limit() -> usize {
+    match unistd::sysconf(unistd::SysconfVar::PAGE_SIZE) {
+        Ok(Some(page_size)) => page_size as usize - 1,
+        _ => 4095,
+    }
+}
+
+/// Reject mount data the kernel would truncate, which leads to confusing
+/// errors or options silently not being applied.
+fn check_data_len(m: &SpecMount, data: &str, max: usize) -> Result<()> {
+    if data.len() <= max {
+        return Ok(());
+    }
+
+    let dest = m.destination().clone();
+    let len = data.len();
+    Err(match m.typ().as_deref() {
+        Some("overlay") => MountError::OverlayDataTooLong { dest, len, max },
+        _ => MountError::DataTooLong { dest, len, max },
+    })
+}
+
+/// Resolve the directories of overlay mount data (lowerdir, upperdir and
+/// workdir) against the rootfs. Relative entries are joined securely with the
+/// rootfs, upperdir and workdir are created if missing and create_dirs is set,
+/// and the option string is reassembled with the resolved paths.
+fn resolve_overlay_data(rootfs: &Path, data: &str, create_dirs: bool) -> Result<String> {
+    let mut options = Vec::new();
+    let mut has_lowerdir = false;
+    let mut has_upperdir = false;
+    let mut has_workdir = false;
+
+    for option in data.split(',').filter(|o| !o.is_empty()) {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, value),
+            None => {
+                options.push(option.to_string());
+                continue;
+            }
+        };
+
+        match key {
+            "lowerdir" => {
+                let lowerdirs = split_overlay_lowerdir(value)
+                    .iter()
+                    .map(|dir| {
+                        if dir.is_empty() {
+                            return Err(MountError::InvalidOverlay(format!(
+                                "empty entry in lowerdir {value:?}"
+                            )));
+                        }
+                        let dir = resolve_overlay_dir(rootfs, dir)?;
+                        Ok(escape_overlay_dir(&dir))
+                    })
+                    .collect::<Result<Vec<String>>>()?;
+                has_lowerdir = true;
+                options.push(format!("lowerdir={}", lowerdirs.join(":")));
+            }
+            "upperdir" | "workdir" => {
+                let dir = resolve_overlay_dir(rootfs, value)?;
+                if create_dirs {
+                    create_dir_all(&dir).map_err(|err| MountError::Io {
+                        source: err,
+                        path: dir.clone(),
+                    })?;
+                }
+                if key == "upperdir" {
+                    has_upperdir = true;
+                } else {
+                    has_workdir = true;
+                }
+                options.push(format!("{key}={}", escape_overlay_dir(&dir)));
+            }
+            _ => options.push(option.to_string()),
+        }
+    }
+
+    if !has_lowerdir {
+        return Err(MountError::InvalidOverlay(
+            "a lowerdir option is required".to_string(),
+        ));
+    }
+
+    if has_upperdir && !has_workdir {
+        return Err(MountError::InvalidOverlay(
+            "a workdir option is required with an upperdir".to_string(),
+        ));
+    }
+
+    Ok(options.join(","))
+}
+
+/// Split an overlay lowerdir value on unescaped colons. A backslash escapes
+/// the following character, e.g. `/a\:b:/c` is `/a:b` and `/c`.
+fn split_overlay_lowerdir(lowerdir: &str) -> Vec<String> {
+    let mut dirs = Vec::new();
+    let mut current = String::new();
+    let mut chars = lowerdir.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            ':' => dirs.push(mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    dirs.push(current);
+
+    dirs
+}
+
+fn resolve_overlay_dir(rootfs: &Path, dir: &str) -> Result<PathBuf> {
+    let path = Path::new(dir);
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+
+    utils::secure_join(rootfs, path).map_err(|err| MountError::SecureJoin {
+        source: err,
+        root: rootfs.to_path_buf(),
+        path: path.to_path_buf(),
+    })
+}
+
+fn escape_overlay_dir(dir: &Path) -> String {
+    dir.to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace(':', "\\:")
+}
+
+/// Options shared by all filesystems mounted for the cgroup v1 hierarchy.
+#[cfg(feature = "v1")]
+const CGROUP_V1_MOUNT_OPTIONS: [&str; 3] = ["noexec", "nosuid", "nodev"];
+
+/// Build the tmpfs mount holding the cgroup v1 hierarchies. The given options
+/// are appended to the common cgroup mount options.
+#[cfg(feature = "v1")]
+fn tmpfs_mount(destination: &Path, options: Vec<String>) -> Result<SpecMount> {
+    Ok(SpecMountBuilder::default()
+        .source("tmpfs")
+        .typ("tmpfs")
+        .destination(destination)
+        .options(
+            CGROUP_V1_MOUNT_OPTIONS
+                .iter()
+                .map(|o| o.to_string())
+                .chain(options)
+                .collect::<Vec<String>>(),
+        )
+        .build()?)
+}
+
+/// Whether the options of mount make it read-only, which the cgroup v1
+/// subsystems mounted for a cgroup mount have to follow.
+#[cfg(feature = "v1")]
+fn is_readonly(mount: &SpecMount) -> bool {
+    parse_mount(mount).flags.contains(MsFlags::MS_RDONLY)
+}
+
+/// Build the mount of a single cgroup v1 subsystem hierarchy.
+#[cfg(feature = "v1")]
+fn cgroup_subsystem_mount(destination: &Path) -> Result<SpecMount> {
+    Ok(SpecMountBuilder::default()
+        .source("cgroup")
+        .typ("cgroup")
+        .destination(destination)
+        .options(
+            CGROUP_V1_MOUNT_OPTIONS
+                .iter()
+                .map(|o| o.to_string())
+                .collect::<Vec<String>>(),
+        )
+        .build()?)
+}
+
+/// Build a bind mount of a host cgroup directory into the container.
+#[cfg(any(feature = "v1", feature = "v2"))]
+fn bind_mount(source: &Path, destination: &Path, options: &[&str]) -> Result<SpecMount> {
+    Ok(SpecMountBuilder::default()
+        .source(source)
+        .typ("bind")
+        .destination(destination)
+        .options(
+            options
+                .iter()
+                .map(|o| o.to_string())
+                .collect::<Vec<String>>(),
+        )
+        .build()?)
 }
 
 #[cfg(test)]
@@ -479,8 +2304,10 @@ mod tests {
     use std::fs;
 
     use super::*;
-    use crate::syscall::test::{MountArgs, TestHelperSyscall};
-    use anyhow::Result;
+    use crate::syscall::test::{ArgName, MountArgs, TestHelperSyscall, UmountArgs, UsernsFdArgs};
+    use anyhow::{Context, Result};
+    use oci_spec::runtime::LinuxIdMappingBuilder;
+    use serial_test::serial;
 
     #[test]
     fn test_mount_to_container() {
@@ -579,16 +2406,2085 @@ mod tests {
     }
 
     #[test]
-    fn test_make_parent_mount_private() {
-        let tmp_dir = tempfile::tempdir().unwrap();
+    fn test_bind_mount_socket_into_container() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let socket_path = tmp_dir.path().join("host.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket_path)?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
         let m = Mount::new();
-        let result = m.make_parent_mount_private(tmp_dir.path());
-        assert!(result.is_ok());
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/run/app.sock"))
+            .typ("bind")
+            .source(&socket_path)
+            .options(vec!["bind".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
 
-        if let Ok(Some(_)) = result {
-            let set = m
-                .syscall
-                .as_any()
+        m.mount_into_container(mount, &rootfs, &mount_option_config, None)?;
+
+        let dest = rootfs.join("run/app.sock");
+        assert!(dest.is_file());
+        let want = vec![MountArgs {
+            source: Some(socket_path),
+            target: dest,
+            fstype: Some("bind".to_string()),
+            flags: MsFlags::MS_BIND,
+            data: Some("".to_string()),
+        }];
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(want, got);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_bind_mount_source() -> Result<()> {
+        let source = Path::new("/home/user/../user/./data");
+        let denied =
+            |errno| move |_: &Path| Err::<PathBuf, _>(std::io::Error::from_raw_os_error(errno));
+
+        assert_eq!(
+            resolve_bind_mount_source(source, |_| Ok(PathBuf::from("/data")))?,
+            PathBuf::from("/data")
+        );
+
+        // permission errors fall back to the normalized source
+        for errno in [libc::EACCES, libc::EPERM] {
+            assert_eq!(
+                resolve_bind_mount_source(source, denied(errno))?,
+                PathBuf::from("/home/user/data")
+            );
+        }
+
+        let err = resolve_bind_mount_source(source, denied(libc::ENOENT)).unwrap_err();
+        assert!(matches!(err, MountError::MissingBindSource(ref path) if path == source));
+        let err = resolve_bind_mount_source(source, denied(libc::EIO)).unwrap_err();
+        assert!(matches!(err, MountError::Io { ref path, .. } if path == source));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_cgroup_mount_type() -> Result<()> {
+        let cgroup_mount = |typ: &str| {
+            SpecMountBuilder::default()
+                .destination(PathBuf::from("/sys/fs/cgroup"))
+                .typ(typ)
+                .source(PathBuf::from(typ))
+                .build()
+        };
+
+        // a cgroup2 mount on a host without a unified hierarchy
+        let err = check_cgroup_mount_type(&cgroup_mount("cgroup2")?, || Ok(Legacy)).unwrap_err();
+        assert!(
+            matches!(err, MountError::Cgroup2OnLegacy(ref dest) if dest == Path::new("/sys/fs/cgroup"))
+        );
+        assert!(err
+            .to_string()
+            .contains("change the type of the mount to cgroup"));
+        check_cgroup_mount_type(&cgroup_mount("cgroup2")?, || Ok(Hybrid))?;
+        check_cgroup_mount_type(&cgroup_mount("cgroup2")?, || Ok(Unified))?;
+
+        // a cgroup mount on a host without the v1 hierarchies
+        let err = check_cgroup_mount_type(&cgroup_mount("cgroup")?, || Ok(Unified)).unwrap_err();
+        assert!(
+            matches!(err, MountError::CgroupOnUnified(ref dest) if dest == Path::new("/sys/fs/cgroup"))
+        );
+        assert!(err
+            .to_string()
+            .contains("change the type of the mount to cgroup2"));
+        check_cgroup_mount_type(&cgroup_mount("cgroup")?, || Ok(Legacy))?;
+        check_cgroup_mount_type(&cgroup_mount("cgroup")?, || Ok(Hybrid))?;
+
+        // the setup isn't determined for other mounts
+        check_cgroup_mount_type(&cgroup_mount("tmpfs")?, || unreachable!())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_setup_mount_relative_bind_source() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let bundle = tmp_dir.path().join("bundle");
+        create_dir_all(bundle.join("data"))?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("data"))
+            .options(vec!["bind".to_string()])
+            .build()?;
+        let options = MountOptions {
+            bundle_dir: Some(&bundle),
+            ..MountOptions::new(&rootfs)
+        };
+
+        m.setup_mount(mount, &options)?;
+
+        let want = vec![MountArgs {
+            source: Some(canonicalize(bundle.join("data"))?),
+            target: rootfs.join("data"),
+            fstype: Some("bind".to_string()),
+            flags: MsFlags::MS_BIND,
+            data: Some("".to_string()),
+        }];
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(want, got);
+
+        // absolute sources are not touched
+        let absolute = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(bundle.join("data"))
+            .build()?;
+        assert!(matches!(
+            resolve_bind_source(absolute, Some(Path::new("/other"))),
+            Cow::Borrowed(_)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_destination() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        create_dir_all(rootfs.join("var/data"))?;
+        // absolute symlinks are resolved against the rootfs, not the host
+        std::os::unix::fs::symlink("/var/data", rootfs.join("data"))?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data/cache"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .build()?;
+
+        let resolved = m.resolve_destination(rootfs, mount)?;
+        assert!(!resolved.exists());
+        m.mount_into_container(mount, rootfs, &parse_mount(mount), None)?;
+
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].target, resolved);
+        assert!(resolved.ends_with("var/data/cache"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_mount_file() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let outside = tempfile::tempdir()?;
+
+        let dest = tmp_dir.path().join("resolv.conf");
+        create_mount_file(&dest)?;
+        assert!(dest.is_file());
+        // an existing mount point is fine
+        create_mount_file(&dest)?;
+
+        let link = tmp_dir.path().join("hosts");
+        std::os::unix::fs::symlink(outside.path().join("hosts"), &link)?;
+        let err = create_mount_file(&link).unwrap_err();
+        assert!(
+            matches!(&err, MountError::DestinationIsSymlink(path) if *path == link),
+            "{err}"
+        );
+        assert!(!outside.path().join("hosts").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_data_len() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let max = mount_data_limit();
+        assert!(max >= 4095);
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec![format!("x-option={}", "a".repeat(max))])
+            .build()?;
+        let err = m
+            .mount_into_container(mount, tmp_dir.path(), &parse_mount(mount), None)
+            .unwrap_err();
+        assert!(
+            matches!(&err, MountError::DataTooLong { len, .. } if *len == max + 9),
+            "{err}"
+        );
+        assert!(m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args()
+            .is_empty());
+
+        let overlay = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("overlay")
+            .source(PathBuf::from("overlay"))
+            .build()?;
+        let err = check_data_len(overlay, &"a".repeat(11), 10).unwrap_err();
+        assert!(err.to_string().contains("lowerdir"), "{err}");
+        check_data_len(overlay, &"a".repeat(10), 10)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allowed_and_denied_mount_types() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let allowed = vec!["tmpfs".to_string(), "proc".to_string()];
+        let denied = vec!["proc".to_string()];
+        let options = MountOptions {
+            allowed_mount_types: &allowed,
+            denied_mount_types: &denied,
+            ..MountOptions::new(tmp_dir.path())
+        };
+        let mount = |destination: &str, typ: &str| {
+            SpecMountBuilder::default()
+                .destination(PathBuf::from(destination))
+                .typ(typ)
+                .source(PathBuf::from(typ))
+                .build()
+        };
+
+        let m = Mount::new();
+        m.setup_mount(&mount("/tmp", "tmpfs")?, &options)?;
+
+        // denied although it is allowed
+        let err = m
+            .setup_mount(&mount("/proc", "proc")?, &options)
+            .unwrap_err();
+        assert!(
+            matches!(&err, MountError::TypeNotAllowed { typ, dest } if typ == "proc" && dest == Path::new("/proc")),
+            "{err}"
+        );
+
+        // not in the allowlist
+        let err = m
+            .setup_mount(&mount("/mnt", "fuse")?, &options)
+            .unwrap_err();
+        assert!(err.to_string().contains("\"fuse\""), "{err}");
+
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].target, tmp_dir.path().join("tmp"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_defaults() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let type_defaults = HashMap::from([(
+            "tmpfs".to_string(),
+            vec!["nosuid".to_string(), "nodev".to_string()],
+        )]);
+        let options = MountOptions {
+            type_defaults: Some(&type_defaults),
+            ..MountOptions::new(tmp_dir.path())
+        };
+        let tmpfs = |options: Vec<&str>| {
+            SpecMountBuilder::default()
+                .destination(PathBuf::from("/tmp"))
+                .typ("tmpfs")
+                .source(PathBuf::from("tmpfs"))
+                .options(options.into_iter().map(String::from).collect::<Vec<_>>())
+                .build()
+        };
+
+        // the defaults are added to the options of the mount
+        let m = Mount::new();
+        m.setup_mount(&tmpfs(vec!["noexec"])?, &options)?;
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(
+            got[0].flags,
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC
+        );
+
+        // and overridden by them
+        let m = Mount::new();
+        m.setup_mount(&tmpfs(vec!["dev"])?, &options)?;
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].flags, MsFlags::MS_NOSUID);
+
+        // other types are left alone
+        let proc = SpecMountBuilder::default()
+            .destination(PathBuf::from("/proc"))
+            .typ("proc")
+            .source(PathBuf::from("proc"))
+            .build()?;
+        assert!(matches!(
+            apply_type_defaults(&proc, Some(&type_defaults)),
+            Cow::Borrowed(_)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_setup_mount_proc_hidepid() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let proc_mount = |typ: &str| {
+            SpecMountBuilder::default()
+                .destination(PathBuf::from("/proc"))
+                .typ(typ)
+                .source(PathBuf::from(typ))
+                .options(vec![
+                    "nosuid".to_string(),
+                    "hidepid=2".to_string(),
+                    "gid=1000".to_string(),
+                ])
+                .build()
+        };
+        let options = MountOptions {
+            label: Some("system_u:object_r:container_file_t:s0"),
+            ..MountOptions::new(tmp_dir.path())
+        };
+
+        // the options are passed on as they are, without the label
+        let m = Mount::new();
+        m.setup_mount(&proc_mount("proc")?, &options)?;
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].flags, MsFlags::MS_NOSUID);
+        assert_eq!(got[0].data.as_deref(), Some("hidepid=2,gid=1000"));
+
+        let m = Mount::new();
+        let err = m.setup_mount(&proc_mount("tmpfs")?, &options).unwrap_err();
+        assert!(
+            matches!(&err, MountError::ProcOnlyOption { option, dest } if option == "hidepid=2" && dest == Path::new("/proc")),
+            "{err}"
+        );
+
+        // gid alone is fine for other filesystems
+        let devpts = SpecMountBuilder::default()
+            .destination(PathBuf::from("/dev/pts"))
+            .typ("devpts")
+            .source(PathBuf::from("devpts"))
+            .options(vec!["gid=5".to_string()])
+            .build()?;
+        check_proc_options(&devpts)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_into_container_rejects_rootfs_destination() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        for destination in ["/", "/..", "/tmp/.."] {
+            let mount = &SpecMountBuilder::default()
+                .destination(PathBuf::from(destination))
+                .typ("tmpfs")
+                .source(PathBuf::from("tmpfs"))
+                .build()?;
+
+            let err = m
+                .mount_into_container(mount, &rootfs, &parse_mount(mount), None)
+                .unwrap_err();
+            assert!(
+                matches!(&err, MountError::DestinationIsRootfs(dest) if dest == Path::new(destination)),
+                "{err}"
+            );
+        }
+
+        // changing the propagation of the rootfs is still allowed
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/"))
+            .options(vec!["rslave".to_string()])
+            .build()?;
+        m.mount_into_container(mount, &rootfs, &parse_mount(mount), None)?;
+
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].flags, MsFlags::MS_SLAVE | MsFlags::MS_REC);
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_create_mount_dir_ignores_umask() -> Result<()> {
+        use nix::sys::stat::umask;
+
+        let tmp_dir = tempfile::tempdir()?;
+        let modes_with_umask = |mask: u32| -> Result<Vec<u32>> {
+            let base = tmp_dir.path().join(format!("{mask:o}"));
+            let old = umask(Mode::from_bits_truncate(mask));
+            let res = create_mount_dir(&base.join("default/dir"), None)
+                .and_then(|_| create_mount_dir(&base.join("mode/dir"), Some(0o775)));
+            umask(old);
+            res?;
+
+            ["default", "default/dir", "mode", "mode/dir"]
+                .into_iter()
+                .map(|dir| Ok(fs::metadata(base.join(dir))?.permissions().mode() & 0o7777))
+                .collect()
+        };
+
+        let want = vec![0o755, 0o755, 0o775, 0o775];
+        assert_eq!(modes_with_umask(0o022)?, want);
+        assert_eq!(modes_with_umask(0o077)?, want);
+
+        // existing directories are left alone
+        create_mount_dir(tmp_dir.path(), Some(0o755))?;
+        assert_eq!(
+            fs::metadata(tmp_dir.path())?.permissions().mode() & 0o777,
+            0o700
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_into_container_dir_mode() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/dev/hugepages/data"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .build()?;
+        let mut mount_option_config = parse_mount(mount);
+        mount_option_config.dir_mode = Some(0o750);
+
+        m.mount_into_container(mount, &rootfs, &mount_option_config, None)?;
+
+        for dir in ["dev", "dev/hugepages", "dev/hugepages/data"] {
+            let mode = std::fs::metadata(rootfs.join(dir))?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o750, "unexpected mode of {dir}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_setup_mount_root_fd() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        let root_fd = fcntl::open(
+            rootfs,
+            OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .build()?;
+        let options = MountOptions {
+            root_fd: Some(root_fd),
+            // the path is not used with a rootfs fd
+            ..MountOptions::new(Path::new("/nonexistent"))
+        };
+
+        let result = m.setup_mount(mount, &options);
+        unistd::close(root_fd)?;
+        result?;
+
+        assert!(rootfs.join("data").is_dir());
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        // mounted on the fd of the destination opened beneath the rootfs fd
+        assert!(got[0].target.starts_with("/proc/self/fd"), "{got:?}");
+        assert_ne!(got[0].target, proc_fd_path(root_fd));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_beneath() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        create_dir_all(rootfs.join("data"))?;
+        std::os::unix::fs::symlink("/", rootfs.join("absolute"))?;
+        std::os::unix::fs::symlink("../..", rootfs.join("up"))?;
+        let root_fd = fcntl::open(
+            rootfs,
+            OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )?;
+
+        let check = || -> Result<()> {
+            let fd = open_beneath(root_fd, rootfs, &rootfs.join("data"))?;
+            assert_eq!(
+                fs::read_link(proc_fd_path(fd))?,
+                canonicalize(rootfs.join("data"))?
+            );
+            unistd::close(fd)?;
+
+            let fd = open_beneath(root_fd, rootfs, rootfs)?;
+            unistd::close(fd)?;
+
+            // symlinks leading out of the rootfs are not followed
+            for escape in ["absolute/etc", "up/etc"] {
+                let err = open_beneath(root_fd, rootfs, &rootfs.join(escape)).unwrap_err();
+                assert!(
+                    matches!(err, MountError::OpenBeneath { ref dest, .. } if *dest == rootfs.join(escape)),
+                    "{err}"
+                );
+            }
+            Ok(())
+        };
+        let result = check();
+        unistd::close(root_fd)?;
+
+        result
+    }
+
+    #[test]
+    fn test_setup_mount_x_mount_mkdir() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data/cache"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec!["X-mount.mkdir=0700".to_string()])
+            .build()?;
+        let options = MountOptions::new(&rootfs);
+
+        m.setup_mount(mount, &options)?;
+
+        for dir in ["data", "data/cache"] {
+            let mode = std::fs::metadata(rootfs.join(dir))?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o700, "unexpected mode of {dir}");
+        }
+
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].data, Some("".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_setup_all_mounts() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+
+        let m = Mount::new();
+        let mounts = vec![
+            SpecMountBuilder::default()
+                .destination(PathBuf::from("/tmp"))
+                .typ("tmpfs")
+                .source(PathBuf::from("tmpfs"))
+                .build()?,
+            SpecMountBuilder::default()
+                .destination(PathBuf::from("/run/lock"))
+                .typ("tmpfs")
+                .source(PathBuf::from("tmpfs"))
+                .build()?,
+        ];
+        let options = MountOptions::new(rootfs);
+
+        let got = m.setup_all_mounts(&mounts, &options)?;
+
+        assert_eq!(vec![rootfs.join("tmp"), rootfs.join("run/lock")], got);
+        let targets: Vec<PathBuf> = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args()
+            .into_iter()
+            .map(|args| args.target)
+            .collect();
+        assert_eq!(got, targets);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_groups() -> Result<()> {
+        let mounts = [
+            "/proc",
+            "/dev",
+            "/dev/pts",
+            "/sys",
+            "/dev/shm",
+            "/sys/fs/cgroup",
+            "/a/b",
+            "/a/c",
+            "/a",
+            "/ab",
+        ]
+        .into_iter()
+        .map(|dest| {
+            SpecMountBuilder::default()
+                .destination(PathBuf::from(dest))
+                .typ("tmpfs")
+                .source(PathBuf::from("tmpfs"))
+                .build()
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        assert_eq!(
+            mount_groups(&mounts),
+            vec![vec![0], vec![1, 2, 4], vec![3, 5], vec![6, 7, 8], vec![9]]
+        );
+        assert!(mount_groups(&[]).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_setup_all_mounts_parallel() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+
+        let m = Mount::new();
+        let mounts = ["/tmp", "/run", "/run/lock", "/dev/shm", "/mnt/data"]
+            .into_iter()
+            .map(|dest| {
+                SpecMountBuilder::default()
+                    .destination(PathBuf::from(dest))
+                    .typ("tmpfs")
+                    .source(PathBuf::from("tmpfs"))
+                    .build()
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let options = MountOptions::new(rootfs);
+
+        for threads in [0, 1, 3, 16] {
+            let got = m.setup_all_mounts_parallel(&mounts, &options, threads)?;
+            let want: Vec<PathBuf> = ["tmp", "run", "run/lock", "dev/shm", "mnt/data"]
+                .into_iter()
+                .map(|dest| rootfs.join(dest))
+                .collect();
+            assert_eq!(want, got, "{threads} threads");
+            assert!(got.iter().all(|dest| dest.is_dir()));
+        }
+
+        // a failing mount is reported like by setup_all_mounts
+        let mut invalid = mounts.clone();
+        invalid.push(
+            SpecMountBuilder::default()
+                .destination(PathBuf::from("/missing"))
+                .typ("bind")
+                .source(tmp_dir.path().join("missing"))
+                .build()?,
+        );
+        let err = m
+            .setup_all_mounts_parallel(&invalid, &options, 2)
+            .unwrap_err();
+        assert!(
+            matches!(&err, MountError::Setup { index: 5, dest, .. } if dest == Path::new("/missing")),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mask_path() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        create_dir_all(rootfs.join("proc/acpi"))?;
+        std::fs::write(rootfs.join("proc/kcore"), "")?;
+
+        let m = Mount::new();
+        m.mask_path(rootfs, Path::new("/proc/kcore"))?;
+        m.mask_path(rootfs, Path::new("/proc/acpi"))?;
+        m.mask_path(rootfs, Path::new("/proc/missing"))?;
+
+        let want = vec![
+            MountArgs {
+                source: Some(PathBuf::from("/dev/null")),
+                target: rootfs.join("proc/kcore"),
+                fstype: None,
+                flags: MsFlags::MS_BIND,
+                data: None,
+            },
+            MountArgs {
+                source: Some(PathBuf::from("tmpfs")),
+                target: rootfs.join("proc/acpi"),
+                fstype: Some("tmpfs".to_string()),
+                flags: MsFlags::MS_RDONLY,
+                data: None,
+            },
+        ];
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(want, got);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_make_readonly() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        create_dir_all(rootfs.join("proc/sys"))?;
+
+        let m = Mount::new();
+        m.make_readonly(rootfs, Path::new("/proc/sys"))?;
+        m.make_readonly(rootfs, Path::new("/proc/missing"))?;
+
+        let dest = rootfs.join("proc/sys");
+        let want = vec![
+            MountArgs {
+                source: Some(dest.clone()),
+                target: dest.clone(),
+                fstype: None,
+                flags: MsFlags::MS_BIND | MsFlags::MS_REC,
+                data: None,
+            },
+            MountArgs {
+                source: Some(dest.clone()),
+                target: dest,
+                fstype: None,
+                flags: MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY,
+                data: None,
+            },
+        ];
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(want, got);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tmpcopyup() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        let dest = rootfs.join("etc");
+        create_dir_all(dest.join("conf.d"))?;
+        std::fs::write(dest.join("hostname"), "container")?;
+        std::fs::write(dest.join("conf.d/app.conf"), "debug")?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/etc"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec!["tmpcopyup".to_string(), "ro".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+        assert!(mount_option_config.copy_up);
+        assert_eq!("", mount_option_config.data);
+
+        m.mount_into_container(mount, rootfs, &mount_option_config, None)?;
+
+        assert_eq!("container", std::fs::read_to_string(dest.join("hostname"))?);
+        assert_eq!(
+            "debug",
+            std::fs::read_to_string(dest.join("conf.d/app.conf"))?
+        );
+        // mounted writable to copy the contents, then remounted read-only
+        let flags: Vec<MsFlags> = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args()
+            .into_iter()
+            .map(|args| args.flags)
+            .collect();
+        assert_eq!(
+            vec![MsFlags::empty(), MsFlags::MS_RDONLY | MsFlags::MS_REMOUNT],
+            flags
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tmpcopyup_rollback() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        let dest = rootfs.join("etc");
+        create_dir_all(&dest)?;
+        std::fs::write(dest.join("hostname"), "container")?;
+
+        let m = Mount::new();
+        let mocks = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        // the tmpfs mount succeeds, the read-only remount fails
+        mocks.set_ret_err(ArgName::Mount, || {
+            Err(SyscallError::Mount {
+                source: Errno::EPERM,
+            })
+        });
+        mocks.set_ret_err_after(ArgName::Mount, 1);
+
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/etc"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec!["tmpcopyup".to_string(), "ro".to_string()])
+            .build()?;
+        assert!(m
+            .mount_into_container(mount, rootfs, &parse_mount(mount), None)
+            .is_err());
+
+        assert_eq!(mocks.get_mount_args().len(), 1);
+        let got = mocks.get_umount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].target, dest.canonicalize()?);
+        assert_eq!(got[0].flags, MntFlags::MNT_DETACH);
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_enter_rootfs_pivot_root() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let cwd = std::env::current_dir()?;
+
+        let syscall = create_syscall();
+        let res = enter_rootfs(syscall.as_ref(), tmp_dir.path(), false);
+        std::env::set_current_dir(cwd)?;
+        res?;
+
+        let mocks = syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert_eq!(
+            vec![(tmp_dir.path().to_path_buf(), tmp_dir.path().to_path_buf())],
+            mocks.get_pivot_root_args()
+        );
+        let want = vec![MountArgs {
+            source: None,
+            target: PathBuf::from("/"),
+            fstype: None,
+            flags: MsFlags::MS_SLAVE | MsFlags::MS_REC,
+            data: None,
+        }];
+        assert_eq!(want, mocks.get_mount_args());
+        assert_eq!(
+            vec![UmountArgs {
+                target: PathBuf::from("/"),
+                flags: MntFlags::MNT_DETACH,
+            }],
+            mocks.get_umount_args()
+        );
+        assert!(mocks.get_chroot_args().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_enter_rootfs_no_pivot() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let cwd = std::env::current_dir()?;
+
+        let m = Mount::new();
+        let res = m.enter_rootfs(tmp_dir.path(), true);
+        std::env::set_current_dir(cwd)?;
+        res?;
+
+        let mocks = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        let want = vec![MountArgs {
+            source: Some(tmp_dir.path().to_path_buf()),
+            target: PathBuf::from("/"),
+            fstype: None,
+            flags: MsFlags::MS_MOVE,
+            data: None,
+        }];
+        assert_eq!(want, mocks.get_mount_args());
+        assert_eq!(vec![PathBuf::from(".")], mocks.get_chroot_args());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_label() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        let label = Some("system_u:object_r:container_file_t:s0");
+
+        let mount_with_label = |m: &Mount, typ: &str, dest: &str| -> Result<Option<String>> {
+            let mount = &SpecMountBuilder::default()
+                .destination(PathBuf::from(dest))
+                .typ(typ)
+                .source(PathBuf::from(typ))
+                .build()?;
+            m.mount_into_container(mount, rootfs, &parse_mount(mount), label)?;
+            let args = m
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap()
+                .get_mount_args();
+            Ok(args.last().unwrap().data.clone())
+        };
+
+        let m = Mount::new();
+        assert_eq!(
+            Some("context=\"system_u:object_r:container_file_t:s0\"".to_string()),
+            mount_with_label(&m, "ext4", "/data")?
+        );
+        assert_eq!(
+            Some("".to_string()),
+            mount_with_label(&m, "mqueue", "/dev/mqueue")?
+        );
+
+        let m = Mount::new().with_unlabeled_fs(&["ext4"]);
+        assert_eq!(Some("".to_string()), mount_with_label(&m, "ext4", "/data")?);
+        assert!(mount_with_label(&m, "mqueue", "/dev/mqueue")?
+            .unwrap()
+            .starts_with("context="));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_into_container_retries_busy() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/tmp"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .build()?;
+        fn busy() -> std::result::Result<(), SyscallError> {
+            Err(SyscallError::Mount {
+                source: Errno::EBUSY,
+            })
+        }
+
+        // busy once, the retry succeeds
+        let m = Mount::new();
+        let mocks = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        mocks.set_ret_err(ArgName::Mount, busy);
+        m.mount_into_container(mount, rootfs, &parse_mount(mount), None)?;
+        let got = mocks.get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].target, rootfs.join("tmp"));
+
+        // still busy on the retry
+        let m = Mount::new();
+        let mocks = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        mocks.set_ret_err(ArgName::Mount, busy);
+        mocks.set_ret_err_times(ArgName::Mount, 2);
+        let err = m
+            .mount_into_container(mount, rootfs, &parse_mount(mount), None)
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                MountError::Syscall(SyscallError::Mount {
+                    source: Errno::EBUSY
+                })
+            ),
+            "{err}"
+        );
+        assert!(mocks.get_mount_args().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_label_context_options() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("ext4")
+            .source(PathBuf::from("/dev/sda1"))
+            .options(vec!["noatime".to_string()])
+            .build()?;
+
+        for option in ["context", "fscontext", "defcontext", "rootcontext"] {
+            let m = Mount::new();
+            let label = format!("{option}=system_u:object_r:container_file_t:s0");
+            m.mount_into_container(mount, rootfs, &parse_mount(mount), Some(&label))?;
+            let got = m
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap()
+                .get_mount_args();
+            assert_eq!(
+                Some(format!(
+                    "{option}=\"system_u:object_r:container_file_t:s0\""
+                )),
+                got[0].data,
+            );
+        }
+
+        // anything else before a = isn't a context option
+        assert_eq!(("context", "foo=bar"), selinux_context_option("foo=bar"));
+        assert_eq!(
+            ("context", "system_u:object_r:container_file_t:s0"),
+            selinux_context_option("system_u:object_r:container_file_t:s0")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_cgroup_mount() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let options = MountOptions {
+            skip_cgroup_mount: true,
+            ..MountOptions::new(tmp_dir.path())
+        };
+
+        let m = Mount::new();
+        for typ in ["cgroup", "cgroup2"] {
+            let mount = SpecMountBuilder::default()
+                .destination(PathBuf::from("/sys/fs/cgroup"))
+                .typ(typ)
+                .source(PathBuf::from(typ))
+                .build()?;
+            m.setup_mount(&mount, &options)?;
+        }
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert!(syscall.get_mount_args().is_empty());
+        assert!(!tmp_dir.path().join("sys/fs/cgroup").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_warn_if_slow() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .build()?;
+        let options = MountOptions {
+            slow_mount_threshold: Some(Duration::from_millis(10)),
+            ..MountOptions::new(tmp_dir.path())
+        };
+
+        let m = Mount::new();
+        m.syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .set_delay(ArgName::Mount, Duration::from_millis(50));
+        let start = Instant::now();
+        m.setup_all_mounts(&[mount.clone()], &options)?;
+        let elapsed = start.elapsed();
+
+        assert!(warn_if_slow(
+            0,
+            mount.destination(),
+            elapsed,
+            options.slow_mount_threshold
+        ));
+        assert!(!warn_if_slow(
+            0,
+            mount.destination(),
+            elapsed,
+            Some(Duration::from_secs(60))
+        ));
+        assert!(!warn_if_slow(0, mount.destination(), elapsed, None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_after_mounts() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mounts = vec![SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .build()?];
+
+        for sync_after_mounts in [false, true] {
+            let options = MountOptions {
+                sync_after_mounts,
+                ..MountOptions::new(tmp_dir.path())
+            };
+
+            let m = Mount::new();
+            m.setup_all_mounts(&mounts, &options)?;
+
+            let syscall = m
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap();
+            assert_eq!(syscall.get_mount_args().len(), 1);
+            assert_eq!(syscall.get_sync_count(), usize::from(sync_after_mounts));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_duplicate_destinations() -> Result<()> {
+        let mount = |dest: &str| {
+            SpecMountBuilder::default()
+                .destination(PathBuf::from(dest))
+                .typ("tmpfs")
+                .source(PathBuf::from("tmpfs"))
+                .build()
+        };
+
+        let mounts = vec![mount("/tmp")?, mount("/run")?, mount("/run/lock")?];
+        check_duplicate_destinations(&mounts)?;
+
+        let mounts = vec![
+            mount("/tmp")?,
+            mount("/run")?,
+            mount("/tmp")?,
+            mount("/run")?,
+            mount("/tmp")?,
+        ];
+        let err = check_duplicate_destinations(&mounts).unwrap_err();
+        assert!(
+            matches!(&err, MountError::DuplicateDestinations(dests)
+                if *dests == vec![PathBuf::from("/tmp"), PathBuf::from("/run")]),
+            "{err}"
+        );
+
+        // nothing is mounted if the destinations are ambiguous
+        let tmp_dir = tempfile::tempdir()?;
+        let options = MountOptions::new(tmp_dir.path());
+        let m = Mount::new();
+        assert!(m.setup_all_mounts(&mounts, &options).is_err());
+        assert!(m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_readonly_order() -> Result<()> {
+        let mount = |dest: &str, options: &[&str]| {
+            SpecMountBuilder::default()
+                .destination(PathBuf::from(dest))
+                .typ("bind")
+                .source(PathBuf::from("/var/lib/data"))
+                .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+                .build()
+        };
+
+        // the read-only parent is mounted first, the writable child stays visible
+        let mounts = vec![
+            mount("/data", &["rbind", "ro"])?,
+            mount("/data/cache", &["rbind", "rw"])?,
+        ];
+        check_readonly_order(&mounts)?;
+
+        let mounts = vec![
+            mount("/data/cache", &["rbind", "rw"])?,
+            mount("/database", &["rbind", "ro"])?,
+            mount("/data", &["rbind", "ro"])?,
+        ];
+        let err = check_readonly_order(&mounts).unwrap_err();
+        assert!(
+            matches!(&err, MountError::ShadowedByReadonly { writable, readonly }
+                if writable == Path::new("/data/cache") && readonly == Path::new("/data")),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_setup_all_mounts_error_index() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mounts = ["/tmp", "/run", "/var/tmp"]
+            .iter()
+            .map(|dest| {
+                SpecMountBuilder::default()
+                    .destination(PathBuf::from(dest))
+                    .typ("tmpfs")
+                    .source(PathBuf::from("tmpfs"))
+                    .build()
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let options = MountOptions::new(tmp_dir.path());
+
+        let m = Mount::new();
+        let mocks = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        // the mount of /run fails
+        mocks.set_ret_err(ArgName::Mount, || {
+            Err(SyscallError::Mount {
+                source: Errno::ENOSPC,
+            })
+        });
+        mocks.set_ret_err_after(ArgName::Mount, 1);
+
+        let err = m.setup_all_mounts(&mounts, &options).unwrap_err();
+        assert!(
+            matches!(&err, MountError::Setup { index: 1, dest, .. } if dest == Path::new("/run")),
+            "{err}"
+        );
+        assert!(
+            err.to_string().contains("mount #1 of Some(\"tmpfs\")"),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_remount_locked_flags() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        create_dir_all(&source)?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        let mocks = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        // the bind mount succeeds, the remount fails
+        mocks.set_ret_err(ArgName::Mount, || {
+            Err(SyscallError::Mount {
+                source: Errno::EPERM,
+            })
+        });
+        mocks.set_ret_err_after(ArgName::Mount, 1);
+
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["bind".to_string(), "ro".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        let err = m
+            .mount_into_container(mount, &rootfs, &mount_option_config, None)
+            .unwrap_err();
+        assert!(
+            matches!(err, MountError::LockedFlags { ref dest, .. } if *dest == rootfs.join("data"))
+        );
+        assert!(err.to_string().contains("locked flags"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recursive_readonly_bind_mount() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        create_dir_all(source.join("submount"))?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["rbind".to_string(), "ro".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        m.mount_into_container(mount, &rootfs, &mount_option_config, None)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        let flags = MsFlags::MS_BIND | MsFlags::MS_REC | MsFlags::MS_RDONLY;
+        let want = vec![
+            MountArgs {
+                source: Some(source),
+                target: rootfs.join("data"),
+                fstype: Some("bind".to_string()),
+                flags,
+                data: Some("".to_string()),
+            },
+            MountArgs {
+                source: Some(rootfs.join("data")),
+                target: rootfs.join("data"),
+                fstype: None,
+                flags: flags | MsFlags::MS_REMOUNT,
+                data: None,
+            },
+        ];
+        assert_eq!(want, syscall.get_mount_args());
+
+        let got = syscall.get_mount_setattr_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].flags, linux::AT_RECURSIVE);
+        assert_eq!(got[0].mount_attr.attr_set, linux::MOUNT_ATTR_RDONLY);
+
+        // a non-recursive bind mount only needs the remount
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(tmp_dir.path().join("source"))
+            .options(vec!["bind".to_string(), "ro".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        m.mount_into_container(mount, &rootfs, &mount_option_config, None)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert_eq!(syscall.get_mount_args().len(), 2);
+        assert!(syscall.get_mount_setattr_args().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nosymfollow_rbind_mount() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        create_dir_all(source.join("submount"))?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["rbind".to_string(), "nosymfollow".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        m.mount_into_container(mount, &rootfs, &mount_option_config, None)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        // like MS_NOSYMFOLLOW, nosymfollow leaves the submounts alone
+        let got = syscall.get_mount_setattr_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].flags, 0);
+        assert_eq!(got[0].mount_attr.attr_set, linux::MOUNT_ATTR_NOSYMFOLLOW);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nosymfollow_file_bind_mount() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        fs::write(&source, "data")?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["bind".to_string(), "nosymfollow".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        m.mount_into_container(mount, &rootfs, &mount_option_config, None)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        let got = syscall.get_mount_setattr_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].flags, 0);
+        assert_eq!(got[0].mount_attr.attr_set, linux::MOUNT_ATTR_NOSYMFOLLOW);
+        assert!(rootfs.join("data").is_file());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rro_bind_mount() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        create_dir_all(source.join("submount"))?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        create_dir_all(&rootfs)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["rbind".to_string(), "rro".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        m.mount_into_container(mount, &rootfs, &mount_option_config, None)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        // rro is applied through mount_setattr only, not by a read-only remount
+        let want = vec![MountArgs {
+            source: Some(source),
+            target: rootfs.join("data"),
+            fstype: Some("bind".to_string()),
+            flags: MsFlags::MS_BIND | MsFlags::MS_REC,
+            data: Some("".to_string()),
+        }];
+        assert_eq!(want, syscall.get_mount_args());
+
+        let got = syscall.get_mount_setattr_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].flags, linux::AT_RECURSIVE);
+        assert_eq!(
+            got[0].mount_attr,
+            linux::MountAttr {
+                attr_set: linux::MOUNT_ATTR_RDONLY,
+                attr_clr: 0,
+                propagation: 0,
+                userns_fd: 0,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_propagation_only_mount() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        create_dir_all(tmp_dir.path().join("data"))?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .options(vec!["rslave".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        m.mount_into_container(mount, tmp_dir.path(), &mount_option_config, None)?;
+
+        let want = vec![MountArgs {
+            source: None,
+            target: tmp_dir.path().join("data"),
+            fstype: None,
+            flags: MsFlags::MS_SLAVE | MsFlags::MS_REC,
+            data: None,
+        }];
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(want, got);
+
+        // without any propagation option a source is still required
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .options(vec!["ro".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+        assert!(m
+            .mount_into_container(mount, tmp_dir.path(), &mount_option_config, None)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_idmapped_mount_into_container() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        create_dir_all(&source)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["rbind".to_string()])
+            .build()?;
+        let uid_mappings = vec![LinuxIdMappingBuilder::default()
+            .container_id(0u32)
+            .host_id(1000u32)
+            .size(65536u32)
+            .build()?];
+        let gid_mappings = vec![LinuxIdMappingBuilder::default()
+            .container_id(0u32)
+            .host_id(2000u32)
+            .size(65536u32)
+            .build()?];
+        let mut mount_option_config = parse_mount(mount);
+        mount_option_config.uid_mappings = Some(uid_mappings.clone());
+        mount_option_config.gid_mappings = Some(gid_mappings.clone());
+
+        m.mount_into_container(mount, tmp_dir.path(), &mount_option_config, None)?;
+
+        let syscall = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        assert_eq!(
+            vec![UsernsFdArgs {
+                uid_mappings,
+                gid_mappings
+            }],
+            syscall.get_userns_fd_args()
+        );
+
+        let got = syscall.get_mount_setattr_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].dirfd, -1);
+        assert_eq!(got[0].flags, 0);
+        assert_eq!(got[0].mount_attr.attr_set, linux::MOUNT_ATTR_IDMAP);
+        assert_eq!(got[0].mount_attr.attr_clr, 0);
+        assert!(got[0].pathname.starts_with("/proc/self/fd"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_bind_source() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("does-not-exist");
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["rbind".to_string()])
+            .build()?;
+
+        let err = m
+            .mount_into_container(mount, tmp_dir.path(), &parse_mount(mount), None)
+            .unwrap_err();
+        assert!(matches!(&err, MountError::MissingBindSource(path) if *path == source));
+        assert!(
+            err.to_string()
+                .contains("bind sources must exist on the host"),
+            "{err}"
+        );
+        assert!(m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args()
+            .is_empty());
+
+        Ok(())
+    }
+
+    /// Records the target, level and message of every log record.
+    struct LogCapture(std::sync::Mutex<Vec<(String, log::Level, String)>>);
+
+    impl log::Log for LogCapture {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push((
+                record.target().to_string(),
+                record.level(),
+                record.args().to_string(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    // const Mutex::new needs a newer Rust than the crate supports
+    static LOG_CAPTURE: once_cell::sync::Lazy<LogCapture> =
+        once_cell::sync::Lazy::new(|| LogCapture(std::sync::Mutex::new(Vec::new())));
+
+    #[test]
+    fn test_mount_log_target() -> Result<()> {
+        // no other test installs a logger, so this one can't fail
+        log::set_logger(&*LOG_CAPTURE).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("log-target-source");
+        create_dir_all(&source)?;
+        let mount_opts = MountOptions::new(tmp_dir.path());
+        let bind = SpecMountBuilder::default()
+            .destination(PathBuf::from("/log-target-bind"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["bind".to_string()])
+            .build()?;
+        let tmpfs = SpecMountBuilder::default()
+            .destination(PathBuf::from("/log-target-tmpfs"))
+            .typ("tmpfs")
+            .source("tmpfs")
+            .build()?;
+        let m = Mount::new();
+        m.setup_mount(&bind, &mount_opts)?;
+        m.setup_mount(&tmpfs, &mount_opts)?;
+
+        let records = LOG_CAPTURE.0.lock().unwrap();
+        let find = |destination: &str| {
+            records
+                .iter()
+                .find(|(_, _, msg)| msg.starts_with("mounting") && msg.contains(destination))
+                .map(|(target, level, _)| (target.as_str(), *level))
+        };
+        assert_eq!(
+            find("/log-target-bind"),
+            Some((LOG_TARGET, log::Level::Trace))
+        );
+        assert_eq!(
+            find("/log-target-tmpfs"),
+            Some((LOG_TARGET, log::Level::Debug))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_without_source() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/tmp"))
+            .typ("tmpfs")
+            .options(vec!["nosuid".to_string(), "size=1m".to_string()])
+            .build()?;
+        m.mount_into_container(mount, tmp_dir.path(), &parse_mount(mount), None)?;
+
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].source, Some(PathBuf::from("tmpfs")));
+        assert_eq!(got[0].fstype, Some("tmpfs".to_string()));
+
+        // anything backed by a device or a directory still needs a source
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("ext4")
+            .build()?;
+        let err = m
+            .mount_into_container(mount, tmp_dir.path(), &parse_mount(mount), None)
+            .unwrap_err();
+        assert!(matches!(err, MountError::MissingSource));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_idmapped_mount_recursive() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        create_dir_all(&source)?;
+        let mappings = vec![LinuxIdMappingBuilder::default()
+            .container_id(0u32)
+            .host_id(1000u32)
+            .size(65536u32)
+            .build()?];
+
+        for (option, flags) in [("idmap", 0), ("ridmap", linux::AT_RECURSIVE)] {
+            let m = Mount::new();
+            let mount = &SpecMountBuilder::default()
+                .destination(PathBuf::from("/data"))
+                .typ("bind")
+                .source(&source)
+                .options(vec!["rbind".to_string(), option.to_string()])
+                .build()?;
+            let mut mount_option_config = parse_mount(mount);
+            assert_eq!(mount_option_config.data, "", "option {option}");
+            mount_option_config.uid_mappings = Some(mappings.clone());
+            mount_option_config.gid_mappings = Some(mappings.clone());
+
+            m.mount_into_container(mount, tmp_dir.path(), &mount_option_config, None)?;
+
+            let got = m
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap()
+                .get_mount_setattr_args();
+            assert_eq!(got.len(), 1, "option {option}");
+            assert_eq!(got[0].flags, flags, "option {option}");
+            assert_eq!(got[0].mount_attr.attr_set, linux::MOUNT_ATTR_IDMAP);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_idmapped_mount_requires_both_mappings() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        create_dir_all(&source)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(&source)
+            .options(vec!["rbind".to_string()])
+            .build()?;
+        let mut mount_option_config = parse_mount(mount);
+        mount_option_config.uid_mappings = Some(vec![LinuxIdMappingBuilder::default()
+            .container_id(0u32)
+            .host_id(1000u32)
+            .size(1u32)
+            .build()?]);
+
+        assert!(m
+            .mount_into_container(mount, tmp_dir.path(), &mount_option_config, None)
+            .is_err());
+        assert!(m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_setattr_args()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlay_mount_into_container() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let lower = tmp_dir.path().join("lower");
+        create_dir_all(&lower)?;
+
+        let m = Mount::new();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/merged"))
+            .typ("overlay")
+            .source(PathBuf::from("overlay"))
+            .options(vec![
+                format!("lowerdir={}:lower2", lower.display()),
+                "upperdir=upper".to_string(),
+                "workdir=work".to_string(),
+                "nodev".to_string(),
+            ])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        m.mount_into_container(mount, tmp_dir.path(), &mount_option_config, None)?;
+
+        assert!(tmp_dir.path().join("upper").is_dir());
+        assert!(tmp_dir.path().join("work").is_dir());
+
+        let want = vec![MountArgs {
+            source: Some(PathBuf::from("overlay")),
+            target: tmp_dir.path().join("merged"),
+            fstype: Some("overlay".to_string()),
+            flags: MsFlags::MS_NODEV,
+            data: Some(format!(
+                "lowerdir={}:{},upperdir={},workdir={}",
+                lower.display(),
+                tmp_dir.path().join("lower2").display(),
+                tmp_dir.path().join("upper").display(),
+                tmp_dir.path().join("work").display(),
+            )),
+        }];
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(want, got);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_overlay_data() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path();
+
+        // escaped colons stay part of the directory name
+        let data = resolve_overlay_data(rootfs, "lowerdir=/a\\:b:/c", true)?;
+        assert_eq!(data, "lowerdir=/a\\:b:/c");
+        assert_eq!(
+            split_overlay_lowerdir("/a\\:b:/c"),
+            vec!["/a:b".to_string(), "/c".to_string()]
+        );
+
+        // upperdir without workdir
+        assert!(resolve_overlay_data(rootfs, "lowerdir=/a,upperdir=upper", true).is_err());
+        // no lowerdir at all
+        assert!(resolve_overlay_data(rootfs, "upperdir=up,workdir=work", true).is_err());
+        // empty lowerdir entry
+        assert!(resolve_overlay_data(rootfs, "lowerdir=/a::/b", true).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_setup_mount_with_syscall() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let m = Mount::with_syscall(Box::<TestHelperSyscall>::default());
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/dev"))
+            .typ("tmpfs")
+            .source(PathBuf::from("tmpfs"))
+            .options(vec![
+                "nosuid".to_string(),
+                "ro".to_string(),
+                "mode=755".to_string(),
+            ])
+            .build()?;
+        let options = MountOptions::new(tmp_dir.path());
+
+        m.setup_mount(mount, &options)?;
+
+        // /dev is always mounted writable
+        let want = vec![MountArgs {
+            source: Some(PathBuf::from("tmpfs")),
+            target: tmp_dir.path().join("dev"),
+            fstype: Some("tmpfs".to_string()),
+            flags: MsFlags::MS_NOSUID,
+            data: Some("mode=755".to_string()),
+        }];
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(want, got);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_mount_into_container() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let source = tmp_dir.path().join("source");
+        create_dir_all(&source)?;
+        let m = Mount::new_dry_run();
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/dest"))
+            .source(&source)
+            .typ("bind")
+            .options(vec!["ro".to_string()])
+            .build()?;
+        let mount_option_config = parse_mount(mount);
+
+        m.mount_into_container(mount, tmp_dir.path(), &mount_option_config, None)?;
+
+        assert!(!tmp_dir.path().join("dest").exists());
+        let got = m
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert!(got.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_dest_in_rootfs() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = tmp_dir.path().join("rootfs");
+        let outside = tmp_dir.path().join("outside");
+        create_dir_all(rootfs.join("inside"))?;
+        create_dir_all(&outside)?;
+
+        assert!(ensure_dest_in_rootfs(&rootfs, &rootfs.join("inside")).is_ok());
+
+        // a destination swapped for a symlink pointing out of the rootfs
+        std::os::unix::fs::symlink(&outside, rootfs.join("escape"))?;
+        assert!(ensure_dest_in_rootfs(&rootfs, &rootfs.join("escape")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_observer() -> Result<()> {
+        use std::{cell::RefCell, rc::Rc};
+
+        #[derive(Default)]
+        struct RecordingObserver {
+            events: Rc<RefCell<Vec<(String, PathBuf, bool)>>>,
+        }
+
+        impl MountObserver for RecordingObserver {
+            fn on_mount_start(&self, mount: &SpecMount) {
+                self.events.borrow_mut().push((
+                    "start".to_string(),
+                    mount.destination().clone(),
+                    true,
+                ));
+            }
+
+            fn on_mount_end(
+                &self,
+                mount: &SpecMount,
+                _elapsed: Duration,
+                result: &std::result::Result<(), MountError>,
+            ) {
+                self.events.borrow_mut().push((
+                    "end".to_string(),
+                    mount.destination().clone(),
+                    result.is_ok(),
+                ));
+            }
+        }
+
+        let tmp_dir = tempfile::tempdir()?;
+        let observer = RecordingObserver::default();
+        let events = observer.events.clone();
+        let m = Mount::with_observer(Box::new(observer));
+        let mount = &SpecMountBuilder::default()
+            .destination(PathBuf::from("/dev/shm"))
+            .typ("tmpfs")
+            .source(PathBuf::from("shm"))
+            .build()?;
+
+        m.mount_into_container(mount, tmp_dir.path(), &parse_mount(mount), None)?;
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                ("start".to_string(), PathBuf::from("/dev/shm"), true),
+                ("end".to_string(), PathBuf::from("/dev/shm"), true),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_make_parent_mount_private() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let m = Mount::new();
+        let result = m.make_parent_mount_private(tmp_dir.path());
+        assert!(result.is_ok());
+
+        if let Ok(Some(_)) = result {
+            let set = m
+                .syscall
+                .as_any()
                 .downcast_ref::<TestHelperSyscall>()
                 .unwrap()
                 .get_mount_args();
@@ -607,6 +4503,158 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_make_mount_private_error() {
+        let m = Mount::new();
+        m.syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .set_ret_err(ArgName::Mount, || {
+                Err(SyscallError::Mount {
+                    source: Errno::EPERM,
+                })
+            });
+        let parent_mount = MountInfo {
+            mnt_id: 11,
+            pid: 10,
+            majmin: "".to_string(),
+            root: "/".to_string(),
+            mount_point: PathBuf::from("/var/lib/containers"),
+            mount_options: Default::default(),
+            opt_fields: vec![MountOptFields::Shared(1)],
+            fs_type: "ext4".to_string(),
+            mount_source: Some("/dev/sda1".to_string()),
+            super_options: Default::default(),
+        };
+
+        let err = m.make_mount_private(parent_mount).unwrap_err();
+        assert!(err.to_string().contains("/var/lib/containers"), "{err}");
+        assert!(err.to_string().contains("MS_PRIVATE"), "{err}");
+        assert!(
+            matches!(
+                err,
+                MountError::ParentMountPrivate {
+                    source: SyscallError::Mount {
+                        source: Errno::EPERM
+                    },
+                    ..
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_check_rbind_submounts() -> Result<()> {
+        let mount_info = |mount_point: &str| MountInfo {
+            mnt_id: 11,
+            pid: 10,
+            majmin: "".to_string(),
+            root: "/".to_string(),
+            mount_point: PathBuf::from(mount_point),
+            mount_options: Default::default(),
+            opt_fields: vec![],
+            fs_type: "ext4".to_string(),
+            mount_source: Some("/dev/sda1".to_string()),
+            super_options: Default::default(),
+        };
+        let mount_infos = || {
+            Ok(vec![
+                mount_info("/"),
+                mount_info("/data"),
+                mount_info("/data/a"),
+                mount_info("/data/a/b"),
+                mount_info("/data/c"),
+                mount_info("/database"),
+            ])
+        };
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/data"))
+            .options(vec!["rbind".to_string()])
+            .build()?;
+        let config = parse_mount(&mount);
+
+        check_rbind_submounts(&mount, &config, 3, mount_infos)?;
+        let err = check_rbind_submounts(&mount, &config, 2, mount_infos).unwrap_err();
+        assert!(
+            matches!(err, MountError::TooManySubmounts { ref src, count: 3, max: 2 } if src == Path::new("/data")),
+            "{err}"
+        );
+
+        // a plain bind mount doesn't take the submounts along
+        let mount = SpecMountBuilder::default()
+            .destination(PathBuf::from("/data"))
+            .typ("bind")
+            .source(PathBuf::from("/data"))
+            .options(vec!["bind".to_string()])
+            .build()?;
+        check_rbind_submounts(&mount, &parse_mount(&mount), 0, || unreachable!())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_mounts() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let rootfs = canonicalize(tmp_dir.path())?;
+        create_dir_all(rootfs.join("tmp"))?;
+        assert!(container_mounts(&rootfs)?.is_empty());
+        let mounts = container_mounts(Path::new("/"))?;
+        assert!(mounts.iter().any(|mi| mi.mount_point == Path::new("/")));
+
+        // mounting requires root, the tmpfs is only mounted in a new mount
+        // namespace of the thread below
+        if !unistd::geteuid().is_root() {
+            return Ok(());
+        }
+
+        thread::spawn(move || -> Result<()> {
+            nix::sched::unshare(nix::sched::CloneFlags::CLONE_NEWNS)?;
+            nix::mount::mount(
+                None::<&str>,
+                "/",
+                None::<&str>,
+                MsFlags::MS_PRIVATE | MsFlags::MS_REC,
+                None::<&str>,
+            )?;
+            assert!(container_mounts(&rootfs)?.is_empty());
+
+            nix::mount::mount(
+                Some("tmpfs"),
+                &rootfs.join("tmp"),
+                Some("tmpfs"),
+                MsFlags::empty(),
+                None::<&str>,
+            )?;
+            let mounts = container_mounts(&rootfs)?;
+            assert_eq!(mounts.len(), 1);
+            assert_eq!(mounts[0].mount_point, rootfs.join("tmp"));
+            assert_eq!(mounts[0].fs_type, "tmpfs");
+
+            Ok(())
+        })
+        .join()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_read_mount_infos() -> Result<()> {
+        assert!(!read_mount_infos(Path::new("/proc/self"))?.is_empty());
+
+        let tmp_dir = tempfile::tempdir()?;
+        let proc_dir = tmp_dir.path().join("proc/self");
+        let err = read_mount_infos(&proc_dir).unwrap_err();
+        assert!(
+            matches!(err, MountError::MountInfo { ref path, .. } if *path == proc_dir.join("mountinfo"))
+        );
+        assert!(err.to_string().contains("/proc has to be mounted"), "{err}");
+
+        Ok(())
+    }
+
     #[test]
     #[cfg(feature = "v1")]
     fn test_namespaced_subsystem_success() -> Result<()> {
@@ -623,9 +4671,8 @@ mod tests {
             .context("failed to build cgroup mount")?;
 
         let mount_opts = MountOptions {
-            root: tmp.path(),
-            label: None,
             cgroup_ns: true,
+            ..MountOptions::new(tmp.path())
         };
 
         let subsystem_name = "cpu";
@@ -652,74 +4699,170 @@ mod tests {
             .unwrap()
             .get_mount_args();
 
-        assert_eq!(got.len(), 1);
-        assert_eq!(expected, got[0]);
+        assert_eq!(got.len(), 1);
+        assert_eq!(expected, got[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "v1")]
+    fn test_emulated_subsystem_success() -> Result<()> {
+        // arrange
+        let tmp = tempfile::tempdir().unwrap();
+        let host_cgroup_mount = tmp.path().join("host_cgroup");
+        let host_cgroup = host_cgroup_mount.join("cpu/container1");
+        fs::create_dir_all(&host_cgroup)?;
+
+        let container_cgroup = Path::new("/container_cgroup");
+        let mounter = Mount::new();
+
+        let spec_cgroup_mount = SpecMountBuilder::default()
+            .destination(container_cgroup)
+            .source("cgroup")
+            .typ("cgroup")
+            .build()
+            .context("failed to build cgroup mount")?;
+
+        let mount_opts = MountOptions::new(tmp.path());
+
+        let subsystem_name = "cpu";
+        let mut process_cgroups = HashMap::new();
+        process_cgroups.insert("cpu".to_owned(), "container1".to_owned());
+
+        // act
+        mounter
+            .setup_emulated_subsystem(
+                &spec_cgroup_mount,
+                &mount_opts,
+                subsystem_name,
+                false,
+                &host_cgroup_mount.join(subsystem_name),
+                &process_cgroups,
+            )
+            .context("failed to setup emulated subsystem")?;
+
+        // assert
+        let expected = MountArgs {
+            source: Some(host_cgroup),
+            target: tmp
+                .path()
+                .join_safely(container_cgroup)?
+                .join(subsystem_name),
+            fstype: Some("bind".to_owned()),
+            flags: MsFlags::MS_BIND | MsFlags::MS_REC,
+            data: Some("".to_owned()),
+        };
+
+        let got = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(expected, got[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "v1")]
+    fn test_readonly_subsystems() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let host_cgroup_mount = tmp.path().join("host_cgroup");
+        fs::create_dir_all(host_cgroup_mount.join("cpu/container1"))?;
+
+        let spec_cgroup_mount = SpecMountBuilder::default()
+            .destination("/container_cgroup")
+            .source("cgroup")
+            .typ("cgroup")
+            .options(vec!["ro".to_string(), "nosuid".to_string()])
+            .build()
+            .context("failed to build cgroup mount")?;
+
+        for cgroup_ns in [true, false] {
+            let mount_opts = MountOptions {
+                cgroup_ns,
+                ..MountOptions::new(tmp.path())
+            };
+
+            let mounter = Mount::new();
+            if cgroup_ns {
+                mounter.setup_namespaced_subsystem(
+                    &spec_cgroup_mount,
+                    &mount_opts,
+                    "cpu",
+                    false,
+                )?;
+            } else {
+                let process_cgroups = HashMap::from([("cpu".to_owned(), "container1".to_owned())]);
+                mounter.setup_emulated_subsystem(
+                    &spec_cgroup_mount,
+                    &mount_opts,
+                    "cpu",
+                    false,
+                    &host_cgroup_mount.join("cpu"),
+                    &process_cgroups,
+                )?;
+            }
+
+            let got = mounter
+                .syscall
+                .as_any()
+                .downcast_ref::<TestHelperSyscall>()
+                .unwrap()
+                .get_mount_args();
+            // a bind mount is made read-only by remounting it
+            let last = got.last().unwrap();
+            assert!(last.flags.contains(MsFlags::MS_RDONLY), "{got:?}");
+            if !cgroup_ns {
+                assert!(last.flags.contains(MsFlags::MS_REMOUNT), "{got:?}");
+            }
+        }
 
         Ok(())
     }
 
     #[test]
     #[cfg(feature = "v1")]
-    fn test_emulated_subsystem_success() -> Result<()> {
-        // arrange
+    fn test_emulated_subsystem_missing_source() -> Result<()> {
         let tmp = tempfile::tempdir().unwrap();
         let host_cgroup_mount = tmp.path().join("host_cgroup");
-        let host_cgroup = host_cgroup_mount.join("cpu/container1");
-        fs::create_dir_all(&host_cgroup)?;
+        fs::create_dir_all(host_cgroup_mount.join("cpu"))?;
 
-        let container_cgroup = Path::new("/container_cgroup");
         let mounter = Mount::new();
-
         let spec_cgroup_mount = SpecMountBuilder::default()
-            .destination(container_cgroup)
+            .destination("/container_cgroup")
             .source("cgroup")
             .typ("cgroup")
             .build()
             .context("failed to build cgroup mount")?;
 
-        let mount_opts = MountOptions {
-            root: tmp.path(),
-            label: None,
-            cgroup_ns: false,
-        };
+        let mount_opts = MountOptions::new(tmp.path());
 
-        let subsystem_name = "cpu";
         let mut process_cgroups = HashMap::new();
-        process_cgroups.insert("cpu".to_owned(), "container1".to_owned());
+        process_cgroups.insert("cpu".to_owned(), "does/not/exist".to_owned());
 
-        // act
         mounter
             .setup_emulated_subsystem(
                 &spec_cgroup_mount,
                 &mount_opts,
-                subsystem_name,
+                "cpu",
                 false,
-                &host_cgroup_mount.join(subsystem_name),
+                &host_cgroup_mount.join("cpu"),
                 &process_cgroups,
             )
             .context("failed to setup emulated subsystem")?;
 
-        // assert
-        let expected = MountArgs {
-            source: Some(host_cgroup),
-            target: tmp
-                .path()
-                .join_safely(container_cgroup)?
-                .join(subsystem_name),
-            fstype: Some("bind".to_owned()),
-            flags: MsFlags::MS_BIND | MsFlags::MS_REC,
-            data: Some("".to_owned()),
-        };
-
         let got = mounter
             .syscall
             .as_any()
             .downcast_ref::<TestHelperSyscall>()
             .unwrap()
             .get_mount_args();
-
-        assert_eq!(got.len(), 1);
-        assert_eq!(expected, got[0]);
+        assert!(got.is_empty());
 
         Ok(())
     }
@@ -739,15 +4882,14 @@ mod tests {
             .context("failed to build cgroup mount")?;
 
         let mount_opts = MountOptions {
-            root: tmp.path(),
-            label: None,
             cgroup_ns: true,
+            ..MountOptions::new(tmp.path())
         };
 
         let mounter = Mount::new();
 
         // act
-        mounter
+        let subsystems = mounter
             .mount_cgroup_v1(&spec_cgroup_mount, &mount_opts)
             .context("failed to mount cgroup v1")?;
 
@@ -762,6 +4904,11 @@ mod tests {
 
         let host_mounts = libcgroups::v1::util::list_subsystem_mount_points()?;
         assert_eq!(got.len(), host_mounts.len() + 1);
+        let host_subsystems: Vec<&str> = host_mounts
+            .iter()
+            .filter_map(|m| m.file_name().and_then(|f| f.to_str()))
+            .collect();
+        assert_eq!(subsystems, host_subsystems);
 
         let expected = MountArgs {
             source: Some(PathBuf::from("tmpfs".to_owned())),
@@ -797,6 +4944,152 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[cfg(feature = "v1")]
+    fn test_mount_cgroup_v1_rollback() -> Result<()> {
+        let host_mounts = libcgroups::v1::util::list_subsystem_mount_points()?;
+        if host_mounts.len() < 2 {
+            // a failure in the middle of the subsystems can't be simulated
+            return Ok(());
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        let container_cgroup = PathBuf::from("/sys/fs/cgroup");
+        let spec_cgroup_mount = SpecMountBuilder::default()
+            .destination(&container_cgroup)
+            .source("cgroup")
+            .typ("cgroup")
+            .build()
+            .context("failed to build cgroup mount")?;
+        let mount_opts = MountOptions {
+            cgroup_ns: true,
+            ..MountOptions::new(tmp.path())
+        };
+
+        let mounter = Mount::new();
+        let mocks = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap();
+        // the tmpfs and the first subsystem are mounted, the second one fails
+        mocks.set_ret_err(ArgName::Mount, || {
+            Err(SyscallError::Mount {
+                source: Errno::EBUSY,
+            })
+        });
+        mocks.set_ret_err_after(ArgName::Mount, 2);
+
+        assert!(mounter
+            .mount_cgroup_v1(&spec_cgroup_mount, &mount_opts)
+            .is_err());
+
+        let cgroup_root = tmp.path().join_safely(&container_cgroup)?;
+        let first_subsystem = host_mounts[0].file_name().unwrap();
+        let got: Vec<PathBuf> = mocks
+            .get_umount_args()
+            .into_iter()
+            .map(|args| args.target)
+            .collect();
+        assert_eq!(got, vec![cgroup_root.join(first_subsystem), cgroup_root]);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "v1")]
+    fn test_unmount_all() {
+        let mounter = Mount::new();
+        let mount_points = vec![
+            PathBuf::from("/sys/fs/cgroup"),
+            PathBuf::from("/sys/fs/cgroup/cpu"),
+            PathBuf::from("/sys/fs/cgroup/memory"),
+        ];
+
+        mounter.unmount_all(&mount_points);
+
+        let got = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_umount_args();
+        let want: Vec<crate::syscall::test::UmountArgs> = mount_points
+            .into_iter()
+            .rev()
+            .map(|target| crate::syscall::test::UmountArgs {
+                target,
+                flags: MntFlags::MNT_DETACH,
+            })
+            .collect();
+        assert_eq!(want, got);
+    }
+
+    #[test]
+    #[cfg(feature = "v1")]
+    fn test_filter_subsystem_mounts() {
+        let host_mounts = vec![
+            PathBuf::from("/cgroup/cpu,cpuacct"),
+            PathBuf::from("/sys/fs/cgroup/pids"),
+            PathBuf::from("/cgroup/memory"),
+        ];
+        assert_eq!(
+            Some(PathBuf::from("/cgroup")),
+            libcgroups::v1::util::cgroup_root_of(&host_mounts)
+        );
+
+        assert_eq!(
+            vec![
+                PathBuf::from("/cgroup/cpu,cpuacct"),
+                PathBuf::from("/cgroup/memory")
+            ],
+            filter_subsystem_mounts(host_mounts.clone(), Path::new("/cgroup"))
+        );
+        assert_eq!(
+            vec![PathBuf::from("/sys/fs/cgroup/pids")],
+            filter_subsystem_mounts(host_mounts, Path::new(DEFAULT_CGROUP_ROOT))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "v1")]
+    fn test_mount_cgroup_v1_tmpfs_size() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let container_cgroup = PathBuf::from("/sys/fs/cgroup");
+
+        let spec_cgroup_mount = SpecMountBuilder::default()
+            .destination(&container_cgroup)
+            .source("cgroup")
+            .typ("cgroup")
+            .build()
+            .context("failed to build cgroup mount")?;
+
+        let mount_opts = MountOptions {
+            cgroup_ns: true,
+            cgroup_tmpfs_size: Some("1m".to_owned()),
+            ..MountOptions::new(tmp.path())
+        };
+
+        let mounter = Mount::new();
+
+        mounter
+            .mount_cgroup_v1(&spec_cgroup_mount, &mount_opts)
+            .context("failed to mount cgroup v1")?;
+
+        let got = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+
+        let tmpfs = got.first().context("no tmpfs mount")?;
+        assert_eq!(tmpfs.fstype, Some("tmpfs".to_owned()));
+        assert_eq!(tmpfs.data, Some("mode=755,size=1m".to_owned()));
+
+        Ok(())
+    }
+
     #[test]
     #[cfg(feature = "v2")]
     fn test_mount_cgroup_v2() -> Result<()> {
@@ -812,9 +5105,8 @@ mod tests {
             .context("failed to build cgroup mount")?;
 
         let mount_opts = MountOptions {
-            root: tmp.path(),
-            label: None,
             cgroup_ns: true,
+            ..MountOptions::new(tmp.path())
         };
 
         let mounter = Mount::new();
@@ -825,6 +5117,13 @@ mod tests {
             flags,
             data: String::new(),
             rec_attr: None,
+            attr: None,
+            uid_mappings: None,
+            gid_mappings: None,
+            dir_mode: None,
+            copy_up: false,
+            idmap_recursive: false,
+            root_fd: None,
         };
         mounter
             .mount_cgroup_v2(&spec_cgroup_mount, &mount_opts, &mount_option_config)
@@ -851,4 +5150,214 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "v2")]
+    fn test_mount_cgroup_v2_options() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let container_cgroup = PathBuf::from("/sys/fs/cgroup");
+
+        let spec_cgroup_mount = SpecMountBuilder::default()
+            .destination(&container_cgroup)
+            .source("cgroup")
+            .typ("cgroup")
+            .options(vec![
+                "nosuid".to_string(),
+                "nsdelegate".to_string(),
+                "memory_recursiveprot".to_string(),
+                "cpu".to_string(),
+            ])
+            .build()
+            .context("failed to build cgroup mount")?;
+
+        let mount_opts = MountOptions {
+            cgroup_ns: true,
+            ..MountOptions::new(tmp.path())
+        };
+
+        let mounter = Mount::new();
+        let mount_option_config = parse_mount(&spec_cgroup_mount);
+        mounter
+            .mount_cgroup_v2(&spec_cgroup_mount, &mount_opts, &mount_option_config)
+            .context("failed to mount cgroup v2")?;
+
+        let expected = MountArgs {
+            source: Some(PathBuf::from("cgroup".to_owned())),
+            target: tmp.path().join_safely(container_cgroup)?,
+            fstype: Some("cgroup2".to_owned()),
+            flags: MsFlags::MS_NOSUID,
+            data: Some("nsdelegate,memory_recursiveprot".to_owned()),
+        };
+
+        let got = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(expected, got[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "v2")]
+    fn test_undelegated_cgroup_files() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let cgroup = tmp_dir.path();
+        for file in ["cgroup.procs", "cgroup.subtree_control", "memory.max"] {
+            fs::write(cgroup.join(file), "")?;
+            fs::set_permissions(cgroup.join(file), fs::Permissions::from_mode(0o644))?;
+        }
+        let uid = unistd::geteuid();
+        let gid = unistd::getegid();
+
+        // cgroup.threads is missing, which is ignored
+        assert!(undelegated_cgroup_files(cgroup, uid, gid).is_empty());
+
+        // the files of a cgroup which isn't delegated are read-only
+        fs::set_permissions(
+            cgroup.join("cgroup.subtree_control"),
+            fs::Permissions::from_mode(0o444),
+        )?;
+        assert_eq!(
+            undelegated_cgroup_files(cgroup, uid, gid),
+            vec![cgroup.join("cgroup.subtree_control")]
+        );
+
+        // only writable by the owner, which is another user
+        let other = unistd::Uid::from_raw(uid.as_raw() + 1);
+        assert_eq!(
+            undelegated_cgroup_files(cgroup, other, gid),
+            vec![
+                cgroup.join("cgroup.procs"),
+                cgroup.join("cgroup.subtree_control")
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "v2")]
+    fn test_cgroup2_mount_data() {
+        assert_eq!(cgroup2_mount_data(""), "");
+        assert_eq!(
+            cgroup2_mount_data("nsdelegate,,nsdelegate,memory_recursiveprot"),
+            "nsdelegate,memory_recursiveprot"
+        );
+        // controllers and their interface files are set up through the
+        // hierarchy after mounting, the kernel rejects them as mount data.
+        assert_eq!(
+            cgroup2_mount_data(
+                "io,cpu,memory,io.latency=target=10,io.cost.qos=enable=1,nsdelegate"
+            ),
+            "nsdelegate"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "v2")]
+    fn test_mount_cgroup_v2_keeps_subtree_control() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let container_cgroup = PathBuf::from("/sys/fs/cgroup");
+        let spec_cgroup_mount = SpecMountBuilder::default()
+            .destination(&container_cgroup)
+            .source("cgroup")
+            .typ("cgroup")
+            .options(vec![
+                "nsdelegate".to_string(),
+                "+io".to_string(),
+                "io.latency".to_string(),
+            ])
+            .build()
+            .context("failed to build cgroup mount")?;
+        let mount_opts = MountOptions {
+            cgroup_ns: true,
+            ..MountOptions::new(tmp.path())
+        };
+
+        let mounter = Mount::new();
+        mounter
+            .mount_cgroup_v2(
+                &spec_cgroup_mount,
+                &mount_opts,
+                &parse_mount(&spec_cgroup_mount),
+            )
+            .context("failed to mount cgroup v2")?;
+
+        let got = mounter
+            .syscall
+            .as_any()
+            .downcast_ref::<TestHelperSyscall>()
+            .unwrap()
+            .get_mount_args();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].data, Some("nsdelegate".to_owned()));
+        // nothing but the mount happens, the controllers enabled in the
+        // hierarchy are left alone.
+        let cgroup_dir = tmp.path().join_safely(container_cgroup)?;
+        assert!(!cgroup_dir.join("cgroup.subtree_control").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "v1")]
+    fn test_cgroup_mount_helpers() -> Result<()> {
+        let destination = Path::new("/sys/fs/cgroup");
+
+        let want = SpecMountBuilder::default()
+            .source("tmpfs")
+            .typ("tmpfs")
+            .destination(destination)
+            .options(
+                ["noexec", "nosuid", "nodev", "mode=755"]
+                    .iter()
+                    .map(|o| o.to_string())
+                    .collect::<Vec<String>>(),
+            )
+            .build()?;
+        assert_eq!(
+            want,
+            tmpfs_mount(destination, vec!["mode=755".to_string()])?
+        );
+
+        let want = SpecMountBuilder::default()
+            .source("cgroup")
+            .typ("cgroup")
+            .destination(destination.join("cpu"))
+            .options(
+                ["noexec", "nosuid", "nodev"]
+                    .iter()
+                    .map(|o| o.to_string())
+                    .collect::<Vec<String>>(),
+            )
+            .build()?;
+        assert_eq!(want, cgroup_subsystem_mount(&destination.join("cpu"))?);
+
+        let want = SpecMountBuilder::default()
+            .source("/host/cpu")
+            .destination(destination.join("cpu"))
+            .typ("bind")
+            .options(
+                ["rw", "rbind"]
+                    .iter()
+                    .map(|o| o.to_string())
+                    .collect::<Vec<String>>(),
+            )
+            .build()?;
+        assert_eq!(
+            want,
+            bind_mount(
+                Path::new("/host/cpu"),
+                &destination.join("cpu"),
+                &["rw", "rbind"]
+            )?
+        );
+
+        Ok(())
+    }
 }
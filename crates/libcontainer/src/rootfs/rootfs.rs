@@ -7,7 +7,7 @@ use super::{
 use crate::syscall::{syscall::create_syscall, Syscall};
 use anyhow::{bail, Context, Result};
 use nix::mount::MsFlags;
-use oci_spec::runtime::{Linux, Spec};
+use oci_spec::runtime::{Linux, LinuxNamespaceType, Spec};
 use std::path::Path;
 
 /// Holds information about rootfs
@@ -28,16 +28,21 @@ impl RootFS {
         }
     }
 
+    /// Prepares the rootfs of the container. The cgroup mounts are set up
+    /// for a container with a cgroup namespace if cgroup_ns is set, it is
+    /// detected from the namespaces of the spec if it is None.
     pub fn prepare_rootfs(
         &self,
         spec: &Spec,
         rootfs: &Path,
         bind_devices: bool,
-        cgroup_ns: bool,
+        cgroup_ns: Option<bool>,
+        bundle_dir: Option<&Path>,
     ) -> Result<()> {
         log::debug!("Prepare rootfs: {:?}", rootfs);
         let mut flags = MsFlags::MS_REC;
         let linux = spec.linux().as_ref().context("no linux in spec")?;
+        let cgroup_ns = cgroup_ns.unwrap_or_else(|| has_cgroup_namespace(linux));
 
         match linux.rootfs_propagation().as_deref() {
             Some("shared") => flags |= MsFlags::MS_SHARED,
@@ -66,17 +71,16 @@ impl RootFS {
         )?;
 
         let global_options = MountOptions {
-            root: rootfs,
             label: linux.mount_label().as_deref(),
             cgroup_ns,
+            bundle_dir,
+            ..MountOptions::new(rootfs)
         };
 
         if let Some(mounts) = spec.mounts() {
-            for mount in mounts {
-                mounter
-                    .setup_mount(mount, &global_options)
-                    .with_context(|| format!("failed to setup mount {mount:#?}"))?;
-            }
+            mounter
+                .setup_all_mounts(mounts, &global_options)
+                .context("failed to setup mounts")?;
         }
 
         let symlinker = Symlink::new();
@@ -120,3 +124,42 @@ impl RootFS {
         Ok(())
     }
 }
+
+/// Checks if the container is in a cgroup namespace, a new one or one it
+/// joins, according to the namespaces in linux.
+pub fn has_cgroup_namespace(linux: &Linux) -> bool {
+    linux.namespaces().as_ref().map_or(false, |namespaces| {
+        namespaces
+            .iter()
+            .any(|ns| ns.typ() == LinuxNamespaceType::Cgroup)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::{LinuxBuilder, LinuxNamespaceBuilder};
+
+    #[test]
+    fn test_has_cgroup_namespace() -> Result<()> {
+        let namespace = |typ| LinuxNamespaceBuilder::default().typ(typ).build();
+
+        let linux = LinuxBuilder::default()
+            .namespaces(vec![
+                namespace(LinuxNamespaceType::Mount)?,
+                namespace(LinuxNamespaceType::Cgroup)?,
+            ])
+            .build()?;
+        assert!(has_cgroup_namespace(&linux));
+
+        let linux = LinuxBuilder::default()
+            .namespaces(vec![namespace(LinuxNamespaceType::Mount)?])
+            .build()?;
+        assert!(!has_cgroup_namespace(&linux));
+
+        let linux = LinuxBuilder::default().namespaces(vec![]).build()?;
+        assert!(!has_cgroup_namespace(&linux));
+
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 use anyhow::{anyhow, Result};
 use nix::{mount::MsFlags, sys::stat::SFlag, NixPath};
-use oci_spec::runtime::{LinuxDevice, LinuxDeviceBuilder, LinuxDeviceType, Mount};
+use oci_spec::runtime::{LinuxDevice, LinuxDeviceBuilder, LinuxDeviceType, LinuxIdMapping, Mount};
 use procfs::process::MountInfo;
 use std::{
+    os::unix::io::RawFd,
     path::{Path, PathBuf},
     str::FromStr,
 };
@@ -19,6 +20,32 @@ pub struct MountOptionConfig {
 
     /// RecAttr represents mount properties to be applied recrusively.
     pub rec_attr: Option<linux::MountAttr>,
+
+    /// Mount properties applied to the mount itself only, not to its
+    /// submounts.
+    pub attr: Option<linux::MountAttr>,
+
+    /// UID mappings used to create an idmapped mount.
+    pub uid_mappings: Option<Vec<LinuxIdMapping>>,
+
+    /// GID mappings used to create an idmapped mount.
+    pub gid_mappings: Option<Vec<LinuxIdMapping>>,
+
+    /// Mode of the directories created for the mount destination, 0755 if
+    /// unset. The umask of the process doesn't apply.
+    pub dir_mode: Option<u32>,
+
+    /// Copy the existing contents of the destination into the new tmpfs
+    /// (tmpcopyup).
+    pub copy_up: bool,
+
+    /// Apply the idmapping to the whole mount tree (ridmap) instead of only
+    /// the mount itself (idmap).
+    pub idmap_recursive: bool,
+
+    /// O_PATH fd of the rootfs the destination is opened beneath before
+    /// mounting, see MountOptions::root_fd.
+    pub root_fd: Option<RawFd>,
 }
 
 pub fn default_devices() -> Vec<LinuxDevice> {
@@ -87,11 +114,25 @@ pub fn parse_mount(m: &Mount) -> MountOptionConfig {
     let mut flags = MsFlags::empty();
     let mut data = Vec::new();
     let mut mount_attr: Option<linux::MountAttr> = None;
+    let mut top_attr: Option<linux::MountAttr> = None;
+    let mut copy_up = false;
+    let mut idmap_recursive = false;
+    let mut dir_mode = None;
 
     if let Some(options) = &m.options() {
         for s in options {
             if let Some((is_clear, flag)) = match s.as_str() {
-                "defaults" => Some((false, MsFlags::empty())),
+                // defaults is rw,suid,dev,exec,auto,nouser,async in mount(8)
+                "defaults" => Some((
+                    true,
+                    MsFlags::MS_RDONLY
+                        | MsFlags::MS_NOSUID
+                        | MsFlags::MS_NODEV
+                        | MsFlags::MS_NOEXEC
+                        | MsFlags::MS_SYNCHRONOUS,
+                )),
+                // only meaningful to mount(8) and fstab, not to the kernel
+                "auto" | "noauto" | "nouser" => Some((false, MsFlags::empty())),
                 "ro" => Some((false, MsFlags::MS_RDONLY)),
                 "rw" => Some((true, MsFlags::MS_RDONLY)),
                 "suid" => Some((true, MsFlags::MS_NOSUID)),
@@ -120,10 +161,12 @@ pub fn parse_mount(m: &Mount) -> MountOptionConfig {
                 "rshared" => Some((true, MsFlags::MS_SHARED | MsFlags::MS_REC)),
                 "slave" => Some((true, MsFlags::MS_SLAVE)),
                 "rslave" => Some((true, MsFlags::MS_SLAVE | MsFlags::MS_REC)),
-                "relatime" => Some((true, MsFlags::MS_RELATIME)),
+                "relatime" => Some((false, MsFlags::MS_RELATIME)),
                 "norelatime" => Some((true, MsFlags::MS_RELATIME)),
-                "strictatime" => Some((true, MsFlags::MS_STRICTATIME)),
+                "strictatime" => Some((false, MsFlags::MS_STRICTATIME)),
                 "nostrictatime" => Some((true, MsFlags::MS_STRICTATIME)),
+                "lazytime" => Some((false, MsFlags::MS_LAZYTIME)),
+                "nolazytime" => Some((true, MsFlags::MS_LAZYTIME)),
                 _ => None,
             } {
                 if is_clear {
@@ -134,6 +177,52 @@ pub fn parse_mount(m: &Mount) -> MountOptionConfig {
                 continue;
             }
 
+            if s == "tmpcopyup" {
+                copy_up = true;
+                continue;
+            }
+
+            // X-mount.* options are meant for the runtime, like they are for
+            // mount(8), and never passed to the kernel.
+            if let Some(option) = s.strip_prefix("X-mount.") {
+                match parse_mkdir_mode(option) {
+                    Some(Ok(mode)) => dir_mode = Some(mode),
+                    Some(Err(())) => log::warn!("ignoring invalid mount option {}", s),
+                    None => log::debug!("ignoring unsupported mount option {}", s),
+                }
+                continue;
+            }
+
+            if s == "idmap" || s == "ridmap" {
+                idmap_recursive = s == "ridmap";
+                continue;
+            }
+
+            // MsFlags of nix has no MS_NOSYMFOLLOW, so nosymfollow is applied
+            // with mount_setattr(2), which needs Linux 5.12. Like the mount(2)
+            // flag it only applies to the mount itself, submounts of a
+            // recursive bind mount keep following symlinks (rnosymfollow).
+            if s == "nosymfollow" {
+                top_attr
+                    .get_or_insert(linux::MountAttr {
+                        attr_set: 0,
+                        attr_clr: 0,
+                        propagation: 0,
+                        userns_fd: 0,
+                    })
+                    .attr_set |= linux::MOUNT_ATTR_NOSYMFOLLOW;
+                continue;
+            }
+            if s == "symfollow" {
+                if let Some(attr) = &mut top_attr {
+                    attr.attr_set &= !linux::MOUNT_ATTR_NOSYMFOLLOW;
+                    if attr.attr_set == 0 {
+                        top_attr = None;
+                    }
+                }
+                continue;
+            }
+
             if let Ok(mount_attr_option) = linux::MountAttrOption::from_str(s.as_str()) {
                 let (is_clear, flag) = match mount_attr_option {
                     MountAttrOption::MountArrtRdonly(is_clear, flag) => (is_clear, flag),
@@ -176,16 +265,148 @@ pub fn parse_mount(m: &Mount) -> MountOptionConfig {
             data.push(s.as_str());
         }
     }
+
+    if m.typ().as_deref() == Some("devpts") {
+        data = validate_devpts_data(data);
+    }
+
     MountOptionConfig {
         flags,
         data: data.join(","),
         rec_attr: mount_attr,
+        attr: top_attr,
+        // The mount entry of oci-spec doesn't carry uidMappings/gidMappings
+        // yet, so idmapped mounts have to be requested by filling these in
+        // explicitly.
+        uid_mappings: None,
+        gid_mappings: None,
+        dir_mode,
+        copy_up,
+        idmap_recursive,
+        root_fd: None,
+    }
+}
+
+/// Parse the mode of X-mount.mkdir[=mode], given without the X-mount. prefix.
+/// The mode is octal and defaults to 0755 like for mount(8). None is returned
+/// for other options.
+fn parse_mkdir_mode(option: &str) -> Option<std::result::Result<u32, ()>> {
+    match option.split_once('=') {
+        None if option == "mkdir" => Some(Ok(0o755)),
+        Some(("mkdir", mode)) => Some(
+            u32::from_str_radix(mode, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o7777)
+                .ok_or(()),
+        ),
+        _ => None,
+    }
+}
+
+/// Highest number of ptys a devpts instance can be limited to with max=.
+const DEVPTS_MAX_PTYS: u32 = 1 << 20;
+
+/// Check the mount data of a devpts mount. An invalid max= limit is dropped,
+/// as the kernel would reject the whole mount, and ptmxmode= without
+/// newinstance is warned about, as it then only applies to kernels which
+/// create a new instance for every mount anyway (4.7 and later).
+fn validate_devpts_data(data: Vec<&str>) -> Vec<&str> {
+    if data.iter().any(|d| d.starts_with("ptmxmode=")) && !data.contains(&"newinstance") {
+        log::warn!("devpts mount sets ptmxmode without newinstance");
+    }
+
+    data.into_iter()
+        .filter(|d| match d.strip_prefix("max=") {
+            Some(max) => match max.parse::<u32>() {
+                Ok(max) if max <= DEVPTS_MAX_PTYS => true,
+                _ => {
+                    log::warn!("ignoring invalid devpts option {}", d);
+                    false
+                }
+            },
+            None => true,
+        })
+        .collect()
+}
+
+/// Propagation type of a mount, see mount_namespaces(7).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MountPropagation {
+    Shared,
+    Private,
+    Slave,
+    Unbindable,
+}
+
+impl MountPropagation {
+    pub const ALL: [MountPropagation; 4] = [
+        MountPropagation::Shared,
+        MountPropagation::Private,
+        MountPropagation::Slave,
+        MountPropagation::Unbindable,
+    ];
+
+    /// Parse a propagation mount option like slave or rslave into the
+    /// propagation type and whether it applies recursively.
+    pub fn from_option(option: &str) -> Option<(Self, bool)> {
+        let (name, recursive) = match option.strip_prefix('r') {
+            Some(name) => (name, true),
+            None => (option, false),
+        };
+        let propagation = match name {
+            "shared" => MountPropagation::Shared,
+            "private" => MountPropagation::Private,
+            "slave" => MountPropagation::Slave,
+            "unbindable" => MountPropagation::Unbindable,
+            _ => return None,
+        };
+        Some((propagation, recursive))
+    }
+
+    /// The mount flags setting this propagation type.
+    pub fn flags(self, recursive: bool) -> MsFlags {
+        let flag = match self {
+            MountPropagation::Shared => MsFlags::MS_SHARED,
+            MountPropagation::Private => MsFlags::MS_PRIVATE,
+            MountPropagation::Slave => MsFlags::MS_SLAVE,
+            MountPropagation::Unbindable => MsFlags::MS_UNBINDABLE,
+        };
+        if recursive {
+            flag | MsFlags::MS_REC
+        } else {
+            flag
+        }
+    }
+
+    /// All flags which select a propagation type.
+    pub fn mask() -> MsFlags {
+        Self::ALL
+            .iter()
+            .fold(MsFlags::empty(), |mask, propagation| {
+                mask | propagation.flags(false)
+            })
     }
 }
 
+/// Collect the propagation flags requested by the options of a mount.
+/// parse_mount leaves these out of the mount flags, because mount(2) ignores
+/// every other flag once a propagation flag is given.
+pub fn parse_propagation(m: &Mount) -> MsFlags {
+    m.options()
+        .iter()
+        .flatten()
+        .filter_map(|option| MountPropagation::from_option(option))
+        .fold(MsFlags::empty(), |flags, (propagation, recursive)| {
+            flags | propagation.flags(recursive)
+        })
+}
+
 /// Find parent mount of rootfs in given mount infos
 pub fn find_parent_mount(rootfs: &Path, mount_infos: Vec<MountInfo>) -> Result<MountInfo> {
-    // find the longest mount point
+    // find the longest mount point. When several mounts share the same mount
+    // point (e.g. a bind over a bind), the one listed last in mountinfo is the
+    // most recently mounted and therefore the visible one. max_by returns the
+    // last of several equal elements, which picks exactly that mount.
     let parent_mount_info = mount_infos
         .into_iter()
         .filter(|mi| rootfs.starts_with(&mi.mount_point))
@@ -237,6 +458,89 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_find_parent_mount_with_overlapping_mounts() -> anyhow::Result<()> {
+        let mount_info = |mnt_id, mount_point: &str| MountInfo {
+            mnt_id,
+            pid: 1,
+            majmin: "".to_string(),
+            root: "/".to_string(),
+            mount_point: PathBuf::from(mount_point),
+            mount_options: Default::default(),
+            opt_fields: vec![],
+            fs_type: "ext4".to_string(),
+            mount_source: Some("/dev/sda1".to_string()),
+            super_options: Default::default(),
+        };
+        let mount_infos = vec![
+            mount_info(11, "/"),
+            mount_info(12, "/path"),
+            mount_info(13, "/path/to"),
+            mount_info(14, "/path/to"),
+            mount_info(15, "/other"),
+        ];
+
+        let res = find_parent_mount(Path::new("/path/to/rootfs"), mount_infos)
+            .context("Failed to get parent mount")?;
+        assert_eq!(res.mnt_id, 14);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_propagation() {
+        let cases = [
+            ("shared", MountPropagation::Shared, MsFlags::MS_SHARED),
+            ("private", MountPropagation::Private, MsFlags::MS_PRIVATE),
+            ("slave", MountPropagation::Slave, MsFlags::MS_SLAVE),
+            (
+                "unbindable",
+                MountPropagation::Unbindable,
+                MsFlags::MS_UNBINDABLE,
+            ),
+        ];
+        for (option, propagation, flag) in cases {
+            assert_eq!(
+                MountPropagation::from_option(option),
+                Some((propagation, false))
+            );
+            assert_eq!(
+                MountPropagation::from_option(&format!("r{option}")),
+                Some((propagation, true))
+            );
+            assert_eq!(propagation.flags(false), flag);
+            assert_eq!(propagation.flags(true), flag | MsFlags::MS_REC);
+        }
+
+        assert_eq!(MountPropagation::from_option("rw"), None);
+        assert_eq!(MountPropagation::from_option("rbind"), None);
+        assert_eq!(
+            MountPropagation::mask(),
+            MsFlags::MS_SHARED | MsFlags::MS_PRIVATE | MsFlags::MS_SLAVE | MsFlags::MS_UNBINDABLE
+        );
+    }
+
+    #[test]
+    fn test_parse_propagation() {
+        let mount = MountBuilder::default()
+            .options(vec![
+                "rslave".to_string(),
+                "nosuid".to_string(),
+                "unbindable".to_string(),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(
+            parse_propagation(&mount),
+            MsFlags::MS_SLAVE | MsFlags::MS_REC | MsFlags::MS_UNBINDABLE
+        );
+
+        let mount = MountBuilder::default()
+            .options(vec!["ro".to_string()])
+            .build()
+            .unwrap();
+        assert_eq!(parse_propagation(&mount), MsFlags::empty());
+    }
+
     #[test]
     fn test_find_parent_mount_with_empty_mount_infos() {
         let mount_infos = vec![];
@@ -271,6 +575,13 @@ mod tests {
                 flags: MsFlags::empty(),
                 data: "".to_string(),
                 rec_attr: None,
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config
         );
@@ -291,9 +602,16 @@ mod tests {
         );
         assert_eq!(
             MountOptionConfig {
-                flags: MsFlags::MS_NOSUID,
+                flags: MsFlags::MS_NOSUID | MsFlags::MS_STRICTATIME,
                 data: "mode=755,size=65536k".to_string(),
                 rec_attr: None,
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config
         );
@@ -318,7 +636,14 @@ mod tests {
             MountOptionConfig {
                 flags: MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
                 data: "newinstance,ptmxmode=0666,mode=0620,gid=5".to_string(),
-                rec_attr: None
+                rec_attr: None,
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config
         );
@@ -342,7 +667,14 @@ mod tests {
             MountOptionConfig {
                 flags: MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC | MsFlags::MS_NODEV,
                 data: "mode=1777,size=65536k".to_string(),
-                rec_attr: None
+                rec_attr: None,
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config
         );
@@ -364,7 +696,14 @@ mod tests {
             MountOptionConfig {
                 flags: MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC | MsFlags::MS_NODEV,
                 data: "".to_string(),
-                rec_attr: None
+                rec_attr: None,
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config
         );
@@ -391,6 +730,13 @@ mod tests {
                     | MsFlags::MS_RDONLY,
                 data: "".to_string(),
                 rec_attr: None,
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config
         );
@@ -415,9 +761,17 @@ mod tests {
                 flags: MsFlags::MS_NOSUID
                     | MsFlags::MS_NOEXEC
                     | MsFlags::MS_NODEV
+                    | MsFlags::MS_RELATIME
                     | MsFlags::MS_RDONLY,
                 data: "".to_string(),
-                rec_attr: None
+                rec_attr: None,
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config,
         );
@@ -476,6 +830,13 @@ mod tests {
                     | MsFlags::MS_UNBINDABLE,
                 data: "".to_string(),
                 rec_attr: None,
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config
         );
@@ -510,9 +871,175 @@ mod tests {
             MountOptionConfig {
                 flags: MsFlags::empty(),
                 data: "".to_string(),
-                rec_attr: Some(MountAttr::all())
+                rec_attr: Some(MountAttr::all()),
+                attr: None,
+                uid_mappings: None,
+                gid_mappings: None,
+                dir_mode: None,
+                copy_up: false,
+                idmap_recursive: false,
+                root_fd: None,
             },
             mount_option_config
         );
     }
+
+    #[test]
+    fn test_parse_mount_devpts() {
+        let devpts = |options: &[&str]| {
+            parse_mount(
+                &MountBuilder::default()
+                    .destination(PathBuf::from("/dev/pts"))
+                    .typ("devpts")
+                    .source(PathBuf::from("devpts"))
+                    .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+                    .build()
+                    .unwrap(),
+            )
+        };
+
+        let config = devpts(&["nosuid", "newinstance", "ptmxmode=0666", "max=1024"]);
+        assert_eq!(config.flags, MsFlags::MS_NOSUID);
+        assert_eq!(config.data, "newinstance,ptmxmode=0666,max=1024");
+
+        // ptmxmode without newinstance is only warned about
+        let config = devpts(&["ptmxmode=0666", "mode=0620"]);
+        assert_eq!(config.data, "ptmxmode=0666,mode=0620");
+
+        for max in ["max=-1", "max=many", "max=1048577"] {
+            let config = devpts(&["newinstance", max]);
+            assert_eq!(config.data, "newinstance", "option {max}");
+        }
+
+        // max= is only interpreted for devpts
+        let config = parse_mount(
+            &MountBuilder::default()
+                .typ("tmpfs")
+                .options(vec!["max=many".to_string()])
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(config.data, "max=many");
+    }
+
+    #[test]
+    fn test_parse_mount_defaults_and_positive_options() {
+        let parse_options = |options: &[&str]| {
+            parse_mount(
+                &MountBuilder::default()
+                    .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+                    .build()
+                    .unwrap(),
+            )
+        };
+
+        let config = parse_options(&["ro", "nosuid", "nodev", "noexec", "sync", "defaults"]);
+        assert_eq!(config.flags, MsFlags::empty());
+        assert_eq!(config.data, "");
+
+        let config = parse_options(&["defaults", "ro", "auto", "nouser"]);
+        assert_eq!(config.flags, MsFlags::MS_RDONLY);
+        assert_eq!(config.data, "");
+
+        let config = parse_options(&["nosuid", "nodev", "dev"]);
+        assert_eq!(config.flags, MsFlags::MS_NOSUID);
+
+        let config = parse_options(&["noexec", "nosuid", "exec", "suid"]);
+        assert_eq!(config.flags, MsFlags::empty());
+    }
+
+    #[test]
+    fn test_parse_mount_x_mount_mkdir() {
+        let parse_options = |options: &[&str]| {
+            parse_mount(
+                &MountBuilder::default()
+                    .options(options.iter().map(|o| o.to_string()).collect::<Vec<_>>())
+                    .build()
+                    .unwrap(),
+            )
+        };
+
+        let config = parse_options(&["rbind", "X-mount.mkdir=0700"]);
+        assert_eq!(config.dir_mode, Some(0o700));
+        assert_eq!(config.flags, MsFlags::MS_BIND | MsFlags::MS_REC);
+        assert_eq!(config.data, "");
+
+        let config = parse_options(&["X-mount.mkdir"]);
+        assert_eq!(config.dir_mode, Some(0o755));
+
+        // invalid modes and other X-mount options are dropped
+        for option in [
+            "X-mount.mkdir=0800",
+            "X-mount.mkdir=17777",
+            "X-mount.owner=1000",
+        ] {
+            let config = parse_options(&[option]);
+            assert_eq!(config.dir_mode, None, "{option}");
+            assert_eq!(config.data, "", "{option}");
+        }
+    }
+
+    #[test]
+    fn test_parse_mount_atime_and_symfollow_options() {
+        let cases = [
+            ("lazytime", MsFlags::MS_LAZYTIME),
+            ("relatime", MsFlags::MS_RELATIME),
+            ("strictatime", MsFlags::MS_STRICTATIME),
+            ("symfollow", MsFlags::empty()),
+        ];
+        for (option, flags) in cases {
+            let mount_option_config = parse_mount(
+                &MountBuilder::default()
+                    .options(vec![option.to_string()])
+                    .build()
+                    .unwrap(),
+            );
+            assert_eq!(mount_option_config.flags, flags, "option {option}");
+            assert_eq!(mount_option_config.data, "", "option {option}");
+            assert_eq!(mount_option_config.rec_attr, None, "option {option}");
+        }
+
+        let mount_option_config = parse_mount(
+            &MountBuilder::default()
+                .options(vec![
+                    "lazytime".to_string(),
+                    "nolazytime".to_string(),
+                    "strictatime".to_string(),
+                    "nostrictatime".to_string(),
+                ])
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(mount_option_config.flags, MsFlags::empty());
+        assert_eq!(mount_option_config.data, "");
+
+        let mount_option_config = parse_mount(
+            &MountBuilder::default()
+                .options(vec!["nosymfollow".to_string()])
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(mount_option_config.data, "");
+        assert_eq!(mount_option_config.flags, MsFlags::empty());
+        assert_eq!(mount_option_config.rec_attr, None);
+        assert_eq!(
+            mount_option_config.attr,
+            Some(MountAttr {
+                attr_set: linux::MOUNT_ATTR_NOSYMFOLLOW,
+                attr_clr: 0,
+                propagation: 0,
+                userns_fd: 0,
+            })
+        );
+
+        // the negating option overrides an earlier nosymfollow
+        let mount_option_config = parse_mount(
+            &MountBuilder::default()
+                .options(vec!["nosymfollow".to_string(), "symfollow".to_string()])
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(mount_option_config.data, "");
+        assert_eq!(mount_option_config.attr, None);
+    }
 }
@@ -0,0 +1,11 @@
+use anyhow::Result;
+use oci_spec::runtime::Spec;
+use std::path::Path;
+
+/// Checks the assembled root filesystem of an init container. The validator
+/// runs in the container namespaces once all mounts are set up, right before
+/// the process pivots into the rootfs. Returning an error aborts the creation
+/// of the container.
+pub trait RootfsValidator {
+    fn validate(&self, rootfs: &Path, spec: &Spec) -> Result<()>;
+}
@@ -265,6 +265,15 @@ fn validate_mounts_for_rootless(
     Ok(())
 }
 
+/// Maps id inside of the user namespace to the id on the host through
+/// mappings, None if it isn't mapped.
+pub(crate) fn host_id(id: u32, mappings: &[LinuxIdMapping]) -> Option<u32> {
+    mappings
+        .iter()
+        .find(|m| id >= m.container_id() && id - m.container_id() < m.size())
+        .map(|m| m.host_id() + (id - m.container_id()))
+}
+
 fn is_id_mapped(id: u32, mappings: &[LinuxIdMapping]) -> bool {
     mappings
         .iter()
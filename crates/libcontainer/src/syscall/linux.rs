@@ -8,10 +8,11 @@ use nix::{
     mount::{mount, umount2, MntFlags, MsFlags},
     sched::{unshare, CloneFlags},
     sys::stat::{mknod, Mode, SFlag},
+    sys::wait::waitpid,
     unistd,
-    unistd::{chown, fchdir, pivot_root, setgroups, sethostname, Gid, Uid},
+    unistd::{chown, pipe2, pivot_root, setgroups, sethostname, Gid, Pid, Uid},
 };
-use oci_spec::runtime::LinuxRlimit;
+use oci_spec::runtime::{LinuxIdMapping, LinuxRlimit};
 use std::ffi::{CStr, CString, OsStr};
 use std::fs;
 use std::os::unix::ffi::OsStrExt;
@@ -30,7 +31,7 @@ use crate::{capabilities, utils};
 // see https://man7.org/linux/man-pages/man2/mount_setattr.2.html.
 pub const AT_RECURSIVE: u32 = 0x00008000; // Change the mount properties of the entire mount tree.
 pub const MOUNT_ATTR__ATIME: u64 = 0x00000070; // Setting on how atime should be updated.
-const MOUNT_ATTR_RDONLY: u64 = 0x00000001;
+pub const MOUNT_ATTR_RDONLY: u64 = 0x00000001;
 const MOUNT_ATTR_NOSUID: u64 = 0x00000002;
 const MOUNT_ATTR_NODEV: u64 = 0x00000004;
 const MOUNT_ATTR_NOEXEC: u64 = 0x00000008;
@@ -38,7 +39,8 @@ const MOUNT_ATTR_RELATIME: u64 = 0x00000000;
 const MOUNT_ATTR_NOATIME: u64 = 0x00000010;
 const MOUNT_ATTR_STRICTATIME: u64 = 0x00000020;
 const MOUNT_ATTR_NODIRATIME: u64 = 0x00000080;
-const MOUNT_ATTR_NOSYMFOLLOW: u64 = 0x00200000;
+pub const MOUNT_ATTR_NOSYMFOLLOW: u64 = 0x00200000;
+pub const MOUNT_ATTR_IDMAP: u64 = 0x00100000; // Idmap mount to userns_fd.
 
 /// Constants used by mount_setattr(2).
 pub enum MountAttrOption {
@@ -173,6 +175,46 @@ impl MountAttr {
     }
 }
 
+// Flags used in the resolve field of openat2(2).
+// see https://man7.org/linux/man-pages/man2/openat2.2.html.
+pub const RESOLVE_NO_MAGICLINKS: u64 = 0x02; // Don't follow magic links like /proc/self/fd/*.
+pub const RESOLVE_BENEATH: u64 = 0x08; // Fail if the path resolves outside of dirfd.
+
+#[repr(C)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// A structure used as the third argument of openat2(2).
+pub struct OpenHow {
+    /// Flags of open(2), e.g. O_PATH.
+    pub flags: u64,
+
+    /// Mode of a created file.
+    pub mode: u64,
+
+    /// Restrictions on the resolution of the path.
+    pub resolve: u64,
+}
+
+/// Open path relative to dirfd with openat2(2), which nix and libc don't
+/// wrap yet. Requires Linux 5.6 or later.
+pub fn openat2(
+    dirfd: RawFd,
+    path: &Path,
+    how: &OpenHow,
+) -> std::result::Result<RawFd, syscalls::Errno> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|_| syscalls::Errno::EINVAL)?;
+    let fd = unsafe {
+        syscall!(
+            Sysno::openat2,
+            dirfd,
+            path.as_ptr(),
+            how as *const OpenHow,
+            mem::size_of::<OpenHow>()
+        )
+    }?;
+
+    Ok(fd as RawFd)
+}
+
 /// Empty structure to implement Command trait for
 #[derive(Clone)]
 pub struct LinuxSyscall;
@@ -209,6 +251,30 @@ impl LinuxSyscall {
         Ok(())
     }
 
+    fn write_idmap_userns(
+        pid: Pid,
+        uid_mappings: &[LinuxIdMapping],
+        gid_mappings: &[LinuxIdMapping],
+    ) -> Result<RawFd> {
+        let proc_path = Path::new("/proc").join(pid.to_string());
+        for (map_file, mappings) in [("uid_map", uid_mappings), ("gid_map", gid_mappings)] {
+            let path = proc_path.join(map_file);
+            let content: String = mappings
+                .iter()
+                .map(|m| format!("{} {} {}\n", m.container_id(), m.host_id(), m.size()))
+                .collect();
+            fs::write(&path, content)
+                .map_err(|err| SyscallError::WriteIdMapping { source: err, path })?;
+        }
+
+        open(
+            &proc_path.join("ns/user"),
+            OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|errno| SyscallError::CreateIdmapUserns { source: errno })
+    }
+
     // Get a list of open fds for the calling process.
     fn get_open_fds() -> Result<Vec<i32>> {
         const PROCFS_FD_PATH: &str = "/proc/self/fd";
@@ -245,57 +311,13 @@ impl Syscall for LinuxSyscall {
         self
     }
 
-    /// Function to set given path as root path inside process
-    fn pivot_rootfs(&self, path: &Path) -> Result<()> {
-        // open the path as directory and read only
-        let newroot =
-            open(path, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty()).map_err(|errno| {
-                log::error!("failed to open {:?}", path);
-                SyscallError::PivotRoot { source: errno }
-            })?;
-
-        // make the given path as the root directory for the container
-        // see https://man7.org/linux/man-pages/man2/pivot_root.2.html, specially the notes
-        // pivot root usually changes the root directory to first argument, and then mounts the original root
-        // directory at second argument. Giving same path for both stacks mapping of the original root directory
-        // above the new directory at the same path, then the call to umount unmounts the original root directory from
-        // this path. This is done, as otherwise, we will need to create a separate temporary directory under the new root path
-        // so we can move the original root there, and then unmount that. This way saves the creation of the temporary
-        // directory to put original root directory.
-        pivot_root(path, path).map_err(|errno| {
-            log::error!("failed to pivot root to {:?}", path);
-            SyscallError::PivotRoot { source: errno }
-        })?;
-
-        // Make the original root directory rslave to avoid propagating unmount event to the host mount namespace.
-        // We should use MS_SLAVE not MS_PRIVATE according to https://github.com/opencontainers/runc/pull/1500.
-        mount(
-            None::<&str>,
-            "/",
-            None::<&str>,
-            MsFlags::MS_SLAVE | MsFlags::MS_REC,
-            None::<&str>,
-        )
-        .map_err(|errno| {
-            log::error!("failed to make original root directory rslave");
-            SyscallError::PivotRoot { source: errno }
-        })?;
-
-        // Unmount the original root directory which was stacked on top of new root directory
-        // MNT_DETACH makes the mount point unavailable to new accesses, but waits till the original mount point
-        // to be free of activity to actually unmount
-        // see https://man7.org/linux/man-pages/man2/umount2.2.html for more information
-        umount2("/", MntFlags::MNT_DETACH).map_err(|errno| {
-            log::error!("failed to unmount old root directory");
+    /// Change the root mount of the mount namespace to new_root and move the
+    /// old root mount to put_old, see pivot_root(2)
+    fn pivot_root(&self, new_root: &Path, put_old: &Path) -> Result<()> {
+        pivot_root(new_root, put_old).map_err(|errno| {
+            log::error!("failed to pivot root to {:?}", new_root);
             SyscallError::PivotRoot { source: errno }
-        })?;
-        // Change directory to the new root
-        fchdir(newroot).map_err(|errno| {
-            log::error!("failed to change directory to new root");
-            SyscallError::PivotRoot { source: errno }
-        })?;
-
-        Ok(())
+        })
     }
 
     /// Set namespace for process
@@ -464,6 +486,10 @@ impl Syscall for LinuxSyscall {
             .map_err(|errno| SyscallError::Mount { source: errno })
     }
 
+    fn umount2(&self, target: &Path, flags: MntFlags) -> Result<()> {
+        umount2(target, flags).map_err(|errno| SyscallError::Umount { source: errno })
+    }
+
     fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
         symlink(original, link).map_err(|err| {
             log::error!("failed to create symlink from {original:?} to {link:?}: {err}");
@@ -548,6 +574,74 @@ impl Syscall for LinuxSyscall {
             Err(e) => Err(SyscallError::MountSetattr { source: e }),
         }
     }
+
+    fn open_userns_fd(
+        &self,
+        uid_mappings: &[LinuxIdMapping],
+        gid_mappings: &[LinuxIdMapping],
+    ) -> Result<RawFd> {
+        // The kernel needs a user namespace carrying the mappings to idmap a
+        // mount. Spawn a short lived child in a new user namespace, write the
+        // mappings for it and hold on to its namespace. The child blocks on
+        // the pipe until we are done and exits once the write end is closed.
+        let (read_end, write_end) = pipe2(OFlag::O_CLOEXEC)
+            .map_err(|errno| SyscallError::CreateIdmapUserns { source: errno })?;
+
+        let mut clone = clone3::Clone3::default();
+        clone.flag_newuser().exit_signal(libc::SIGCHLD as u64);
+        let pid = match unsafe { clone.call() } {
+            Ok(0) => {
+                let _ = unistd::close(write_end);
+                let mut buf = [0u8; 1];
+                let _ = unistd::read(read_end, &mut buf);
+                // _exit so the child doesn't run the atexit handlers or flush
+                // the stdio buffers it copied from the parent
+                unsafe { libc::_exit(0) };
+            }
+            Ok(pid) => Pid::from_raw(pid),
+            Err(err) => {
+                let _ = unistd::close(read_end);
+                let _ = unistd::close(write_end);
+                return Err(SyscallError::CreateIdmapUserns {
+                    source: nix::errno::from_i32(err.0),
+                });
+            }
+        };
+        let _ = unistd::close(read_end);
+
+        let result = Self::write_idmap_userns(pid, uid_mappings, gid_mappings);
+
+        let _ = unistd::close(write_end);
+        let _ = waitpid(pid, None);
+
+        result
+    }
+
+    fn sync(&self) -> Result<()> {
+        unistd::sync();
+        Ok(())
+    }
+
+    fn sched_setscheduler(&self, pid: Pid, policy: i32, priority: i32) -> Result<()> {
+        let param = libc::sched_param {
+            sched_priority: priority,
+        };
+        match unsafe { libc::sched_setscheduler(pid.as_raw(), policy, &param) } {
+            0 => Ok(()),
+            _ => Err(SyscallError::SchedSetScheduler {
+                source: Errno::last(),
+            }),
+        }
+    }
+
+    fn set_priority(&self, pid: Pid, nice: i32) -> Result<()> {
+        match unsafe { libc::setpriority(libc::PRIO_PROCESS, pid.as_raw() as libc::id_t, nice) } {
+            0 => Ok(()),
+            _ => Err(SyscallError::SetPriority {
+                source: Errno::last(),
+            }),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -64,6 +64,8 @@ pub enum SyscallError {
     Chroot { source: nix::errno::Errno },
     #[error("mount failed")]
     Mount { source: nix::errno::Errno },
+    #[error("umount failed")]
+    Umount { source: nix::errno::Errno },
     #[error("symlink failed")]
     Symlink { source: std::io::Error },
     #[error("mknod failed")]
@@ -82,6 +84,17 @@ pub enum SyscallError {
     InvalidFilename(std::path::PathBuf),
     #[error("mount_setattr failed")]
     MountSetattr { source: syscalls::Errno },
+    #[error("failed to create user namespace for idmapped mount")]
+    CreateIdmapUserns { source: nix::errno::Errno },
+    #[error("sched_setscheduler failed")]
+    SchedSetScheduler { source: nix::errno::Errno },
+    #[error("setpriority failed")]
+    SetPriority { source: nix::errno::Errno },
+    #[error("failed to write id mapping to {path:?}")]
+    WriteIdMapping {
+        source: std::io::Error,
+        path: std::path::PathBuf,
+    },
 }
 
 type Result<T> = std::result::Result<T, SyscallError>;
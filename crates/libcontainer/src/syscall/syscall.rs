@@ -5,14 +5,14 @@ use bitflags::bitflags;
 use caps::{CapSet, CapsHashSet};
 use libc;
 use nix::{
-    mount::MsFlags,
+    mount::{MntFlags, MsFlags},
     sched::CloneFlags,
     sys::stat::{Mode, SFlag},
-    unistd::{Gid, Uid},
+    unistd::{Gid, Pid, Uid},
 };
-use std::{any::Any, ffi::OsStr, path::Path, sync::Arc};
+use std::{any::Any, ffi::OsStr, os::unix::io::RawFd, path::Path, sync::Arc};
 
-use oci_spec::runtime::LinuxRlimit;
+use oci_spec::runtime::{LinuxIdMapping, LinuxRlimit};
 
 use crate::syscall::{
     linux::{LinuxSyscall, MountAttr},
@@ -24,7 +24,7 @@ use crate::syscall::{
 /// container management
 pub trait Syscall {
     fn as_any(&self) -> &dyn Any;
-    fn pivot_rootfs(&self, path: &Path) -> Result<()>;
+    fn pivot_root(&self, new_root: &Path, put_old: &Path) -> Result<()>;
     fn chroot(&self, path: &Path) -> Result<()>;
     fn set_ns(&self, rawfd: i32, nstype: CloneFlags) -> Result<()>;
     fn set_id(&self, uid: Uid, gid: Gid) -> Result<()>;
@@ -42,6 +42,7 @@ pub trait Syscall {
         flags: MsFlags,
         data: Option<&str>,
     ) -> Result<()>;
+    fn umount2(&self, target: &Path, flags: MntFlags) -> Result<()>;
     fn symlink(&self, original: &Path, link: &Path) -> Result<()>;
     fn mknod(&self, path: &Path, kind: SFlag, perm: Mode, dev: u64) -> Result<()>;
     fn chown(&self, path: &Path, owner: Option<Uid>, group: Option<Gid>) -> Result<()>;
@@ -55,6 +56,14 @@ pub trait Syscall {
         mount_attr: &MountAttr,
         size: libc::size_t,
     ) -> Result<()>;
+    fn open_userns_fd(
+        &self,
+        uid_mappings: &[LinuxIdMapping],
+        gid_mappings: &[LinuxIdMapping],
+    ) -> Result<RawFd>;
+    fn sync(&self) -> Result<()>;
+    fn sched_setscheduler(&self, pid: Pid, policy: i32, priority: i32) -> Result<()>;
+    fn set_priority(&self, pid: Pid, nice: i32) -> Result<()>;
 }
 
 pub fn create_syscall() -> Box<dyn Syscall> {
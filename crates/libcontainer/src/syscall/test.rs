@@ -3,19 +3,22 @@ use std::{
     cell::{Ref, RefCell, RefMut},
     collections::HashMap,
     ffi::{OsStr, OsString},
+    os::unix::io::RawFd,
     path::{Path, PathBuf},
     sync::Arc,
+    thread,
+    time::Duration,
 };
 
 use caps::{CapSet, CapsHashSet};
 use nix::{
-    mount::MsFlags,
+    mount::{MntFlags, MsFlags},
     sched::CloneFlags,
     sys::stat::{Mode, SFlag},
-    unistd::{Gid, Uid},
+    unistd::{Gid, Pid, Uid},
 };
 
-use oci_spec::runtime::LinuxRlimit;
+use oci_spec::runtime::{LinuxIdMapping, LinuxRlimit};
 
 use super::{linux, Result, Syscall};
 
@@ -28,6 +31,12 @@ pub struct MountArgs {
     pub data: Option<String>,
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UmountArgs {
+    pub target: PathBuf,
+    pub flags: MntFlags,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct MknodArgs {
     pub path: PathBuf,
@@ -43,11 +52,28 @@ pub struct ChownArgs {
     pub group: Option<Gid>,
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MountSetattrArgs {
+    pub dirfd: i32,
+    pub pathname: PathBuf,
+    pub flags: u32,
+    pub mount_attr: linux::MountAttr,
+    pub size: libc::size_t,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UsernsFdArgs {
+    pub uid_mappings: Vec<LinuxIdMapping>,
+    pub gid_mappings: Vec<LinuxIdMapping>,
+}
+
 #[derive(Default)]
 struct Mock {
     values: Vec<Box<dyn Any>>,
     ret_err: Option<fn() -> Result<()>>,
     ret_err_times: usize,
+    ret_err_after: usize,
+    delay: Option<Duration>,
 }
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone)]
@@ -55,6 +81,7 @@ pub enum ArgName {
     Namespace,
     Unshare,
     Mount,
+    Umount,
     Symlink,
     Mknod,
     Chown,
@@ -62,6 +89,13 @@ pub enum ArgName {
     Domainname,
     Groups,
     Capability,
+    MountSetattr,
+    UsernsFd,
+    Chroot,
+    Sync,
+    PivotRoot,
+    SchedSetScheduler,
+    SetPriority,
 }
 
 impl ArgName {
@@ -70,6 +104,7 @@ impl ArgName {
             ArgName::Namespace,
             ArgName::Unshare,
             ArgName::Mount,
+            ArgName::Umount,
             ArgName::Symlink,
             ArgName::Mknod,
             ArgName::Chown,
@@ -77,6 +112,13 @@ impl ArgName {
             ArgName::Domainname,
             ArgName::Groups,
             ArgName::Capability,
+            ArgName::MountSetattr,
+            ArgName::UsernsFd,
+            ArgName::Chroot,
+            ArgName::Sync,
+            ArgName::PivotRoot,
+            ArgName::SchedSetScheduler,
+            ArgName::SetPriority,
         ]
         .iter()
         .copied()
@@ -103,7 +145,13 @@ impl Default for MockCalls {
 
 impl MockCalls {
     fn act(&self, name: ArgName, value: Box<dyn Any>) -> Result<()> {
-        if self.args.get(&name).unwrap().borrow().ret_err_times > 0 {
+        if let Some(delay) = self.args.get(&name).unwrap().borrow().delay {
+            thread::sleep(delay);
+        }
+
+        if self.args.get(&name).unwrap().borrow().ret_err_after > 0 {
+            self.args.get(&name).unwrap().borrow_mut().ret_err_after -= 1;
+        } else if self.args.get(&name).unwrap().borrow().ret_err_times > 0 {
             self.args.get(&name).unwrap().borrow_mut().ret_err_times -= 1;
             if let Some(e) = &self.args.get(&name).unwrap().borrow().ret_err {
                 return e();
@@ -138,8 +186,11 @@ impl Syscall for TestHelperSyscall {
         self
     }
 
-    fn pivot_rootfs(&self, _path: &Path) -> Result<()> {
-        unimplemented!()
+    fn pivot_root(&self, new_root: &Path, put_old: &Path) -> Result<()> {
+        self.mocks.act(
+            ArgName::PivotRoot,
+            Box::new((new_root.to_path_buf(), put_old.to_path_buf())),
+        )
     }
 
     fn set_ns(&self, rawfd: i32, nstype: CloneFlags) -> Result<()> {
@@ -178,8 +229,9 @@ impl Syscall for TestHelperSyscall {
         Some(OsString::from("youki").into())
     }
 
-    fn chroot(&self, _: &Path) -> Result<()> {
-        todo!()
+    fn chroot(&self, path: &Path) -> Result<()> {
+        self.mocks
+            .act(ArgName::Chroot, Box::new(path.to_path_buf()))
     }
 
     fn mount(
@@ -202,6 +254,16 @@ impl Syscall for TestHelperSyscall {
         )
     }
 
+    fn umount2(&self, target: &Path, flags: MntFlags) -> Result<()> {
+        self.mocks.act(
+            ArgName::Umount,
+            Box::new(UmountArgs {
+                target: target.to_owned(),
+                flags,
+            }),
+        )
+    }
+
     fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
         self.mocks.act(
             ArgName::Symlink,
@@ -241,13 +303,54 @@ impl Syscall for TestHelperSyscall {
 
     fn mount_setattr(
         &self,
-        _: i32,
-        _: &Path,
-        _: u32,
-        _: &linux::MountAttr,
-        _: libc::size_t,
+        dirfd: i32,
+        pathname: &Path,
+        flags: u32,
+        mount_attr: &linux::MountAttr,
+        size: libc::size_t,
     ) -> Result<()> {
-        todo!()
+        self.mocks.act(
+            ArgName::MountSetattr,
+            Box::new(MountSetattrArgs {
+                dirfd,
+                pathname: pathname.to_path_buf(),
+                flags,
+                mount_attr: mount_attr.clone(),
+                size,
+            }),
+        )
+    }
+
+    fn open_userns_fd(
+        &self,
+        uid_mappings: &[LinuxIdMapping],
+        gid_mappings: &[LinuxIdMapping],
+    ) -> Result<RawFd> {
+        self.mocks.act(
+            ArgName::UsernsFd,
+            Box::new(UsernsFdArgs {
+                uid_mappings: uid_mappings.to_vec(),
+                gid_mappings: gid_mappings.to_vec(),
+            }),
+        )?;
+        // There is no real user namespace behind the mock, an invalid fd is
+        // enough for the callers to pass around and close.
+        Ok(-1)
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.mocks.act(ArgName::Sync, Box::new(()))
+    }
+
+    fn sched_setscheduler(&self, pid: Pid, policy: i32, priority: i32) -> Result<()> {
+        self.mocks.act(
+            ArgName::SchedSetScheduler,
+            Box::new((pid, policy, priority)),
+        )
+    }
+
+    fn set_priority(&self, pid: Pid, nice: i32) -> Result<()> {
+        self.mocks.act(ArgName::SetPriority, Box::new((pid, nice)))
     }
 }
 
@@ -261,6 +364,18 @@ impl TestHelperSyscall {
         self.mocks.fetch_mut(name).ret_err_times = times;
     }
 
+    /// Let the given number of calls succeed before the error set with
+    /// set_ret_err is returned.
+    pub fn set_ret_err_after(&self, name: ArgName, calls: usize) {
+        self.mocks.fetch_mut(name).ret_err_after = calls;
+    }
+
+    /// Make every call take at least the given time, to simulate slow
+    /// syscalls.
+    pub fn set_delay(&self, name: ArgName, delay: Duration) {
+        self.mocks.fetch_mut(name).delay = Some(delay);
+    }
+
     pub fn get_setns_args(&self) -> Vec<(i32, CloneFlags)> {
         self.mocks
             .fetch(ArgName::Namespace)
@@ -297,6 +412,15 @@ impl TestHelperSyscall {
             .collect::<Vec<MountArgs>>()
     }
 
+    pub fn get_umount_args(&self) -> Vec<UmountArgs> {
+        self.mocks
+            .fetch(ArgName::Umount)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<UmountArgs>().unwrap().clone())
+            .collect::<Vec<UmountArgs>>()
+    }
+
     pub fn get_symlink_args(&self) -> Vec<(PathBuf, PathBuf)> {
         self.mocks
             .fetch(ArgName::Symlink)
@@ -342,6 +466,65 @@ impl TestHelperSyscall {
             .collect::<Vec<String>>()
     }
 
+    pub fn get_chroot_args(&self) -> Vec<PathBuf> {
+        self.mocks
+            .fetch(ArgName::Chroot)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<PathBuf>().unwrap().clone())
+            .collect::<Vec<PathBuf>>()
+    }
+
+    pub fn get_pivot_root_args(&self) -> Vec<(PathBuf, PathBuf)> {
+        self.mocks
+            .fetch(ArgName::PivotRoot)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<(PathBuf, PathBuf)>().unwrap().clone())
+            .collect::<Vec<(PathBuf, PathBuf)>>()
+    }
+
+    pub fn get_sched_setscheduler_args(&self) -> Vec<(Pid, i32, i32)> {
+        self.mocks
+            .fetch(ArgName::SchedSetScheduler)
+            .values
+            .iter()
+            .map(|x| *x.downcast_ref::<(Pid, i32, i32)>().unwrap())
+            .collect::<Vec<(Pid, i32, i32)>>()
+    }
+
+    pub fn get_set_priority_args(&self) -> Vec<(Pid, i32)> {
+        self.mocks
+            .fetch(ArgName::SetPriority)
+            .values
+            .iter()
+            .map(|x| *x.downcast_ref::<(Pid, i32)>().unwrap())
+            .collect::<Vec<(Pid, i32)>>()
+    }
+
+    /// Number of times sync was called.
+    pub fn get_sync_count(&self) -> usize {
+        self.mocks.fetch(ArgName::Sync).values.len()
+    }
+
+    pub fn get_mount_setattr_args(&self) -> Vec<MountSetattrArgs> {
+        self.mocks
+            .fetch(ArgName::MountSetattr)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<MountSetattrArgs>().unwrap().clone())
+            .collect::<Vec<MountSetattrArgs>>()
+    }
+
+    pub fn get_userns_fd_args(&self) -> Vec<UsernsFdArgs> {
+        self.mocks
+            .fetch(ArgName::UsernsFd)
+            .values
+            .iter()
+            .map(|x| x.downcast_ref::<UsernsFdArgs>().unwrap().clone())
+            .collect::<Vec<UsernsFdArgs>>()
+    }
+
     pub fn get_groups_args(&self) -> Vec<Vec<Gid>> {
         self.mocks
             .fetch(ArgName::Groups)
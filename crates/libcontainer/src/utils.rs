@@ -209,6 +209,12 @@ pub enum WrappedIOError {
         source: std::io::Error,
         path: PathBuf,
     },
+    #[error("failed to rename {from:?} to {to:?}")]
+    Rename {
+        source: std::io::Error,
+        from: PathBuf,
+        to: PathBuf,
+    },
     #[error("failed to get metadata")]
     GetMetadata { source: std::io::Error },
     #[error("metada doesn't match the expected attributes")]
@@ -225,6 +231,31 @@ pub fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(
     })
 }
 
+/// Writes contents to a temporary file next to path and renames it into place,
+/// so that readers of path never see a partially written file.
+pub fn write_file_atomically<P: AsRef<Path>, C: AsRef<[u8]>>(
+    path: P,
+    contents: C,
+) -> Result<(), WrappedIOError> {
+    let path = path.as_ref();
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = write_file(&tmp_path, contents).and_then(|_| {
+        fs::rename(&tmp_path, path).map_err(|err| WrappedIOError::Rename {
+            source: err,
+            from: tmp_path.clone(),
+            to: path.to_path_buf(),
+        })
+    });
+    // a partially written or unrenamed temporary file must not stay behind
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
 pub fn create_dir_all<P: AsRef<Path>>(path: P) -> Result<(), WrappedIOError> {
     fs::create_dir_all(path.as_ref()).map_err(|err| WrappedIOError::CreateDirAll {
         source: err,
@@ -500,6 +531,20 @@ mod tests {
         assert!(dir.is_none());
     }
 
+    #[test]
+    fn test_write_file_atomically() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("container.pid");
+        fs::write(&path, "1234567")?;
+
+        write_file_atomically(&path, "42")?;
+
+        assert_eq!(fs::read_to_string(&path)?, "42");
+        let entries = fs::read_dir(tmp.path())?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(entries.len(), 1);
+        Ok(())
+    }
+
     #[test]
     fn test_get_cgroup_path() {
         let cid = "sample_container_id";
@@ -5,6 +5,10 @@ pub mod default;
 
 pub static EMPTY: Vec<String> = Vec::new();
 
+/// Annotation of the spec selecting the executor of the container by its
+/// name, e.g. to run a container with a wasm executor instead of natively.
+pub const HANDLER_ANNOTATION: &str = "run.oci.handler";
+
 pub trait Executor {
     /// Executes the workload
     fn exec(&self, spec: &Spec) -> Result<()>;
@@ -22,11 +26,26 @@ pub struct ExecutorManager {
 }
 
 impl ExecutorManager {
+    /// Executes the workload with the executor named by the
+    /// [`HANDLER_ANNOTATION`] of the spec, or else with the first executor
+    /// which can handle it.
     pub fn exec(&self, spec: &Spec) -> Result<()> {
         if self.executors.is_empty() {
             bail!("executors must not be empty");
         };
 
+        // An executor named by the annotation takes precedence. Other values
+        // are left to the executors to interpret in can_handle.
+        let handler = spec
+            .annotations()
+            .as_ref()
+            .and_then(|annotations| annotations.get(HANDLER_ANNOTATION));
+        if let Some(handler) = handler {
+            if let Some(executor) = self.executors.iter().find(|e| e.name() == handler.as_str()) {
+                return executor.exec(spec).context("execution failed");
+            }
+        }
+
         for executor in self.executors.iter() {
             if executor.can_handle(spec)? {
                 return executor.exec(spec).context("execution failed");
@@ -35,3 +54,71 @@ impl ExecutorManager {
         bail!("cannot find an executor that satisfies all requirements")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci_spec::runtime::SpecBuilder;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    struct MockExecutor {
+        name: &'static str,
+        executed: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl Executor for MockExecutor {
+        fn exec(&self, _: &Spec) -> Result<()> {
+            self.executed.borrow_mut().push(self.name);
+            Ok(())
+        }
+
+        fn can_handle(&self, _: &Spec) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn name(&self) -> &'static str {
+            self.name
+        }
+    }
+
+    fn exec(annotations: Option<HashMap<String, String>>) -> Result<Vec<&'static str>> {
+        let executed = Rc::new(RefCell::new(Vec::new()));
+        let manager = ExecutorManager {
+            executors: ["native", "wasm"]
+                .into_iter()
+                .map(|name| {
+                    Box::new(MockExecutor {
+                        name,
+                        executed: executed.clone(),
+                    }) as Box<dyn Executor>
+                })
+                .collect(),
+        };
+        let mut spec = SpecBuilder::default().build()?;
+        spec.set_annotations(annotations);
+
+        manager.exec(&spec)?;
+        let executed = executed.borrow().clone();
+        Ok(executed)
+    }
+
+    #[test]
+    fn test_exec_handler_annotation() -> Result<()> {
+        let annotations = |handler: &str| {
+            Some(HashMap::from([(
+                HANDLER_ANNOTATION.to_string(),
+                handler.to_string(),
+            )]))
+        };
+
+        assert_eq!(exec(annotations("wasm"))?, vec!["wasm"]);
+        assert_eq!(exec(annotations("native"))?, vec!["native"]);
+        // the first executor which can handle the spec runs otherwise
+        assert_eq!(exec(annotations("unknown"))?, vec!["native"]);
+        assert_eq!(exec(None)?, vec!["native"]);
+
+        Ok(())
+    }
+}